@@ -0,0 +1,592 @@
+use std::{collections::HashMap, sync::Mutex, pin::Pin, future::Future};
+
+use actix_web::{cookie, FromRequest, HttpRequest, dev::Payload, ResponseError, http::{StatusCode, Method, header::AUTHORIZATION}, HttpResponseBuilder, cookie::{Cookie, SameSite}, web::Data};
+use chrono::{NaiveDateTime, Local, Duration};
+use sha2::{Sha256, Digest};
+use rand::distributions::{Alphanumeric, DistString};
+
+use crate::{db::{DB, store::{load_user_auth, store_user_auth, load_user_totp, store_user_totp, delete_user_totp, load_api_tokens, store_api_token, rename_user_auth, load_oidc_config, load_oidc_identities, store_oidc_identities}}, data::{UserID, InviteID, RegistrationMode, BanKind}};
+
+pub mod oidc;
+pub mod throttle;
+pub mod token;
+pub mod totp;
+pub mod username;
+
+use self::{oidc::{OidcConfig, OidcFlow, ExternalIdentity}, throttle::Throttle, token::{ApiToken, ApiTokenID, Scope}, username::UserNameError};
+
+const PENDING_LOGIN_MINUTES: i64 = 5;
+const PENDING_LOGIN_MAX_TRIES: u8 = 5;
+const TEMPORARY_PASSWORD_LENGTH: usize = 16;
+
+pub struct Auth {
+    sessions: HashMap<SessionID, (UserID, NaiveDateTime)>,
+    pending_logins: HashMap<PendingLoginID, (UserID, NaiveDateTime, u8)>,
+    account_throttle: Throttle,
+    ip_login_throttle: Throttle,
+    ip_signup_throttle: Throttle,
+    api_tokens: HashMap<ApiTokenID, ApiToken>,
+    oidc: Option<OidcConfig>,
+    oidc_flows: HashMap<String, OidcFlow>,
+    oidc_identities: HashMap<ExternalIdentity, UserID>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SessionID(pub String);
+
+/// Handed out after the password check when the user has 2FA enabled,
+/// only good for finishing that one login with a TOTP or recovery code
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PendingLoginID(pub String);
+
+pub struct PasswordStore {
+    pub salt: String,
+    pub hashed: String,
+}
+
+pub struct TotpStore {
+    pub secret: String,
+    /// Hashed with [totp::hash_recovery_code], each one is removed once used
+    pub recovery_codes: Vec<String>,
+}
+
+pub enum LoginStep {
+    Done(UserID, SessionID),
+    NeedsTotp(PendingLoginID),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum LoginError {
+    #[error("Wrong credentials")]
+    WrongCredentials,
+    #[error("Invalid user name. Only alphanumeric characters, '_' & '-' are allowed")]
+    InvalidUserName,
+    #[error("Wrong 2FA code")]
+    WrongTotpCode,
+    #[error("Login expired, please enter your password again")]
+    PendingLoginExpired,
+    #[error("Too many attempts, try again after {}", throttle::format_until(.0))]
+    TooManyAttempts(NaiveDateTime),
+    #[error("Your account is {0}")]
+    Banned(String),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum TotpError {
+    #[error("Wrong 2FA code")]
+    WrongCode,
+    #[error("2FA is already enabled")]
+    AlreadyEnabled,
+    #[error("2FA is not enabled")]
+    NotEnabled,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum SignupError {
+    #[error("User with such name already exists")]
+    AlreadyExists,
+    #[error(transparent)]
+    InvalidUserName(#[from] UserNameError),
+    #[error("Too many new accounts from your network, try again after {}", throttle::format_until(.0))]
+    TooManyAttempts(NaiveDateTime),
+    #[error("Registration is closed")]
+    RegistrationClosed,
+    #[error("You need an invite code to join")]
+    InviteRequired,
+    #[error("This invite code doesn't exist or was already used")]
+    InvalidInvite,
+}
+
+impl Auth {
+    pub fn init() -> Self {
+        Self {
+            sessions: HashMap::new(),
+            pending_logins: HashMap::new(),
+            account_throttle: Throttle::new(5, Duration::seconds(30)),
+            ip_login_throttle: Throttle::new(20, Duration::minutes(1)),
+            ip_signup_throttle: Throttle::new(3, Duration::hours(1)),
+            api_tokens: load_api_tokens(),
+            oidc: load_oidc_config(),
+            oidc_flows: HashMap::new(),
+            oidc_identities: load_oidc_identities(),
+        }
+    }
+
+    fn secure_password(password: &str) -> PasswordStore {
+        let salt = Alphanumeric.sample_string(&mut rand::thread_rng(), 16);
+        let mut hasher = Sha256::new();
+        hasher.update(password.to_string() + salt.as_str());
+        let hashed = hasher.finalize();
+        let hashed = String::from_utf8_lossy(hashed.as_slice()).to_string();
+        PasswordStore { salt, hashed }
+    }
+
+    fn match_password(&self, user_name: &str, password: &str) -> bool {
+        let store = load_user_auth(user_name);
+        match store {
+            Some(store) => {
+                let mut hasher = Sha256::new();
+                hasher.update(password.to_string() + store.salt.as_str());
+                let hashed = hasher.finalize();
+                let hashed = String::from_utf8_lossy(hashed.as_slice()).to_string();
+                hashed == store.hashed
+            },
+            None => false,
+        }
+    }
+
+    fn gen_session_id(&self) -> SessionID {
+        let id = SessionID(Alphanumeric.sample_string(&mut rand::thread_rng(), 128));
+        if self.sessions.contains_key(&id) {
+            self.gen_session_id()
+        } else {
+            id
+        }
+    }
+
+    fn gen_pending_login_id(&self) -> PendingLoginID {
+        let id = PendingLoginID(Alphanumeric.sample_string(&mut rand::thread_rng(), 64));
+        if self.pending_logins.contains_key(&id) {
+            self.gen_pending_login_id()
+        } else {
+            id
+        }
+    }
+
+    pub fn create_session(&mut self, user: UserID) -> SessionID {
+        let session_id = self.gen_session_id();
+        self.sessions.insert(session_id.clone(), (user, Local::now().naive_local()));
+        session_id
+    }
+
+    /// Holds the login back until the second factor is in, for those who have one
+    fn start_login(&mut self, user: UserID) -> LoginStep {
+        if self.has_totp(&user) {
+            let pending_id = self.gen_pending_login_id();
+            self.pending_logins.insert(pending_id.clone(), (user, Local::now().naive_local(), 0));
+            LoginStep::NeedsTotp(pending_id)
+        } else {
+            LoginStep::Done(user.clone(), self.create_session(user))
+        }
+    }
+
+    pub fn signup(&mut self, user_name: &str, password: &str, invite: Option<&str>, ip: &str, db: &mut DB) -> Result<UserID, SignupError> {
+        self.ip_signup_throttle.forget_old();
+        let invite = invite.map(|x| x.trim())
+            .filter(|x| !x.is_empty())
+            .map(|x| InviteID(x.to_string()));
+        let mode = db.get_settings().registration_mode;
+        if let Some(until) = self.ip_signup_throttle.locked_until(ip) {
+            Err(SignupError::TooManyAttempts(until))
+        } else if mode == RegistrationMode::Closed {
+            Err(SignupError::RegistrationClosed)
+        } else if mode == RegistrationMode::InviteOnly && invite.is_none() {
+            Err(SignupError::InviteRequired)
+        } else if invite.as_ref().is_some_and(|x| !db.is_invite_usable(x)) {
+            Err(SignupError::InvalidInvite)
+        } else if let Err(e) = username::validate_new_user_name(user_name, db) {
+            Err(SignupError::InvalidUserName(e))
+        } else if load_user_auth(user_name).is_some() {
+            Err(SignupError::AlreadyExists)
+        } else {
+            self.ip_signup_throttle.record_attempt(ip);
+            let password_store = Self::secure_password(password);
+            let user = db.create_new_user(user_name, Some(&password_store));
+            if let Some(invite) = &invite {
+                db.use_invite(invite, &user);
+            }
+            Ok(user)
+        }
+    }
+
+    /// Bans are only revealed once the password checks out
+    pub fn login(&mut self, user_name: &str, password: &str, ip: &str, db: &DB) -> Result<LoginStep, LoginError> {
+        self.account_throttle.forget_old();
+        self.ip_login_throttle.forget_old();
+        let locked_until = self.account_throttle.locked_until(user_name)
+            .max(self.ip_login_throttle.locked_until(ip));
+        if let Some(until) = locked_until {
+            Err(LoginError::TooManyAttempts(until))
+        } else if !username::is_well_formed(user_name) {
+            Err(LoginError::InvalidUserName)
+        } else if self.match_password(user_name, password) {
+            self.account_throttle.reset(user_name);
+            let password_store = Self::secure_password(password);
+            store_user_auth(user_name, &password_store);
+            let id = UserID(user_name.to_string());
+            if let Some(ban) = db.get_ban(&id).filter(|x| x.kind == BanKind::Ban) {
+                Err(LoginError::Banned(ban.describe()))
+            } else {
+                Ok(self.start_login(id))
+            }
+        } else {
+            self.account_throttle.record_attempt(user_name);
+            self.ip_login_throttle.record_attempt(ip);
+            Err(LoginError::WrongCredentials)
+        }
+    }
+
+    /// Second step of the login, `code` may be either a TOTP code or one of the recovery codes
+    pub fn login_totp(&mut self, pending_id: &PendingLoginID, code: &str, db: &DB) -> Result<(UserID, SessionID), LoginError> {
+        let Some((user, started, tries)) = self.pending_logins.get(pending_id) else {
+            return Err(LoginError::PendingLoginExpired);
+        };
+        if Local::now().naive_local().signed_duration_since(*started) > Duration::minutes(PENDING_LOGIN_MINUTES)
+            || *tries >= PENDING_LOGIN_MAX_TRIES {
+            self.pending_logins.remove(pending_id);
+            return Err(LoginError::PendingLoginExpired);
+        }
+        let user = user.clone();
+        if let Some(until) = self.account_throttle.locked_until(&user.0) {
+            return Err(LoginError::TooManyAttempts(until));
+        }
+        if let Some(ban) = db.get_ban(&user).filter(|x| x.kind == BanKind::Ban) {
+            self.pending_logins.remove(pending_id);
+            return Err(LoginError::Banned(ban.describe()));
+        }
+        let Some(mut store) = load_user_totp(&user.0) else {
+            self.pending_logins.remove(pending_id);
+            return Ok((user.clone(), self.create_session(user)));
+        };
+        let recovery_code = totp::hash_recovery_code(code);
+        if totp::verify_code(&store.secret, code) {
+            self.pending_logins.remove(pending_id);
+            Ok((user.clone(), self.create_session(user)))
+        } else if let Some(i) = store.recovery_codes.iter().position(|x| x == &recovery_code) {
+            store.recovery_codes.remove(i);
+            store_user_totp(&user.0, &store);
+            self.pending_logins.remove(pending_id);
+            Ok((user.clone(), self.create_session(user)))
+        } else {
+            if let Some((_, _, tries)) = self.pending_logins.get_mut(pending_id) {
+                *tries += 1;
+            }
+            self.account_throttle.record_attempt(&user.0);
+            Err(LoginError::WrongTotpCode)
+        }
+    }
+
+    pub fn has_totp(&self, user: &UserID) -> bool {
+        load_user_totp(&user.0).is_some()
+    }
+
+    /// Returns the recovery codes in plain text, this is the only time they can be shown
+    pub fn enable_totp(&mut self, user: &UserID, secret: &str, code: &str) -> Result<Vec<String>, TotpError> {
+        if self.has_totp(user) {
+            Err(TotpError::AlreadyEnabled)
+        } else if !totp::verify_code(secret, code) {
+            Err(TotpError::WrongCode)
+        } else {
+            let recovery_codes = totp::gen_recovery_codes();
+            store_user_totp(&user.0, &TotpStore {
+                secret: secret.to_string(),
+                recovery_codes: recovery_codes.iter().map(|x| totp::hash_recovery_code(x)).collect(),
+            });
+            Ok(recovery_codes)
+        }
+    }
+
+    pub fn disable_totp(&mut self, user: &UserID, code: &str) -> Result<(), TotpError> {
+        let Some(store) = load_user_totp(&user.0) else {
+            return Err(TotpError::NotEnabled);
+        };
+        let recovery_code = totp::hash_recovery_code(code);
+        if totp::verify_code(&store.secret, code) || store.recovery_codes.contains(&recovery_code) {
+            delete_user_totp(&user.0);
+            Ok(())
+        } else {
+            Err(TotpError::WrongCode)
+        }
+    }
+
+    pub fn remaining_recovery_codes(&self, user: &UserID) -> usize {
+        load_user_totp(&user.0).map_or(0, |x| x.recovery_codes.len())
+    }
+
+    /// Everything that's currently locked out, as (kind, account name or IP, locked until, attempts)
+    pub fn lockouts(&self) -> Vec<(&'static str, &str, NaiveDateTime, u32)> {
+        let accounts = self.account_throttle.locked().into_iter().map(|(k, u, f)| ("account", k, u, f));
+        let login_ips = self.ip_login_throttle.locked().into_iter().map(|(k, u, f)| ("login IP", k, u, f));
+        let signup_ips = self.ip_signup_throttle.locked().into_iter().map(|(k, u, f)| ("signup IP", k, u, f));
+        accounts.chain(login_ips).chain(signup_ips).collect()
+    }
+
+    pub fn unlock(&mut self, key: &str) {
+        self.account_throttle.reset(key);
+        self.ip_login_throttle.reset(key);
+        self.ip_signup_throttle.reset(key);
+    }
+
+    pub fn logout(&mut self, user: UserSession) {
+        if let Some(session_id) = &user.session_id {
+            self.sessions.remove(session_id);
+        }
+    }
+
+    /// Logs the user out everywhere
+    pub fn end_sessions(&mut self, user: &UserID) {
+        self.sessions.retain(|_, (x, _)| x != user);
+        self.pending_logins.retain(|_, (x, _, _)| x != user);
+    }
+
+    /// When each of the user's sessions was last used, most recent first
+    pub fn get_sessions(&self, user: &UserID) -> Vec<NaiveDateTime> {
+        let mut sessions = self.sessions.values()
+            .filter(|(x, _)| x == user)
+            .map(|(_, last_use)| *last_use)
+            .collect::<Vec<_>>();
+        sessions.sort_unstable_by(|a, b| b.cmp(a));
+        sessions
+    }
+
+    /// Replaces the password with a random one for an admin to pass on, logs the user out everywhere and revokes their API tokens
+    pub fn reset_password(&mut self, user: &UserID) -> String {
+        let password = Alphanumeric.sample_string(&mut rand::thread_rng(), TEMPORARY_PASSWORD_LENGTH);
+        store_user_auth(&user.0, &Self::secure_password(&password));
+        self.end_sessions(user);
+        self.revoke_api_tokens(user);
+        password
+    }
+
+    /// Keeps the user logged in and their tokens working after [DB::rename_user]
+    pub fn rename_user(&mut self, old: &UserID, new: &UserID) {
+        rename_user_auth(&old.0, &new.0);
+        self.sessions.values_mut()
+            .filter(|(user, _)| user == old)
+            .for_each(|(user, _)| *user = new.clone());
+        self.pending_logins.values_mut()
+            .filter(|(user, _, _)| user == old)
+            .for_each(|(user, _, _)| *user = new.clone());
+        for (id, token) in self.api_tokens.iter_mut().filter(|(_, x)| &x.user == old) {
+            token.user = new.clone();
+            store_api_token(id, token);
+        }
+        if self.has_oidc_identity(old) {
+            self.oidc_identities.values_mut()
+                .filter(|x| *x == old)
+                .for_each(|x| *x = new.clone());
+            store_oidc_identities(&self.oidc_identities);
+        }
+    }
+
+    pub fn get_user_for_session_id(&mut self, session_id: SessionID) -> Option<(SessionID, &UserID)> {
+        match self.sessions.remove(&session_id) {
+            Some((user, _)) => {
+                let session_id = self.gen_session_id();
+                self.sessions.insert(session_id.clone(), (user, Local::now().naive_local()));
+                let user = self.sessions.get(&session_id).map(|x| &x.0);
+                user.map(|u| (session_id, u))
+            },
+            None => None,
+        }
+    }
+
+    pub fn delete_sessions_older_than(&mut self, age: &Duration) {
+        self.sessions.retain(|_, (_, last_use)| Local::now().naive_local().signed_duration_since(last_use.clone()).gt(age))
+    }
+}
+
+pub struct UserSession {
+    pub user: UserID,
+    /// `None` when authenticated with an API token
+    pub session_id: Option<SessionID>,
+    /// Only set for API tokens, browser sessions can do anything the user can
+    pub scopes: Option<Vec<Scope>>,
+}
+
+impl UserSession {
+    pub fn keep<'a>(&self, response: &'a mut HttpResponseBuilder) -> &'a mut HttpResponseBuilder {
+        match &self.session_id {
+            Some(session_id) => response.cookie(build_session_cookie(session_id)),
+            None => response,
+        }
+    }
+
+    pub fn allows(&self, scope: Scope) -> bool {
+        self.scopes.as_ref().is_none_or(|x| x.contains(&scope))
+    }
+
+    /// Account management (settings, 2FA, tokens) is off limits for API tokens
+    pub fn is_browser(&self) -> bool {
+        self.scopes.is_none()
+    }
+}
+
+pub fn build_session_cookie<'a>(session_id: &'a SessionID) -> Cookie<'a> {
+    Cookie::build("session-id", session_id.0.as_str())
+        .path("/")
+        //.secure(true) <-- only works with https
+        .same_site(SameSite::Strict)
+        .http_only(true)
+        .max_age(cookie::time::Duration::days(30))
+        .finish()
+}
+
+pub fn build_pending_login_cookie<'a>(pending_id: &'a PendingLoginID) -> Cookie<'a> {
+    Cookie::build("pending-login", pending_id.0.as_str())
+        .path("/")
+        .same_site(SameSite::Strict)
+        .http_only(true)
+        .max_age(cookie::time::Duration::minutes(PENDING_LOGIN_MINUTES))
+        .finish()
+}
+
+pub fn bearer_token(req: &HttpRequest) -> Option<&str> {
+    req.headers().get(AUTHORIZATION)
+        .and_then(|x| x.to_str().ok())
+        .and_then(|x| x.strip_prefix("Bearer "))
+        .map(|x| x.trim())
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum SessionRequestError {
+    #[error("No Session")]
+    NoSession,
+}
+
+impl ResponseError for SessionRequestError {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::UNAUTHORIZED
+    }
+}
+
+impl FromRequest for UserSession {
+    type Error = SessionRequestError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {        
+        let mut auth = req.app_data::<Data<Mutex<Auth>>>().unwrap().lock().unwrap();
+        let session = match bearer_token(req) {
+            Some(token) => auth.get_api_token(token)
+                // Tokens without the read scope can't even look at pages
+                .filter(|x| req.method() != Method::GET || x.scopes.contains(&Scope::Read))
+                .map(|x| UserSession { user: x.user.clone(), session_id: None, scopes: Some(x.scopes.clone()) }),
+            None => req.cookie("session-id")
+                .map(|c| c.value().to_string())
+                .and_then(|id|
+                    auth.get_user_for_session_id(SessionID(id)).map(|(new_session_id, user)| {
+                        UserSession { user: user.clone(), session_id: Some(new_session_id), scopes: None }
+                    })
+                ),
+        };
+        Box::pin(async move {
+            session.map_or_else(|| Err(SessionRequestError::NoSession), |x| Ok(x))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::scratch_store;
+    use super::*;
+
+    const PASSWORD: &str = "correct horse battery";
+
+    fn log_in_to_totp(auth: &mut Auth, db: &DB, user: &UserID) -> PendingLoginID {
+        match auth.login(&user.0, PASSWORD, "127.0.0.1", db) {
+            Ok(LoginStep::NeedsTotp(pending_id)) => pending_id,
+            Ok(LoginStep::Done(_, _)) => panic!("2FA was skipped"),
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    #[test]
+    fn totp_guards_the_login() {
+        let _store = scratch_store();
+        let mut db = DB::load();
+        let mut auth = Auth::init();
+        let user = auth.signup("alice", PASSWORD, None, "127.0.0.1", &mut db).unwrap();
+        let secret = totp::gen_secret();
+        assert!(matches!(auth.enable_totp(&user, &secret, "wrong"), Err(TotpError::WrongCode)));
+        auth.enable_totp(&user, &secret, &totp::current_code(&secret)).unwrap();
+        assert!(matches!(auth.enable_totp(&user, &secret, &totp::current_code(&secret)), Err(TotpError::AlreadyEnabled)));
+
+        let pending_id = log_in_to_totp(&mut auth, &db, &user);
+        assert!(matches!(auth.login_totp(&pending_id, "wrong", &db), Err(LoginError::WrongTotpCode)));
+        assert_eq!(auth.login_totp(&pending_id, &totp::current_code(&secret), &db).unwrap().0, user);
+        assert!(matches!(auth.login_totp(&pending_id, &totp::current_code(&secret), &db), Err(LoginError::PendingLoginExpired)));
+    }
+
+    #[test]
+    fn recovery_codes_work_once_each() {
+        let _store = scratch_store();
+        let mut db = DB::load();
+        let mut auth = Auth::init();
+        let user = auth.signup("bob", PASSWORD, None, "127.0.0.1", &mut db).unwrap();
+        let secret = totp::gen_secret();
+        let recovery_codes = auth.enable_totp(&user, &secret, &totp::current_code(&secret)).unwrap();
+        assert_eq!(auth.remaining_recovery_codes(&user), recovery_codes.len());
+
+        let pending_id = log_in_to_totp(&mut auth, &db, &user);
+        assert_eq!(auth.login_totp(&pending_id, &recovery_codes[0].to_uppercase(), &db).unwrap().0, user);
+        assert_eq!(auth.remaining_recovery_codes(&user), recovery_codes.len() - 1);
+        let pending_id = log_in_to_totp(&mut auth, &db, &user);
+        assert!(matches!(auth.login_totp(&pending_id, &recovery_codes[0], &db), Err(LoginError::WrongTotpCode)));
+
+        assert!(matches!(auth.disable_totp(&user, &recovery_codes[0]), Err(TotpError::WrongCode)));
+        auth.disable_totp(&user, &recovery_codes[1]).unwrap();
+        assert!(!auth.has_totp(&user));
+        assert!(matches!(auth.login(&user.0, PASSWORD, "127.0.0.1", &db), Ok(LoginStep::Done(_, _))));
+    }
+
+    #[test]
+    fn wrong_passwords_lock_the_account() {
+        let _store = scratch_store();
+        let mut db = DB::load();
+        let mut auth = Auth::init();
+        let user = auth.signup("dave", PASSWORD, None, "127.0.0.1", &mut db).unwrap();
+        for i in 0..5 {
+            assert!(matches!(auth.login(&user.0, "wrong", &format!("10.0.0.{}", i), &db), Err(LoginError::WrongCredentials)));
+        }
+        let error = auth.login(&user.0, PASSWORD, "10.0.1.1", &db).err().unwrap();
+        assert!(matches!(error, LoginError::TooManyAttempts(_)));
+        // The date and offset are spelled out, the user may be in another time zone
+        assert!(error.to_string().contains(" UTC"));
+        assert_eq!(auth.lockouts().len(), 1);
+
+        auth.unlock(&user.0);
+        assert!(matches!(auth.login(&user.0, PASSWORD, "10.0.1.1", &db), Ok(LoginStep::Done(_, _))));
+    }
+
+    #[test]
+    fn signups_are_throttled_per_ip() {
+        let _store = scratch_store();
+        let mut db = DB::load();
+        let mut auth = Auth::init();
+        for name in ["erin", "frank", "grace"] {
+            auth.signup(name, PASSWORD, None, "10.0.0.1", &mut db).unwrap();
+        }
+        assert!(matches!(auth.signup("heidi", PASSWORD, None, "10.0.0.1", &mut db), Err(SignupError::TooManyAttempts(_))));
+        auth.signup("heidi", PASSWORD, None, "10.0.0.2", &mut db).unwrap();
+    }
+
+    #[test]
+    fn pending_logins_run_out_of_tries() {
+        let _store = scratch_store();
+        let mut db = DB::load();
+        let mut auth = Auth::init();
+        let user = auth.signup("carol", PASSWORD, None, "127.0.0.1", &mut db).unwrap();
+        let secret = totp::gen_secret();
+        auth.enable_totp(&user, &secret, &totp::current_code(&secret)).unwrap();
+        let pending_id = log_in_to_totp(&mut auth, &db, &user);
+        for _ in 0..PENDING_LOGIN_MAX_TRIES {
+            assert!(auth.login_totp(&pending_id, "wrong", &db).is_err());
+        }
+        assert!(auth.login_totp(&pending_id, &totp::current_code(&secret), &db).is_err());
+    }
+
+    #[test]
+    fn password_resets_lock_out_every_way_in() {
+        let _store = scratch_store();
+        let mut db = DB::load();
+        let mut auth = Auth::init();
+        let user = auth.signup("carol", PASSWORD, None, "127.0.0.1", &mut db).unwrap();
+        let session_id = auth.create_session(user.clone());
+        let token = auth.create_api_token(&user, "bot", vec![Scope::Read]).unwrap();
+
+        let password = auth.reset_password(&user);
+        assert!(auth.get_user_for_session_id(session_id).is_none() && auth.get_api_token(&token).is_none());
+        assert!(matches!(auth.login("carol", PASSWORD, "127.0.0.1", &db), Err(LoginError::WrongCredentials)));
+        assert!(matches!(auth.login("carol", &password, "127.0.0.1", &db), Ok(LoginStep::Done(_, _))));
+    }
+}
\ No newline at end of file
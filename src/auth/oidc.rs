@@ -0,0 +1,533 @@
+use std::{sync::Arc, time};
+
+use actix_web::cookie::{self, Cookie, SameSite};
+use chrono::{NaiveDateTime, Local, Duration, Utc};
+use data_encoding::BASE64URL_NOPAD;
+use json::JsonValue;
+use openssl::{bn::BigNum, hash::MessageDigest, pkey::PKey, rsa::Rsa, sign::Verifier};
+use rand::distributions::{Alphanumeric, DistString};
+use sha2::{Sha256, Digest};
+
+use crate::{data::{UserID, RegistrationMode, BanKind}, db::{DB, store::{store_oidc_identities, load_user_auth}}};
+
+use super::{Auth, LoginStep, username};
+
+const FLOW_MINUTES: i64 = 10;
+/// How far the issuer's clock may be ahead of ours
+const CLOCK_SKEW_SECONDS: i64 = 60;
+
+/// The one issuer we trust, read from `store/auth-oidc/config.json`
+#[derive(Clone)]
+pub struct OidcConfig {
+    pub issuer: String,
+    pub client_id: String,
+    /// Public clients only have PKCE to go on
+    pub client_secret: Option<String>,
+    pub redirect_uri: String,
+    /// What the login button says
+    pub label: String,
+}
+
+/// `sub` is only unique within its `iss`, so both make up the key
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ExternalIdentity {
+    pub issuer: String,
+    pub subject: String,
+}
+
+/// The endpoints from the issuer's discovery document
+pub struct Provider {
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+/// One trip to the issuer and back, keyed by the `state` it carries
+pub struct OidcFlow {
+    provider: Provider,
+    code_verifier: String,
+    nonce: String,
+    /// Set when a logged in user connects their account instead of logging in
+    pub link_to: Option<UserID>,
+    started: NaiveDateTime,
+}
+
+/// The claims we use out of a verified ID token
+pub struct IdToken {
+    pub identity: ExternalIdentity,
+    preferred_name: Option<String>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum OidcError {
+    #[error("Single sign-on is not set up")]
+    NotConfigured,
+    #[error("Couldn't reach the sign-on provider")]
+    Unreachable,
+    #[error("The sign-on provider sent something we don't understand")]
+    InvalidResponse,
+    #[error("The sign-on provider's ID token didn't check out")]
+    InvalidIdToken,
+    #[error("Sign-on took too long or was started in another browser, please try again")]
+    UnknownFlow,
+    #[error("Sign-on was cancelled ({0})")]
+    Denied(String),
+    #[error("This identity is already linked to another account")]
+    AlreadyLinked,
+    #[error("No account is linked to this identity and registration is closed")]
+    RegistrationClosed,
+    #[error("No account is linked to this identity and new accounts need an invite, sign up with one and link your identity in settings")]
+    InviteRequired,
+    #[error("Couldn't find a free user name, sign up normally and link your identity in settings")]
+    NoFreeUserName,
+    #[error("Your account has no password, unlinking would lock you out")]
+    LastLoginMethod,
+    #[error("Your account is {0}")]
+    Banned(String),
+}
+
+fn agent() -> Result<ureq::Agent, OidcError> {
+    let tls = native_tls::TlsConnector::new().map_err(|_| OidcError::Unreachable)?;
+    Ok(ureq::AgentBuilder::new()
+        .tls_connector(Arc::new(tls))
+        .timeout(time::Duration::from_secs(10))
+        .build())
+}
+
+fn read_json(response: Result<ureq::Response, ureq::Error>) -> Result<JsonValue, OidcError> {
+    let body = match response {
+        Ok(response) => response.into_string().map_err(|_| OidcError::InvalidResponse)?,
+        Err(ureq::Error::Status(_, _)) => return Err(OidcError::InvalidResponse),
+        Err(ureq::Error::Transport(_)) => return Err(OidcError::Unreachable),
+    };
+    json::parse(&body).map_err(|_| OidcError::InvalidResponse)
+}
+
+/// Fetches the discovery document, blocks on the network
+pub fn discover(config: &OidcConfig) -> Result<Provider, OidcError> {
+    let url = config.issuer.trim_end_matches('/').to_string() + "/.well-known/openid-configuration";
+    let json = read_json(agent()?.get(&url).call())?;
+    if json["issuer"].as_str() != Some(config.issuer.as_str()) {
+        return Err(OidcError::InvalidResponse);
+    }
+    let endpoint = |key: &str| json[key].as_str()
+        .map(|x| x.to_string())
+        .ok_or(OidcError::InvalidResponse);
+    Ok(Provider {
+        authorization_endpoint: endpoint("authorization_endpoint")?,
+        token_endpoint: endpoint("token_endpoint")?,
+        jwks_uri: endpoint("jwks_uri")?,
+    })
+}
+
+/// Trades the authorization code for an ID token and verifies it, blocks on the network
+pub fn redeem_code(config: &OidcConfig, flow: &OidcFlow, code: &str) -> Result<IdToken, OidcError> {
+    let agent = agent()?;
+    let mut form = vec![
+        ("grant_type", "authorization_code"),
+        ("code", code),
+        ("redirect_uri", config.redirect_uri.as_str()),
+        ("client_id", config.client_id.as_str()),
+        ("code_verifier", flow.code_verifier.as_str()),
+    ];
+    if let Some(secret) = &config.client_secret {
+        form.push(("client_secret", secret.as_str()));
+    }
+    let tokens = read_json(agent.post(&flow.provider.token_endpoint).send_form(&form))?;
+    let id_token = tokens["id_token"].as_str().ok_or(OidcError::InvalidResponse)?;
+    let jwks = read_json(agent.get(&flow.provider.jwks_uri).call())?;
+    verify_id_token(config, flow, id_token, &jwks)
+}
+
+fn decode_part(part: &str) -> Result<Vec<u8>, OidcError> {
+    BASE64URL_NOPAD.decode(part.as_bytes()).map_err(|_| OidcError::InvalidIdToken)
+}
+
+fn parse_part(part: &str) -> Result<JsonValue, OidcError> {
+    json::parse(&String::from_utf8_lossy(&decode_part(part)?)).map_err(|_| OidcError::InvalidIdToken)
+}
+
+fn verify_rs256(jwk: &JsonValue, message: &[u8], signature: &[u8]) -> Option<bool> {
+    let n = BigNum::from_slice(&BASE64URL_NOPAD.decode(jwk["n"].as_str()?.as_bytes()).ok()?).ok()?;
+    let e = BigNum::from_slice(&BASE64URL_NOPAD.decode(jwk["e"].as_str()?.as_bytes()).ok()?).ok()?;
+    let key = PKey::from_rsa(Rsa::from_public_components(n, e).ok()?).ok()?;
+    let mut verifier = Verifier::new(MessageDigest::sha256(), &key).ok()?;
+    verifier.update(message).ok()?;
+    verifier.verify(signature).ok()
+}
+
+/// Only RS256 is supported, it's the one algorithm every issuer has to offer
+fn verify_id_token(config: &OidcConfig, flow: &OidcFlow, id_token: &str, jwks: &JsonValue) -> Result<IdToken, OidcError> {
+    let [header_part, claims_part, signature_part] = id_token.split('.').collect::<Vec<_>>()[..] else {
+        return Err(OidcError::InvalidIdToken);
+    };
+    let header = parse_part(header_part)?;
+    if header["alg"].as_str() != Some("RS256") {
+        return Err(OidcError::InvalidIdToken);
+    }
+    let kid = header["kid"].as_str();
+    let signed = format!("{}.{}", header_part, claims_part);
+    let signature = decode_part(signature_part)?;
+    let verified = jwks["keys"].members()
+        .filter(|x| x["kty"].as_str() == Some("RSA"))
+        .filter(|x| kid.is_none() || x["kid"].as_str() == kid)
+        .any(|x| verify_rs256(x, signed.as_bytes(), &signature) == Some(true));
+    if !verified {
+        return Err(OidcError::InvalidIdToken);
+    }
+
+    let claims = parse_part(claims_part)?;
+    let audience_ok = match &claims["aud"] {
+        JsonValue::Array(audience) => audience.iter().any(|x| x.as_str() == Some(config.client_id.as_str())),
+        x => x.as_str() == Some(config.client_id.as_str()),
+    };
+    let not_expired = claims["exp"].as_i64()
+        .is_some_and(|x| x + CLOCK_SKEW_SECONDS > Utc::now().timestamp());
+    if claims["iss"].as_str() != Some(config.issuer.as_str())
+        || !audience_ok
+        || !not_expired
+        || claims["nonce"].as_str() != Some(flow.nonce.as_str()) {
+        return Err(OidcError::InvalidIdToken);
+    }
+    let subject = claims["sub"].as_str().ok_or(OidcError::InvalidIdToken)?;
+    let preferred_name = claims["preferred_username"].as_str()
+        .or_else(|| claims["email"].as_str().and_then(|x| x.split('@').next()))
+        .map(|x| x.to_string());
+    Ok(IdToken {
+        identity: ExternalIdentity {
+            issuer: config.issuer.clone(),
+            subject: subject.to_string(),
+        },
+        preferred_name,
+    })
+}
+
+/// Turns whatever the issuer calls the user into a handle nobody has yet
+fn free_user_name(preferred_name: Option<&str>, db: &DB) -> Option<String> {
+    // Leaves room for a `-99` suffix
+    let base = preferred_name.unwrap_or("user").chars()
+        .map(|x| if x.is_ascii_alphanumeric() || x == '_' || x == '-' { x } else { '_' })
+        .take(username::MAX_LENGTH - 3)
+        .collect::<String>();
+    let base = if base.len() < username::MIN_LENGTH { format!("user_{}", base) } else { base };
+    std::iter::once(base.clone())
+        .chain((2..100).map(|x| format!("{}-{}", base, x)))
+        .find(|x| username::validate_new_user_name(x, db).is_ok())
+}
+
+impl Auth {
+    pub fn oidc_config(&self) -> Option<&OidcConfig> {
+        self.oidc.as_ref()
+    }
+
+    /// Returns the state to keep in the browser and where to send it
+    pub fn begin_oidc(&mut self, provider: Provider, link_to: Option<UserID>) -> Result<(String, String), OidcError> {
+        let config = self.oidc.as_ref().ok_or(OidcError::NotConfigured)?;
+        let now = Local::now().naive_local();
+        self.oidc_flows.retain(|_, x| now.signed_duration_since(x.started) < Duration::minutes(FLOW_MINUTES));
+
+        let state = Alphanumeric.sample_string(&mut rand::thread_rng(), 32);
+        let nonce = Alphanumeric.sample_string(&mut rand::thread_rng(), 32);
+        let code_verifier = Alphanumeric.sample_string(&mut rand::thread_rng(), 64);
+        let code_challenge = BASE64URL_NOPAD.encode(&Sha256::digest(code_verifier.as_bytes()));
+        let query = serde_urlencoded::to_string([
+            ("response_type", "code"),
+            ("client_id", config.client_id.as_str()),
+            ("redirect_uri", config.redirect_uri.as_str()),
+            ("scope", "openid profile email"),
+            ("state", state.as_str()),
+            ("nonce", nonce.as_str()),
+            ("code_challenge", code_challenge.as_str()),
+            ("code_challenge_method", "S256"),
+        ]).unwrap();
+        let separator = if provider.authorization_endpoint.contains('?') { '&' } else { '?' };
+        let url = format!("{}{}{}", provider.authorization_endpoint, separator, query);
+
+        self.oidc_flows.insert(state.clone(), OidcFlow { provider, code_verifier, nonce, link_to, started: now });
+        Ok((state, url))
+    }
+
+    /// Each flow can only be finished once
+    pub fn take_oidc_flow(&mut self, state: &str) -> Option<OidcFlow> {
+        let now = Local::now().naive_local();
+        self.oidc_flows.remove(state)
+            .filter(|x| now.signed_duration_since(x.started) < Duration::minutes(FLOW_MINUTES))
+    }
+
+    /// Logs in whoever the identity is linked to, new identities get a fresh account.
+    /// Only open registration lets new identities in, an invite can't be passed along through the issuer,
+    /// so invited people sign up normally and link their identity in settings.
+    /// Accounts with 2FA still have to go through the second step like any other login
+    pub fn login_oidc(&mut self, id_token: IdToken, db: &mut DB) -> Result<LoginStep, OidcError> {
+        let user = match self.oidc_identities.get(&id_token.identity) {
+            Some(user) if db.get_user(user).is_some() => user.clone(),
+            _ => {
+                match db.get_settings().registration_mode {
+                    RegistrationMode::Open => {},
+                    RegistrationMode::InviteOnly => return Err(OidcError::InviteRequired),
+                    RegistrationMode::Closed => return Err(OidcError::RegistrationClosed),
+                }
+                let name = free_user_name(id_token.preferred_name.as_deref(), db)
+                    .ok_or(OidcError::NoFreeUserName)?;
+                let user = db.create_new_user(&name, None);
+                self.oidc_identities.insert(id_token.identity, user.clone());
+                store_oidc_identities(&self.oidc_identities);
+                user
+            },
+        };
+        if let Some(ban) = db.get_ban(&user).filter(|x| x.kind == BanKind::Ban) {
+            return Err(OidcError::Banned(ban.describe()));
+        }
+        Ok(self.start_login(user))
+    }
+
+    pub fn link_oidc_identity(&mut self, user: &UserID, identity: ExternalIdentity, db: &DB) -> Result<(), OidcError> {
+        match self.oidc_identities.get(&identity) {
+            Some(owner) if owner != user && db.get_user(owner).is_some() => Err(OidcError::AlreadyLinked),
+            _ => {
+                self.oidc_identities.insert(identity, user.clone());
+                store_oidc_identities(&self.oidc_identities);
+                Ok(())
+            },
+        }
+    }
+
+    pub fn unlink_oidc_identities(&mut self, user: &UserID) -> Result<(), OidcError> {
+        if load_user_auth(&user.0).is_none() {
+            return Err(OidcError::LastLoginMethod);
+        }
+        self.oidc_identities.retain(|_, x| x != user);
+        store_oidc_identities(&self.oidc_identities);
+        Ok(())
+    }
+
+    pub fn has_oidc_identity(&self, user: &UserID) -> bool {
+        self.oidc_identities.values().any(|x| x == user)
+    }
+}
+
+/// Has to survive the cross-site redirect back from the issuer, so it can't be `Strict`
+pub fn build_oidc_state_cookie(state: &str) -> Cookie<'_> {
+    Cookie::build("oidc-state", state)
+        .path("/auth/oidc")
+        .same_site(SameSite::Lax)
+        .http_only(true)
+        .max_age(cookie::time::Duration::minutes(FLOW_MINUTES))
+        .finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::{Read, Write, BufRead, BufReader}, net::TcpListener, sync::Mutex, thread};
+
+    use actix_web::{App, test::{TestRequest, init_service, call_and_read_body}, web::Data, cookie::Cookie};
+    use json::object;
+    use openssl::{pkey::Private, sign::Signer};
+
+    use crate::{auth::{PasswordStore, totp}, testing::scratch_store, routes::auth_oidc_callback};
+    use super::*;
+
+    const CLIENT_ID: &str = "lamda-network";
+
+    /// Serves discovery, the token endpoint and the keys on localhost,
+    /// handing out whatever ID token the test put into `id_token` last
+    struct MockIssuer {
+        config: OidcConfig,
+        key: PKey<Private>,
+        id_token: Arc<Mutex<String>>,
+    }
+
+    impl MockIssuer {
+        fn start() -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let issuer = format!("http://{}", listener.local_addr().unwrap());
+            let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+            let rsa = key.rsa().unwrap();
+            let discovery = object! {
+                issuer: issuer.as_str(),
+                authorization_endpoint: issuer.clone() + "/authorize",
+                token_endpoint: issuer.clone() + "/token",
+                jwks_uri: issuer.clone() + "/jwks",
+            }.to_string();
+            let jwks = object! {
+                keys: [{
+                    kty: "RSA",
+                    kid: "test",
+                    n: BASE64URL_NOPAD.encode(&rsa.n().to_vec()),
+                    e: BASE64URL_NOPAD.encode(&rsa.e().to_vec()),
+                }],
+            }.to_string();
+            let id_token = Arc::new(Mutex::new(String::new()));
+            let served_token = id_token.clone();
+            thread::spawn(move || for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { continue };
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut content_length = 0;
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).unwrap();
+                    if header.trim().is_empty() {
+                        break;
+                    }
+                    if let Some((_, value)) = header.split_once(':').filter(|(x, _)| x.eq_ignore_ascii_case("content-length")) {
+                        content_length = value.trim().parse().unwrap();
+                    }
+                }
+                reader.read_exact(&mut vec![0; content_length]).unwrap();
+                let body = match request_line.split(' ').nth(1).unwrap_or_default() {
+                    "/.well-known/openid-configuration" => discovery.clone(),
+                    "/token" => object! { id_token: served_token.lock().unwrap().as_str() }.to_string(),
+                    "/jwks" => jwks.clone(),
+                    _ => "{}".to_string(),
+                };
+                let _ = write!(stream, "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body);
+            });
+            let config = OidcConfig {
+                issuer,
+                client_id: CLIENT_ID.to_string(),
+                client_secret: None,
+                redirect_uri: "http://localhost:8080/auth/oidc/callback".to_string(),
+                label: "Mock".to_string(),
+            };
+            Self { config, key, id_token }
+        }
+
+        fn sign(&self, claims: JsonValue) -> String {
+            let header = BASE64URL_NOPAD.encode(object! { alg: "RS256", kid: "test" }.to_string().as_bytes());
+            let claims = BASE64URL_NOPAD.encode(claims.to_string().as_bytes());
+            let mut signer = Signer::new(MessageDigest::sha256(), &self.key).unwrap();
+            signer.update(format!("{}.{}", header, claims).as_bytes()).unwrap();
+            format!("{}.{}.{}", header, claims, BASE64URL_NOPAD.encode(&signer.sign_to_vec().unwrap()))
+        }
+
+        /// Runs the whole trip for `subject`, the issuer signing `nonce` instead of the flow's own when given
+        fn redeem(&self, auth: &mut Auth, subject: &str, nonce: Option<&str>, link_to: Option<UserID>) -> Result<IdToken, OidcError> {
+            let (state, url) = auth.begin_oidc(discover(&self.config)?, link_to).unwrap();
+            assert!(url.starts_with(&(self.config.issuer.clone() + "/authorize?")) && url.contains("code_challenge_method=S256"));
+            let flow = auth.take_oidc_flow(&state).unwrap();
+            *self.id_token.lock().unwrap() = self.sign(object! {
+                iss: self.config.issuer.as_str(),
+                aud: CLIENT_ID,
+                sub: subject,
+                exp: Utc::now().timestamp() + 300,
+                nonce: nonce.unwrap_or(flow.nonce.as_str()),
+                preferred_username: "Sam Smith",
+            });
+            redeem_code(&self.config, &flow, "code")
+        }
+    }
+
+    fn auth_for(issuer: &MockIssuer) -> Auth {
+        let mut auth = Auth::init();
+        auth.oidc = Some(issuer.config.clone());
+        auth
+    }
+
+    fn logged_in(step: Result<LoginStep, OidcError>) -> UserID {
+        match step {
+            Ok(LoginStep::Done(user, _)) => user,
+            Ok(LoginStep::NeedsTotp(_)) => panic!("Asked for 2FA"),
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    #[test]
+    fn flows_only_finish_once_with_their_own_state_and_nonce() {
+        let _store = scratch_store();
+        let issuer = MockIssuer::start();
+        let mut auth = auth_for(&issuer);
+        let (state, _) = auth.begin_oidc(discover(&issuer.config).unwrap(), None).unwrap();
+        assert!(auth.take_oidc_flow("some other state").is_none());
+        assert!(auth.take_oidc_flow(&state).is_some());
+        assert!(auth.take_oidc_flow(&state).is_none());
+
+        assert!(matches!(issuer.redeem(&mut auth, "sub-1", Some("replayed"), None), Err(OidcError::InvalidIdToken)));
+        assert_eq!(issuer.redeem(&mut auth, "sub-1", None, None).unwrap().identity.subject, "sub-1");
+    }
+
+    #[test]
+    fn callback_refuses_a_state_from_another_browser() {
+        let _store = scratch_store();
+        let issuer = MockIssuer::start();
+        let mut auth = auth_for(&issuer);
+        let (state, _) = auth.begin_oidc(discover(&issuer.config).unwrap(), None).unwrap();
+        let auth = Data::new(Mutex::new(auth));
+        let db = Data::new(Mutex::new(DB::load()));
+        let body = actix_web::rt::System::new().block_on(async {
+            let app = init_service(App::new()
+                .app_data(auth.clone())
+                .app_data(db.clone())
+                .service(auth_oidc_callback)).await;
+            let request = TestRequest::get()
+                .uri(&format!("/auth/oidc/callback?state={}&code=code", state))
+                .cookie(Cookie::new("oidc-state", "planted"))
+                .to_request();
+            call_and_read_body(&app, request).await
+        });
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains("/login?error="), "{}", body);
+        // The flow is still there for the browser that started it
+        assert!(auth.lock().unwrap().take_oidc_flow(&state).is_some());
+    }
+
+    #[test]
+    fn unknown_subjects_get_a_new_account_only_while_registration_is_open() {
+        let _store = scratch_store();
+        let issuer = MockIssuer::start();
+        let mut auth = auth_for(&issuer);
+        let mut db = DB::load();
+        let id_token = issuer.redeem(&mut auth, "sub-1", None, None).unwrap();
+        let user = logged_in(auth.login_oidc(id_token, &mut db));
+        assert_eq!(user.0, "Sam_Smith");
+        assert!(db.get_user(&user).is_some() && auth.has_oidc_identity(&user));
+        // Same subject, same account
+        let id_token = issuer.redeem(&mut auth, "sub-1", None, None).unwrap();
+        assert_eq!(logged_in(auth.login_oidc(id_token, &mut db)), user);
+        // A new subject can't take the name that's in use now
+        let id_token = issuer.redeem(&mut auth, "sub-2", None, None).unwrap();
+        assert_eq!(logged_in(auth.login_oidc(id_token, &mut db)).0, "Sam_Smith-2");
+
+        db.set_registration(RegistrationMode::InviteOnly, 0);
+        let id_token = issuer.redeem(&mut auth, "sub-3", None, None).unwrap();
+        assert!(matches!(auth.login_oidc(id_token, &mut db), Err(OidcError::InviteRequired)));
+        let id_token = issuer.redeem(&mut auth, "sub-1", None, None).unwrap();
+        assert_eq!(logged_in(auth.login_oidc(id_token, &mut db)), user);
+        db.set_registration(RegistrationMode::Closed, 0);
+        let id_token = issuer.redeem(&mut auth, "sub-3", None, None).unwrap();
+        assert!(matches!(auth.login_oidc(id_token, &mut db), Err(OidcError::RegistrationClosed)));
+        let id_token = issuer.redeem(&mut auth, "sub-1", None, None).unwrap();
+        assert_eq!(logged_in(auth.login_oidc(id_token, &mut db)), user);
+    }
+
+    #[test]
+    fn linking_logs_in_to_the_existing_account() {
+        let _store = scratch_store();
+        let issuer = MockIssuer::start();
+        let mut auth = auth_for(&issuer);
+        let mut db = DB::load();
+        let carol = db.create_new_user("carol", Some(&PasswordStore { salt: String::new(), hashed: String::new() }));
+        let dave = db.create_new_user("dave", None);
+
+        let id_token = issuer.redeem(&mut auth, "sub-carol", None, Some(carol.clone())).unwrap();
+        auth.link_oidc_identity(&carol, id_token.identity, &db).unwrap();
+        let id_token = issuer.redeem(&mut auth, "sub-carol", None, None).unwrap();
+        assert!(matches!(auth.link_oidc_identity(&dave, id_token.identity.clone(), &db), Err(OidcError::AlreadyLinked)));
+        assert_eq!(logged_in(auth.login_oidc(id_token, &mut db)), carol);
+        assert!(db.get_user(&UserID("Sam_Smith".to_string())).is_none());
+        // 2FA isn't skipped just because the issuer vouched for the identity
+        let secret = totp::gen_secret();
+        auth.enable_totp(&carol, &secret, &totp::current_code(&secret)).unwrap();
+        let id_token = issuer.redeem(&mut auth, "sub-carol", None, None).unwrap();
+        let Ok(LoginStep::NeedsTotp(pending_id)) = auth.login_oidc(id_token, &mut db) else {
+            panic!("2FA was skipped");
+        };
+        assert_eq!(auth.login_totp(&pending_id, &totp::current_code(&secret), &db).unwrap().0, carol);
+
+        // Unlinking is fine for carol, who still has a password, dave would be locked out
+        assert!(matches!(auth.unlink_oidc_identities(&dave), Err(OidcError::LastLoginMethod)));
+        auth.unlink_oidc_identities(&carol).unwrap();
+        assert!(!auth.has_oidc_identity(&carol));
+    }
+}
\ No newline at end of file
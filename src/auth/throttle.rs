@@ -0,0 +1,116 @@
+use std::{cmp::Reverse, collections::HashMap};
+
+use chrono::{NaiveDateTime, Local, Duration};
+
+/// Attempts older than this are forgotten
+const FORGET_AFTER_HOURS: i64 = 24;
+const MAX_LOCKOUT_HOURS: i64 = 24;
+
+struct Attempts {
+    count: u32,
+    last: NaiveDateTime,
+    locked_until: Option<NaiveDateTime>,
+}
+
+/// Whoever is locked out may be anywhere, so the date and our offset from UTC are spelled out
+pub fn format_until(until: &NaiveDateTime) -> String {
+    match until.and_local_timezone(Local).earliest() {
+        Some(until) => until.format("%Y-%m-%d %H:%M:%S UTC%:z").to_string(),
+        None => until.format("%Y-%m-%d %H:%M:%S").to_string(),
+    }
+}
+
+/// Counts attempts per key (an account name or an IP address)
+/// and locks the key out for exponentially longer once the free attempts are used up
+pub struct Throttle {
+    free_attempts: u32,
+    base_lockout: Duration,
+    attempts: HashMap<String, Attempts>,
+}
+
+impl Throttle {
+    pub fn new(free_attempts: u32, base_lockout: Duration) -> Self {
+        Self {
+            free_attempts,
+            base_lockout,
+            attempts: HashMap::new(),
+        }
+    }
+
+    /// Returns when the key may try again, if it's locked out right now
+    pub fn locked_until(&self, key: &str) -> Option<NaiveDateTime> {
+        let now = Local::now().naive_local();
+        self.attempts.get(key)
+            .and_then(|x| x.locked_until)
+            .filter(|until| until > &now)
+    }
+
+    /// Logins only count the attempts that failed, signups count every account that was made
+    pub fn record_attempt(&mut self, key: &str) {
+        let now = Local::now().naive_local();
+        let attempts = self.attempts.entry(key.to_string()).or_insert(Attempts { count: 0, last: now, locked_until: None });
+        if now.signed_duration_since(attempts.last) > Duration::hours(FORGET_AFTER_HOURS) {
+            attempts.count = 0;
+        }
+        attempts.count += 1;
+        attempts.last = now;
+        if attempts.count >= self.free_attempts {
+            let exponent = (attempts.count - self.free_attempts).min(16);
+            let lockout = (self.base_lockout * 2i32.pow(exponent)).min(Duration::hours(MAX_LOCKOUT_HOURS));
+            attempts.locked_until = Some(now + lockout);
+        }
+    }
+
+    pub fn reset(&mut self, key: &str) {
+        self.attempts.remove(key);
+    }
+
+    /// Keys that are locked out right now, with when the lock ends and how many attempts got them there
+    pub fn locked(&self) -> Vec<(&str, NaiveDateTime, u32)> {
+        let now = Local::now().naive_local();
+        let mut locked = self.attempts.iter()
+            .filter_map(|(key, x)| x.locked_until
+                .filter(|until| until > &now)
+                .map(|until| (key.as_str(), until, x.count)))
+            .collect::<Vec<_>>();
+        locked.sort_unstable_by_key(|x| Reverse(x.1));
+        locked
+    }
+
+    pub fn forget_old(&mut self) {
+        let now = Local::now().naive_local();
+        self.attempts.retain(|_, x| now.signed_duration_since(x.last) <= Duration::hours(FORGET_AFTER_HOURS)
+            || x.locked_until.is_some_and(|until| until > now))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locks_out_for_longer_each_time() {
+        let mut throttle = Throttle::new(3, Duration::minutes(1));
+        throttle.record_attempt("1.2.3.4");
+        throttle.record_attempt("1.2.3.4");
+        assert!(throttle.locked_until("1.2.3.4").is_none());
+        throttle.record_attempt("1.2.3.4");
+        let first = throttle.locked_until("1.2.3.4").unwrap();
+        throttle.record_attempt("1.2.3.4");
+        let second = throttle.locked_until("1.2.3.4").unwrap();
+        assert!(second - first > Duration::seconds(50));
+        assert!(throttle.locked_until("5.6.7.8").is_none());
+        assert_eq!(throttle.locked(), vec![("1.2.3.4", second, 4)]);
+
+        throttle.reset("1.2.3.4");
+        assert!(throttle.locked_until("1.2.3.4").is_none());
+    }
+
+    #[test]
+    fn lockouts_stay_under_a_day() {
+        let mut throttle = Throttle::new(1, Duration::hours(1));
+        (0..40).for_each(|_| throttle.record_attempt("alice"));
+        let until = throttle.locked_until("alice").unwrap();
+        assert!(until - Local::now().naive_local() <= Duration::hours(MAX_LOCKOUT_HOURS));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,191 @@
+use chrono::{DateTime, Utc};
+use rand::distributions::{Alphanumeric, DistString};
+use sha2::{Sha256, Digest};
+
+use crate::{data::UserID, db::store::{store_api_token, delete_api_token}};
+
+use super::Auth;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ApiTokenID(pub String);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    Read,
+    Post,
+    Moderate,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 3] = [Scope::Read, Scope::Post, Scope::Moderate];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::Read => "read",
+            Scope::Post => "post",
+            Scope::Moderate => "moderate",
+        }
+    }
+
+    pub fn parse(scope: &str) -> Option<Scope> {
+        Scope::ALL.into_iter().find(|x| x.as_str() == scope)
+    }
+}
+
+/// Lets bots and scripts act as a user through `Authorization: Bearer <id>.<secret>`,
+/// but only within the scopes it was minted with
+pub struct ApiToken {
+    pub user: UserID,
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    pub created: DateTime<Utc>,
+    pub hashed_secret: String,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ApiTokenError {
+    #[error("Give the token a name")]
+    EmptyName,
+    #[error("Pick at least one scope")]
+    NoScopes,
+}
+
+fn hash_secret(secret: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(secret);
+    format!("{:x}", hasher.finalize())
+}
+
+impl Auth {
+    fn gen_api_token_id(&self) -> ApiTokenID {
+        let id = ApiTokenID(Alphanumeric.sample_string(&mut rand::thread_rng(), 16));
+        if self.api_tokens.contains_key(&id) {
+            self.gen_api_token_id()
+        } else {
+            id
+        }
+    }
+
+    /// Returns the full token, this is the only time it can be shown
+    pub fn create_api_token(&mut self, user: &UserID, name: &str, scopes: Vec<Scope>) -> Result<String, ApiTokenError> {
+        if name.trim().is_empty() {
+            return Err(ApiTokenError::EmptyName);
+        }
+        if scopes.is_empty() {
+            return Err(ApiTokenError::NoScopes);
+        }
+        let id = self.gen_api_token_id();
+        let secret = Alphanumeric.sample_string(&mut rand::thread_rng(), 48);
+        let token = ApiToken {
+            user: user.clone(),
+            name: name.trim().to_string(),
+            scopes,
+            created: Utc::now(),
+            hashed_secret: hash_secret(&secret),
+        };
+        store_api_token(&id, &token);
+        self.api_tokens.insert(id.clone(), token);
+        Ok(format!("{}.{}", id.0, secret))
+    }
+
+    pub fn revoke_api_token(&mut self, user: &UserID, id: &ApiTokenID) {
+        if self.api_tokens.get(id).is_some_and(|x| &x.user == user) {
+            self.api_tokens.remove(id);
+            delete_api_token(id);
+        }
+    }
+
+    /// For when whoever is behind the tokens shouldn't get in anymore
+    pub fn revoke_api_tokens(&mut self, user: &UserID) {
+        let ids = self.api_tokens.iter()
+            .filter(|(_, x)| &x.user == user)
+            .map(|(id, _)| id.clone())
+            .collect::<Vec<_>>();
+        for id in ids {
+            self.api_tokens.remove(&id);
+            delete_api_token(&id);
+        }
+    }
+
+    pub fn api_tokens_for_user(&self, user: &UserID) -> Vec<(&ApiTokenID, &ApiToken)> {
+        let mut tokens = self.api_tokens.iter()
+            .filter(|(_, x)| &x.user == user)
+            .collect::<Vec<_>>();
+        tokens.sort_unstable_by_key(|(_, x)| x.created);
+        tokens
+    }
+
+    pub fn get_api_token(&self, token: &str) -> Option<&ApiToken> {
+        let (id, secret) = token.split_once('.')?;
+        self.api_tokens.get(&ApiTokenID(id.to_string()))
+            .filter(|x| x.hashed_secret == hash_secret(secret))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use actix_web::{FromRequest, test::TestRequest, web::Data, http::header::AUTHORIZATION};
+
+    use crate::{auth::UserSession, testing::scratch_store};
+    use super::*;
+
+    fn session(auth: &Data<Mutex<Auth>>, request: TestRequest, token: &str) -> Option<UserSession> {
+        let request = request
+            .app_data(auth.clone())
+            .insert_header((AUTHORIZATION, format!("Bearer {}", token)))
+            .to_http_request();
+        actix_web::rt::System::new().block_on(UserSession::extract(&request)).ok()
+    }
+
+    #[test]
+    fn tokens_stay_within_their_scopes() {
+        let _store = scratch_store();
+        let auth = Data::new(Mutex::new(Auth::init()));
+        let user = UserID("alice".to_string());
+        let reader = auth.lock().unwrap().create_api_token(&user, "reader", vec![Scope::Read]).unwrap();
+        let poster = auth.lock().unwrap().create_api_token(&user, "poster", vec![Scope::Post]).unwrap();
+        let moderator = auth.lock().unwrap().create_api_token(&user, "moderator", vec![Scope::Read, Scope::Moderate]).unwrap();
+
+        let reading = session(&auth, TestRequest::get(), &reader).unwrap();
+        assert_eq!(reading.user, user);
+        assert!(reading.allows(Scope::Read) && !reading.allows(Scope::Post) && !reading.allows(Scope::Moderate));
+        assert!(!reading.is_browser());
+        // Without the read scope pages are off limits too
+        assert!(session(&auth, TestRequest::get(), &poster).is_none());
+        let posting = session(&auth, TestRequest::post(), &poster).unwrap();
+        assert!(posting.allows(Scope::Post) && !posting.allows(Scope::Moderate));
+        assert!(session(&auth, TestRequest::post(), &moderator).unwrap().allows(Scope::Moderate));
+
+        let (id, _) = reader.split_once('.').unwrap();
+        assert!(session(&auth, TestRequest::get(), &format!("{}.wrong", id)).is_none());
+        assert!(session(&auth, TestRequest::get(), id).is_none());
+    }
+
+    #[test]
+    fn tokens_outlive_restarts_until_revoked() {
+        let _store = scratch_store();
+        let mut auth = Auth::init();
+        let user = UserID("bob".to_string());
+        assert!(matches!(auth.create_api_token(&user, " ", vec![Scope::Read]), Err(ApiTokenError::EmptyName)));
+        assert!(matches!(auth.create_api_token(&user, "bot", vec![]), Err(ApiTokenError::NoScopes)));
+        let token = auth.create_api_token(&user, "bot", vec![Scope::Read, Scope::Post]).unwrap();
+
+        let mut auth = Auth::init();
+        assert_eq!(auth.get_api_token(&token).unwrap().scopes, vec![Scope::Read, Scope::Post]);
+        let id = ApiTokenID(token.split_once('.').unwrap().0.to_string());
+        auth.revoke_api_token(&UserID("mallory".to_string()), &id);
+        assert!(auth.get_api_token(&token).is_some());
+        auth.revoke_api_token(&user, &id);
+        assert!(auth.get_api_token(&token).is_none());
+        assert!(Auth::init().get_api_token(&token).is_none());
+
+        let tokens = ["one", "two"].map(|x| auth.create_api_token(&user, x, vec![Scope::Read]).unwrap());
+        let other = auth.create_api_token(&UserID("carol".to_string()), "bot", vec![Scope::Read]).unwrap();
+        auth.revoke_api_tokens(&user);
+        let auth = Auth::init();
+        assert!(tokens.iter().all(|x| auth.get_api_token(x).is_none()));
+        assert!(auth.api_tokens_for_user(&user).is_empty() && auth.get_api_token(&other).is_some());
+    }
+}
\ No newline at end of file
@@ -0,0 +1,112 @@
+use chrono::Utc;
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use qrcode::{QrCode, render::svg};
+use rand::{RngCore, distributions::{Alphanumeric, DistString}};
+use sha1::Sha1;
+use sha2::{Sha256, Digest};
+
+const ISSUER: &str = "λ network";
+const STEP_SECONDS: i64 = 30;
+const DIGITS: u32 = 6;
+const RECOVERY_CODE_COUNT: usize = 10;
+
+pub fn gen_secret() -> String {
+    let mut bytes = [0u8; 20];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    BASE32_NOPAD.encode(&bytes)
+}
+
+fn code_at(key: &[u8], counter: u64) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_from_slice(key).unwrap();
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+    let offset = (hash[hash.len() - 1] & 0xf) as usize;
+    let truncated = u32::from_be_bytes([hash[offset], hash[offset + 1], hash[offset + 2], hash[offset + 3]]) & 0x7fff_ffff;
+    truncated % 10u32.pow(DIGITS)
+}
+
+/// Accepts codes from the current time step and the ones right before and after it,
+/// so slightly drifting clocks don't lock anyone out
+pub fn verify_code(secret: &str, code: &str) -> bool {
+    let Ok(key) = BASE32_NOPAD.decode(secret.as_bytes()) else {
+        return false;
+    };
+    let Ok(code) = code.trim().replace(' ', "").parse::<u32>() else {
+        return false;
+    };
+    let counter = Utc::now().timestamp() / STEP_SECONDS;
+    (counter - 1..=counter + 1).any(|c| code_at(&key, c as u64) == code)
+}
+
+pub fn otpauth_uri(secret: &str, user_name: &str) -> String {
+    let issuer = ISSUER.replace(' ', "%20");
+    format!("otpauth://totp/{issuer}:{user_name}?secret={secret}&issuer={issuer}&digits={DIGITS}&period={STEP_SECONDS}")
+}
+
+pub fn render_qr_svg(uri: &str) -> String {
+    QrCode::new(uri.as_bytes()).unwrap()
+        .render::<svg::Color>()
+        .min_dimensions(200, 200)
+        .build()
+}
+
+pub fn gen_recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| Alphanumeric.sample_string(&mut rand::thread_rng(), 12).to_lowercase())
+        .collect()
+}
+
+pub fn hash_recovery_code(code: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(code.trim().to_lowercase());
+    format!("{:x}", hasher.finalize())
+}
+
+/// What an authenticator app would show right now
+#[cfg(test)]
+pub fn current_code(secret: &str) -> String {
+    let key = BASE32_NOPAD.decode(secret.as_bytes()).unwrap();
+    format!("{:06}", code_at(&key, (Utc::now().timestamp() / STEP_SECONDS) as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_rfc_6238() {
+        // The SHA-1 test vectors, cut down to six digits
+        let key = b"12345678901234567890";
+        assert_eq!(code_at(key, 59 / 30), 287082);
+        assert_eq!(code_at(key, 1111111109 / 30), 81804);
+        assert_eq!(code_at(key, 1234567890 / 30), 5924);
+    }
+
+    #[test]
+    fn accepts_neighbouring_steps_only() {
+        let secret = gen_secret();
+        let key = BASE32_NOPAD.decode(secret.as_bytes()).unwrap();
+        let counter = (Utc::now().timestamp() / STEP_SECONDS) as u64;
+        let code = |c| format!("{:06}", code_at(&key, c));
+        assert!(verify_code(&secret, &current_code(&secret)));
+        assert!(verify_code(&secret, &code(counter - 1)));
+        let next = code(counter + 1);
+        assert!(verify_code(&secret, &format!(" {} {} ", &next[..3], &next[3..])));
+        // Some other step could happen to share the code, so only check it when it doesn't
+        if ![counter - 1, counter, counter + 1].iter().any(|x| code_at(&key, *x) == code_at(&key, counter - 5)) {
+            assert!(!verify_code(&secret, &code(counter - 5)));
+        }
+        assert!(!verify_code(&secret, "not a code"));
+        assert!(!verify_code("not base32!", &current_code(&secret)));
+    }
+
+    #[test]
+    fn recovery_codes_are_unique_and_forgiving() {
+        let codes = gen_recovery_codes();
+        assert_eq!(codes.len(), RECOVERY_CODE_COUNT);
+        assert!(codes.iter().all(|x| x.len() == 12 && x.chars().all(|x| !x.is_ascii_uppercase())));
+        assert_eq!(codes.iter().map(|x| hash_recovery_code(x)).collect::<std::collections::HashSet<_>>().len(), codes.len());
+        assert_eq!(hash_recovery_code(&codes[0]), hash_recovery_code(&format!("  {} ", codes[0].to_uppercase())));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,113 @@
+use regex::Regex;
+
+use crate::db::DB;
+
+pub const MIN_LENGTH: usize = 3;
+pub const MAX_LENGTH: usize = 24;
+
+/// Names that would be confused with the site itself or its routes
+const RESERVED: &[&str] = &[
+    "admin", "administrator", "overlord", "mod", "moderator", "moderators",
+    "inspection", "settings", "login", "logout", "signup", "search",
+    "auth", "do", "api", "root", "system", "staff", "support",
+    "null", "undefined", "anonymous", "deleted",
+];
+
+#[derive(thiserror::Error, Debug)]
+pub enum UserNameError {
+    #[error("Invalid user name. Only ASCII letters, digits, '_' & '-' are allowed")]
+    InvalidCharacters,
+    #[error("User name must be at least {MIN_LENGTH} characters long")]
+    TooShort,
+    #[error("User name can't be longer than {MAX_LENGTH} characters")]
+    TooLong,
+    #[error("This user name is reserved")]
+    Reserved,
+    #[error("User with such name already exists")]
+    Taken,
+    #[error("There's no user with this name")]
+    NotFound,
+}
+
+/// User names end up as file names in the store, so anything outside of the
+/// anchored pattern (slashes, dots...) must never get through.
+/// Keeping them ASCII also rules out confusable Unicode look-alikes
+pub fn is_well_formed(name: &str) -> bool {
+    Regex::new("^[a-zA-Z0-9_-]+$").unwrap().is_match(name)
+}
+
+/// What uniqueness is decided on, so `Alice` and `alice` can't both exist
+pub fn fold(name: &str) -> String {
+    name.to_ascii_lowercase()
+}
+
+/// Everything but uniqueness
+pub fn validate_user_name_format(name: &str) -> Result<(), UserNameError> {
+    if !is_well_formed(name) {
+        Err(UserNameError::InvalidCharacters)
+    } else if name.len() < MIN_LENGTH {
+        Err(UserNameError::TooShort)
+    } else if name.len() > MAX_LENGTH {
+        Err(UserNameError::TooLong)
+    } else if RESERVED.contains(&fold(name).as_str()) {
+        Err(UserNameError::Reserved)
+    } else {
+        Ok(())
+    }
+}
+
+pub fn validate_new_user_name(name: &str, db: &DB) -> Result<(), UserNameError> {
+    validate_user_name_format(name)?;
+    if db.is_user_name_taken(name, None) {
+        Err(UserNameError::Taken)
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{auth::{Auth, SignupError}, data::UserID, testing::scratch_store};
+    use super::*;
+
+    #[test]
+    fn only_plain_ascii_names_are_well_formed() {
+        for name in ["alice", "Bob_2", "x-y"] {
+            assert!(is_well_formed(name), "{}", name);
+        }
+        for name in ["../x", "a/b", "a\\b", "a.b", "a b", " alice", "alice\n", "ålice", "аlice", ""] {
+            assert!(matches!(validate_user_name_format(name), Err(UserNameError::InvalidCharacters)), "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn names_stay_within_the_limits() {
+        assert!(matches!(validate_user_name_format(&"a".repeat(MIN_LENGTH - 1)), Err(UserNameError::TooShort)));
+        assert!(validate_user_name_format(&"a".repeat(MIN_LENGTH)).is_ok());
+        assert!(validate_user_name_format(&"a".repeat(MAX_LENGTH)).is_ok());
+        assert!(matches!(validate_user_name_format(&"a".repeat(MAX_LENGTH + 1)), Err(UserNameError::TooLong)));
+    }
+
+    #[test]
+    fn reserved_names_are_refused_in_any_case() {
+        for name in ["admin", "Admin", "OVERLORD", "settings", "deleted"] {
+            assert!(matches!(validate_user_name_format(name), Err(UserNameError::Reserved)), "{}", name);
+        }
+    }
+
+    #[test]
+    fn names_are_unique_regardless_of_case() {
+        let _store = scratch_store();
+        let mut db = DB::load();
+        let mut auth = Auth::init();
+        let alice = auth.signup("alice", "correct horse battery", None, "10.0.0.1", &mut db).unwrap();
+        assert!(matches!(
+            auth.signup("Alice", "correct horse battery", None, "10.0.0.2", &mut db),
+            Err(SignupError::InvalidUserName(UserNameError::Taken))
+        ));
+
+        let bob = auth.signup("bob", "correct horse battery", None, "10.0.0.3", &mut db).unwrap();
+        assert!(matches!(db.rename_user(&bob, &UserID("ALICE".to_string())), Err(UserNameError::Taken)));
+        assert!(db.get_user(&alice).is_some() && db.get_user(&bob).is_some());
+    }
+}
\ No newline at end of file
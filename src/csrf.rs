@@ -0,0 +1,169 @@
+use std::{future::{Future, Ready, ready}, pin::Pin, rc::Rc, sync::Mutex, fs::read_to_string};
+
+use actix_web::{dev::{Service, ServiceRequest, ServiceResponse, Transform, Payload, forward_ready}, body::EitherBody, cookie::{Cookie, SameSite}, http::{Method, StatusCode}, web::{Bytes, Data}, Error, FromRequest, HttpMessage, HttpRequest, HttpResponse};
+use rand::distributions::{Alphanumeric, DistString};
+
+use crate::{db::DB, render::render_page, auth::bearer_token};
+
+const COOKIE_NAME: &str = "csrf-token";
+const FIELD_NAME: &str = "csrf";
+
+/// Double-submit token, every form carries it in a hidden `csrf` field
+/// and it has to match the `csrf-token` cookie for the request to go through
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsrfToken(pub String);
+
+fn gen_token() -> CsrfToken {
+    CsrfToken(Alphanumeric.sample_string(&mut rand::thread_rng(), 32))
+}
+
+fn build_csrf_cookie(token: &CsrfToken) -> Cookie<'_> {
+    Cookie::build(COOKIE_NAME, token.0.as_str())
+        .path("/")
+        .same_site(SameSite::Strict)
+        .http_only(true)
+        .finish()
+}
+
+fn render_rejection(req: &HttpRequest, token: &CsrfToken) -> HttpResponse {
+    let db = req.app_data::<Data<Mutex<DB>>>().unwrap().lock().unwrap();
+    let mut response = render_page(&db, None, token, || {
+        read_to_string("assets/page/csrf-error.html").unwrap()
+    });
+    *response.status_mut() = StatusCode::FORBIDDEN;
+    response
+}
+
+/// Rejects any state-changing request without a valid CSRF token,
+/// and hands out the token cookie to browsers that don't have one yet
+pub struct CsrfProtection;
+
+impl<S, B> Transform<S, ServiceRequest> for CsrfProtection
+    where S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static, B: 'static {
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = CsrfMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(CsrfMiddleware { service: Rc::new(service) }))
+    }
+}
+
+pub struct CsrfMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for CsrfMiddleware<S>
+    where S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static, B: 'static {
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        Box::pin(async move {
+            let cookie_token = req.cookie(COOKIE_NAME).map(|c| CsrfToken(c.value().to_string()));
+            let token = cookie_token.clone().unwrap_or_else(gen_token);
+            req.extensions_mut().insert(token.clone());
+            // Browsers never attach bearer tokens on their own, so those requests can't be forged
+            let is_safe = matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS)
+                || bearer_token(req.request()).is_some();
+            if !is_safe {
+                let body = req.extract::<Bytes>().await?;
+                let form_token = serde_urlencoded::from_bytes::<Vec<(String, String)>>(&body).ok()
+                    .and_then(|fields| fields.into_iter().find(|(k, _)| k == FIELD_NAME))
+                    .map(|(_, v)| CsrfToken(v));
+                if cookie_token.is_none() || form_token != cookie_token {
+                    let mut response = render_rejection(req.request(), &token);
+                    let _ = response.add_cookie(&build_csrf_cookie(&token));
+                    return Ok(req.into_response(response).map_into_right_body());
+                }
+                let (_, mut payload) = actix_http::h1::Payload::create(true);
+                payload.unread_data(body);
+                req.set_payload(payload.into());
+            }
+            let mut response = service.call(req).await?;
+            if cookie_token.is_none() {
+                let _ = response.response_mut().add_cookie(&build_csrf_cookie(&token));
+            }
+            Ok(response.map_into_left_body())
+        })
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum CsrfRequestError {
+    #[error("No CSRF token, is the middleware missing?")]
+    NoToken,
+}
+
+impl actix_web::ResponseError for CsrfRequestError {}
+
+impl FromRequest for CsrfToken {
+    type Error = CsrfRequestError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(req.extensions().get::<CsrfToken>().cloned().ok_or(CsrfRequestError::NoToken))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{App, test, web, http::header::{AUTHORIZATION, ContentType}};
+
+    use super::*;
+
+    async fn echo(body: String) -> HttpResponse {
+        HttpResponse::Ok().body(body)
+    }
+
+    fn post(token: Option<&str>, field: Option<&str>) -> test::TestRequest {
+        let mut req = test::TestRequest::post().uri("/do")
+            .insert_header(ContentType::form_url_encoded())
+            .set_payload(field.map_or_else(|| "content=hi".to_string(), |x| format!("{}={}&content=hi", FIELD_NAME, x)));
+        if let Some(token) = token {
+            req = req.cookie(Cookie::new(COOKIE_NAME, token));
+        }
+        req
+    }
+
+    #[actix_web::test]
+    async fn rejects_forged_posts() {
+        let app = test::init_service(App::new()
+            .app_data(Data::new(Mutex::new(DB::default())))
+            .wrap(CsrfProtection)
+            .route("/do", web::post().to(echo))).await;
+
+        for (token, field) in [(None, None), (None, Some("abc")), (Some("abc"), None), (Some("abc"), Some("xyz"))] {
+            let response = test::call_service(&app, post(token, field).to_request()).await;
+            assert_eq!(response.status(), StatusCode::FORBIDDEN, "cookie {:?}, field {:?}", token, field);
+        }
+
+        let response = test::call_service(&app, post(Some("abc"), Some("abc")).to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        // The handler still gets to read the form after the middleware did
+        assert_eq!(test::read_body(response).await, Bytes::from("csrf=abc&content=hi"));
+
+        let response = test::call_service(&app, post(None, None).insert_header((AUTHORIZATION, "Bearer token")).to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn hands_out_a_token_once() {
+        let app = test::init_service(App::new()
+            .app_data(Data::new(Mutex::new(DB::default())))
+            .wrap(CsrfProtection)
+            .route("/", web::get().to(|| async { HttpResponse::Ok().finish() }))).await;
+
+        let response = test::call_service(&app, test::TestRequest::get().uri("/").to_request()).await;
+        let cookie = response.response().cookies().find(|x| x.name() == COOKIE_NAME).unwrap();
+        assert_eq!(cookie.value().len(), 32);
+        let response = test::call_service(&app, test::TestRequest::get().uri("/").cookie(cookie.into_owned()).to_request()).await;
+        assert!(response.response().cookies().all(|x| x.name() != COOKIE_NAME));
+    }
+}
\ No newline at end of file
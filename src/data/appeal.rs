@@ -0,0 +1,16 @@
+use chrono::{DateTime, Utc};
+
+/// The author's side of the story on something that was moderated
+pub struct Appeal {
+    pub statement: String,
+    pub created: DateTime<Utc>,
+    /// What the moderator said when turning it down, the item stays in inspection then
+    pub denied: Option<String>,
+}
+
+/// Tells users what happened to their content, they can't look at inspection themselves
+pub struct Notice {
+    pub created: DateTime<Utc>,
+    pub text: String,
+    pub seen: bool,
+}
\ No newline at end of file
@@ -0,0 +1,88 @@
+use chrono::{DateTime, Duration, Utc};
+use regex::Regex;
+
+use super::{UserID, TopicID, ThreadID};
+
+/// Posts with fewer letters than this are never too loud
+const MIN_CAPS_LETTERS: usize = 10;
+
+pub enum Condition {
+    /// Anywhere in the title or the content
+    Regex(Regex),
+    /// Any of these as a whole word, case doesn't matter
+    Words(Vec<String>),
+    /// More web addresses than this
+    Links(usize),
+    /// At least this share of the letters is upper case
+    Caps(f64),
+    /// Accounts younger than this, the ones from before sign up dates were kept never are
+    AccountAge(Duration),
+}
+
+impl Condition {
+    pub fn matches(&self, text: &str, joined: Option<DateTime<Utc>>) -> bool {
+        match self {
+            Condition::Regex(regex) => regex.is_match(text),
+            Condition::Words(words) => text.to_lowercase()
+                .split(|x: char| !x.is_alphanumeric())
+                .any(|x| words.iter().any(|word| word == x)),
+            Condition::Links(max) => text.matches("http://").count() + text.matches("https://").count() > *max,
+            Condition::Caps(ratio) => {
+                let letters = text.chars().filter(|x| x.is_alphabetic()).count();
+                let upper = text.chars().filter(|x| x.is_uppercase()).count();
+                letters >= MIN_CAPS_LETTERS && upper as f64 >= letters as f64 * ratio
+            },
+            Condition::AccountAge(age) => joined.is_some_and(|x| Utc::now() - x < *age),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Condition::Regex(regex) => format!("matches /{}/", regex.as_str()),
+            Condition::Words(words) => format!("says any of {}", words.join(", ")),
+            Condition::Links(max) => format!("more than {} links", max),
+            Condition::Caps(ratio) => format!("at least {:.0}% upper case", ratio * 100.0),
+            Condition::AccountAge(age) => format!("account younger than {} hours", age.num_hours()),
+        }
+    }
+}
+
+/// Ordered from least to most strict, the strictest one of all matching rules wins
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AutomodAction {
+    Tag(String),
+    Hold,
+    /// With what to tell the author
+    Reject(String),
+}
+
+impl AutomodAction {
+    pub fn describe(&self) -> String {
+        match self {
+            AutomodAction::Tag(tag) => format!("tag as {}", tag),
+            AutomodAction::Hold => "hold for review".to_string(),
+            AutomodAction::Reject(message) => format!("reject with \"{}\"", message),
+        }
+    }
+}
+
+pub struct AutomodRule {
+    pub name: String,
+    /// Applies everywhere when missing
+    pub topic: Option<TopicID>,
+    /// All of them have to match
+    pub conditions: Vec<Condition>,
+    pub action: AutomodAction,
+}
+
+/// Kept around to see which rules catch too much or too little
+pub struct AutomodHit {
+    pub created: DateTime<Utc>,
+    pub rule: String,
+    pub user: UserID,
+    pub topic: TopicID,
+    /// Missing for new threads
+    pub thread: Option<ThreadID>,
+    pub action: String,
+    pub excerpt: String,
+}
\ No newline at end of file
@@ -0,0 +1,9 @@
+use chrono::{DateTime, Utc};
+
+use super::UserID;
+
+pub struct Invite {
+    pub inviter: UserID,
+    pub created: DateTime<Utc>,
+    pub used_by: Option<UserID>,
+}
\ No newline at end of file
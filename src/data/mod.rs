@@ -1,12 +1,28 @@
 
+mod appeal;
+mod automod;
+mod invite;
 mod moderation;
+mod note;
+mod record;
 mod reply;
+mod report;
+mod role;
+mod settings;
 mod thread;
 mod topic;
 mod user;
 
+pub use appeal::*;
+pub use automod::*;
+pub use invite::*;
 pub use moderation::*;
+pub use note::*;
+pub use record::*;
 pub use reply::*;
+pub use report::*;
+pub use role::*;
+pub use settings::*;
 pub use thread::*;
 pub use topic::*;
 pub use user::*;
@@ -24,4 +40,10 @@ pub struct ThreadID(pub String);
 pub struct ReplyID(pub String);
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub struct ModItemID(pub String);
\ No newline at end of file
+pub struct ModItemID(pub String);
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct InviteID(pub String);
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RoleID(pub String);
\ No newline at end of file
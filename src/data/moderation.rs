@@ -1,15 +1,111 @@
+use std::collections::HashMap;
+
 use chrono::{DateTime, Utc};
 
-use super::{TopicID, ThreadID, Reply, Topic, User, Thread};
+use super::{TopicID, ThreadID, ReplyID, UserID, Reply, Topic, User, Thread, Appeal};
 
 pub struct ModItem {
     pub moderated: DateTime<Utc>,
+    /// Whose content this is and how their profile looked back then,
+    /// nobody's for topics that never had an owner
+    pub author: Option<(UserID, User)>,
     pub thing: Moderatable,
+    /// Why it's here, if anyone said
+    pub reason: String,
+    /// Authors get one each
+    pub appeal: Option<Appeal>,
+}
+
+impl ModItem {
+    pub fn author_id(&self) -> Option<&UserID> {
+        self.author.as_ref().map(|(id, _)| id)
+    }
 }
 
 pub enum Moderatable {
-    User(User),
-    Topic(User, Topic),
-    Thread(User, Thread, TopicID),
-    Reply(User, Reply, ThreadID),
+    /// The profile itself is the author snapshot
+    User,
+    /// Along with its threads and all of their replies
+    Topic(TopicID, Topic, HashMap<ThreadID, Thread>, HashMap<ReplyID, Reply>),
+    /// Along with where in the topic it was
+    Thread(ThreadID, Thread, HashMap<ReplyID, Reply>, TopicID, usize),
+    /// Along with where in the thread it was
+    Reply(ReplyID, Reply, ThreadID, usize),
+}
+
+impl Moderatable {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Moderatable::User => "user",
+            Moderatable::Topic(_, _, _, _) => "topic",
+            Moderatable::Thread(_, _, _, _, _) => "thread",
+            Moderatable::Reply(_, _, _, _) => "reply",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BanKind {
+    /// Can't log in at all
+    Ban,
+    /// Can still log in and read, just not post
+    Suspension,
+    /// Can post as usual, but nobody except moderators sees any of it
+    Shadow,
+}
+
+impl BanKind {
+    pub const ALL: [BanKind; 3] = [BanKind::Ban, BanKind::Suspension, BanKind::Shadow];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BanKind::Ban => "ban",
+            BanKind::Suspension => "suspension",
+            BanKind::Shadow => "shadow",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            BanKind::Ban => "Ban",
+            BanKind::Suspension => "Suspend from posting",
+            BanKind::Shadow => "Shadow-ban",
+        }
+    }
+
+    pub fn parse(kind: &str) -> Option<BanKind> {
+        BanKind::ALL.into_iter().find(|x| x.as_str() == kind)
+    }
+}
+
+pub struct Ban {
+    pub moderator: UserID,
+    pub issued: DateTime<Utc>,
+    /// Permanent when missing
+    pub until: Option<DateTime<Utc>>,
+    pub reason: String,
+    pub kind: BanKind,
+}
+
+impl Ban {
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.until.is_none_or(|until| until > now)
+    }
+
+    /// What the affected user gets told, without who issued it
+    pub fn describe(&self) -> String {
+        let what = match self.kind {
+            BanKind::Ban => "banned",
+            BanKind::Suspension => "suspended from posting",
+            BanKind::Shadow => "shadow-banned",
+        };
+        let when = match self.until {
+            Some(until) => format!("until {}", until.format("%Y-%m-%d %H:%M UTC")),
+            None => "permanently".to_string(),
+        };
+        match self.reason.as_str() {
+            "" => format!("{} {}", what, when),
+            reason => format!("{} {}: {}", what, when, reason),
+        }
+    }
 }
\ No newline at end of file
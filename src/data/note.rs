@@ -0,0 +1,32 @@
+use chrono::{DateTime, Utc};
+
+use super::{UserID, Reported};
+
+/// What a moderator note is about
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum NoteSubject {
+    User(UserID),
+    Post(Reported),
+}
+
+pub struct NoteRevision {
+    pub author: UserID,
+    pub created: DateTime<Utc>,
+    pub content: String,
+}
+
+/// Only moderators get to see these. Editing adds a revision instead of replacing the text
+pub struct ModNote {
+    /// Oldest first, never empty
+    pub revisions: Vec<NoteRevision>,
+}
+
+impl ModNote {
+    pub fn current(&self) -> &NoteRevision {
+        self.revisions.last().unwrap()
+    }
+
+    pub fn original(&self) -> &NoteRevision {
+        self.revisions.first().unwrap()
+    }
+}
\ No newline at end of file
@@ -0,0 +1,162 @@
+use chrono::{DateTime, NaiveDate, Utc};
+
+use super::{UserID, TopicID};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModAction {
+    MoveToInspection,
+    Restore,
+    Purge,
+    DeleteReply,
+    DismissReports,
+    Ban,
+    LiftBan,
+    Grant,
+    Revoke,
+    Lock,
+    Unlock,
+    Pin,
+    Unpin,
+    MoveThread,
+    Approve,
+    Reject,
+    CreateTopic,
+    EditTopic,
+    RenameTopic,
+    Archive,
+    Unarchive,
+    DeleteTopic,
+    ForceLogout,
+    ResetPassword,
+    PurgeContent,
+    ShadowBan,
+    LiftShadowBan,
+    AcceptAppeal,
+    DenyAppeal,
+}
+
+impl ModAction {
+    pub const ALL: [ModAction; 29] = [
+        ModAction::MoveToInspection, ModAction::Restore, ModAction::Purge, ModAction::DeleteReply, ModAction::DismissReports,
+        ModAction::Ban, ModAction::LiftBan, ModAction::Grant, ModAction::Revoke,
+        ModAction::Lock, ModAction::Unlock, ModAction::Pin, ModAction::Unpin, ModAction::MoveThread,
+        ModAction::Approve, ModAction::Reject,
+        ModAction::CreateTopic, ModAction::EditTopic, ModAction::RenameTopic, ModAction::Archive, ModAction::Unarchive, ModAction::DeleteTopic,
+        ModAction::ForceLogout, ModAction::ResetPassword, ModAction::PurgeContent,
+        ModAction::ShadowBan, ModAction::LiftShadowBan, ModAction::AcceptAppeal, ModAction::DenyAppeal,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ModAction::MoveToInspection => "inspect",
+            ModAction::Restore => "restore",
+            ModAction::Purge => "purge",
+            ModAction::DeleteReply => "delete-reply",
+            ModAction::DismissReports => "dismiss-reports",
+            ModAction::Ban => "ban",
+            ModAction::LiftBan => "lift-ban",
+            ModAction::Grant => "grant",
+            ModAction::Revoke => "revoke",
+            ModAction::Lock => "lock",
+            ModAction::Unlock => "unlock",
+            ModAction::Pin => "pin",
+            ModAction::Unpin => "unpin",
+            ModAction::MoveThread => "move-thread",
+            ModAction::Approve => "approve",
+            ModAction::Reject => "reject",
+            ModAction::CreateTopic => "create-topic",
+            ModAction::EditTopic => "edit-topic",
+            ModAction::RenameTopic => "rename-topic",
+            ModAction::Archive => "archive",
+            ModAction::Unarchive => "unarchive",
+            ModAction::DeleteTopic => "delete-topic",
+            ModAction::ForceLogout => "force-logout",
+            ModAction::ResetPassword => "reset-password",
+            ModAction::PurgeContent => "purge-content",
+            ModAction::ShadowBan => "shadow-ban",
+            ModAction::LiftShadowBan => "lift-shadow-ban",
+            ModAction::AcceptAppeal => "accept-appeal",
+            ModAction::DenyAppeal => "deny-appeal",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ModAction::MoveToInspection => "Moved to inspection",
+            ModAction::Restore => "Restored",
+            ModAction::Purge => "Deleted for good",
+            ModAction::DeleteReply => "Deleted reply",
+            ModAction::DismissReports => "Dismissed reports",
+            ModAction::Ban => "Banned",
+            ModAction::LiftBan => "Lifted ban",
+            ModAction::Grant => "Granted role",
+            ModAction::Revoke => "Revoked role",
+            ModAction::Lock => "Locked",
+            ModAction::Unlock => "Unlocked",
+            ModAction::Pin => "Pinned",
+            ModAction::Unpin => "Unpinned",
+            ModAction::MoveThread => "Moved",
+            ModAction::Approve => "Approved",
+            ModAction::Reject => "Rejected",
+            ModAction::CreateTopic => "Created",
+            ModAction::EditTopic => "Edited",
+            ModAction::RenameTopic => "Renamed",
+            ModAction::Archive => "Archived",
+            ModAction::Unarchive => "Unarchived",
+            ModAction::DeleteTopic => "Deleted for good",
+            ModAction::ForceLogout => "Logged out",
+            ModAction::ResetPassword => "Reset password of",
+            ModAction::PurgeContent => "Purged content of",
+            ModAction::ShadowBan => "Shadow-banned",
+            ModAction::LiftShadowBan => "Lifted shadow-ban of",
+            ModAction::AcceptAppeal => "Accepted appeal for",
+            ModAction::DenyAppeal => "Turned down appeal for",
+        }
+    }
+
+    /// Shadow-bans only work while the user doesn't know, so they stay out of the transparency log
+    pub fn is_public(&self) -> bool {
+        !matches!(self, ModAction::ShadowBan | ModAction::LiftShadowBan)
+    }
+
+    pub fn parse(action: &str) -> Option<ModAction> {
+        ModAction::ALL.into_iter().find(|x| x.as_str() == action)
+    }
+}
+
+/// One moderation action, kept forever
+pub struct ModRecord {
+    pub created: DateTime<Utc>,
+    pub actor: UserID,
+    pub action: ModAction,
+    /// Whose content or account it was, if anyone's
+    pub user: Option<UserID>,
+    pub topic: Option<TopicID>,
+    /// What it was done to, in words
+    pub target: String,
+    /// Everything from here on is private, only the full log shows it
+    pub reason: String,
+    pub before: String,
+    pub after: String,
+}
+
+/// All given conditions have to match, dates are inclusive
+#[derive(Default)]
+pub struct RecordFilter {
+    pub actor: Option<UserID>,
+    pub user: Option<UserID>,
+    pub topic: Option<TopicID>,
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+}
+
+impl RecordFilter {
+    pub fn matches(&self, record: &ModRecord) -> bool {
+        let day = record.created.date_naive();
+        self.actor.as_ref().is_none_or(|x| x == &record.actor)
+            && self.user.as_ref().is_none_or(|x| record.user.as_ref() == Some(x))
+            && self.topic.as_ref().is_none_or(|x| record.topic.as_ref() == Some(x))
+            && self.from.is_none_or(|x| day >= x)
+            && self.to.is_none_or(|x| day <= x)
+    }
+}
\ No newline at end of file
@@ -6,4 +6,8 @@ pub struct Reply {
     pub created: DateTime<Utc>,
     pub user: UserID,
     pub content: String,
+    /// Put on by automod, only moderators get to see them
+    pub tags: Vec<String>,
+    /// Posted while shadow-banned, only the author and moderators get to see it
+    pub shadowed: bool,
 }
\ No newline at end of file
@@ -0,0 +1,71 @@
+use chrono::{DateTime, Utc};
+
+use super::{ThreadID, ReplyID, UserID};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportReason {
+    Spam,
+    Harassment,
+    OffTopic,
+    Illegal,
+    Other,
+}
+
+impl ReportReason {
+    pub const ALL: [ReportReason; 5] = [
+        ReportReason::Spam, ReportReason::Harassment, ReportReason::OffTopic, ReportReason::Illegal, ReportReason::Other,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReportReason::Spam => "spam",
+            ReportReason::Harassment => "harassment",
+            ReportReason::OffTopic => "off-topic",
+            ReportReason::Illegal => "illegal",
+            ReportReason::Other => "other",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ReportReason::Spam => "Spam",
+            ReportReason::Harassment => "Harassment",
+            ReportReason::OffTopic => "Off-topic",
+            ReportReason::Illegal => "Illegal content",
+            ReportReason::Other => "Something else",
+        }
+    }
+
+    pub fn parse(reason: &str) -> Option<ReportReason> {
+        ReportReason::ALL.into_iter().find(|x| x.as_str() == reason)
+    }
+}
+
+/// What got reported, replies are kept along with their thread so they can be found again
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Reported {
+    Thread(ThreadID),
+    Reply(ThreadID, ReplyID),
+}
+
+impl Reported {
+    pub fn thread(&self) -> &ThreadID {
+        match self {
+            Reported::Thread(thread) | Reported::Reply(thread, _) => thread,
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Reported::Thread(_) => "thread",
+            Reported::Reply(_, _) => "reply",
+        }
+    }
+}
+
+pub struct Report {
+    pub reporter: UserID,
+    pub reason: ReportReason,
+    pub note: String,
+    pub created: DateTime<Utc>,
+}
\ No newline at end of file
@@ -0,0 +1,95 @@
+use std::collections::{HashMap, HashSet};
+
+use super::{RoleID, TopicID};
+
+/// Something a role lets its holders do, checked by `DB::can`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Capability {
+    Post,
+    CreateThread,
+    Upload,
+    /// Inspecting replies and threads, editing the description
+    ModerateTopic,
+    /// Their reports count towards hiding things before anyone looked at them
+    TrustedReports,
+    /// Letting the first posts of new users through, their own never have to wait
+    ApprovePosts,
+    /// Profiles, bans, lockouts, invites and renames
+    ManageUsers,
+    ManageTopics,
+    ManageSite,
+}
+
+impl Capability {
+    pub const ALL: [Capability; 9] = [
+        Capability::Post, Capability::CreateThread, Capability::Upload, Capability::TrustedReports, Capability::ApprovePosts, Capability::ModerateTopic,
+        Capability::ManageUsers, Capability::ManageTopics, Capability::ManageSite,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Capability::Post => "post",
+            Capability::CreateThread => "create-thread",
+            Capability::Upload => "upload",
+            Capability::TrustedReports => "trusted-reports",
+            Capability::ApprovePosts => "approve-posts",
+            Capability::ModerateTopic => "moderate-topic",
+            Capability::ManageUsers => "manage-users",
+            Capability::ManageTopics => "manage-topics",
+            Capability::ManageSite => "manage-site",
+        }
+    }
+
+    pub fn parse(capability: &str) -> Option<Capability> {
+        Capability::ALL.into_iter().find(|x| x.as_str() == capability)
+    }
+
+    /// Holding any of these makes someone a moderator, with everything that comes with it like mandatory 2FA
+    pub fn is_moderation(&self) -> bool {
+        !matches!(self, Capability::Post | Capability::CreateThread | Capability::Upload | Capability::TrustedReports | Capability::ApprovePosts)
+    }
+}
+
+/// A role someone holds, either everywhere or only inside of one topic
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoleGrant {
+    pub role: RoleID,
+    pub topic: Option<TopicID>,
+}
+
+/// Which capabilities each role comes with, read from `store/mod/roles.json`
+pub struct Roles {
+    /// What everyone without a site-wide role gets
+    pub default: RoleID,
+    pub roles: HashMap<RoleID, HashSet<Capability>>,
+}
+
+impl Roles {
+    /// Can do anything no matter what the configuration says
+    pub const OVERLORD: &'static str = "overlord";
+    /// Granted in the topics people create
+    pub const OWNER: &'static str = "owner";
+
+    pub fn allows(&self, role: &RoleID, capability: Capability) -> bool {
+        role.0 == Roles::OVERLORD || self.roles.get(role).is_some_and(|x| x.contains(&capability))
+    }
+}
+
+impl Default for Roles {
+    fn default() -> Self {
+        use Capability::*;
+        let roles = [
+            ("new-user", vec![Post, CreateThread]),
+            ("trusted", vec![Post, CreateThread, Upload, TrustedReports, ApprovePosts]),
+            ("bot", vec![Post]),
+            (Roles::OWNER, vec![Post, CreateThread, Upload, TrustedReports, ApprovePosts, ModerateTopic]),
+            ("moderator", vec![Post, CreateThread, Upload, TrustedReports, ApprovePosts, ModerateTopic, ManageUsers]),
+        ];
+        Self {
+            default: RoleID("new-user".to_string()),
+            roles: roles.into_iter()
+                .map(|(role, capabilities)| (RoleID(role.to_string()), capabilities.into_iter().collect()))
+                .collect(),
+        }
+    }
+}
\ No newline at end of file
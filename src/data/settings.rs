@@ -0,0 +1,46 @@
+/// Site-wide options, changed by overlords from the admin settings page
+pub struct Settings {
+    pub require_mod_2fa: bool,
+    pub registration_mode: RegistrationMode,
+    /// How many invite codes each user may create, overlords have no limit
+    pub invite_quota: usize,
+    /// How many trusted people have to report something before it's hidden, 0 never hides anything
+    pub report_threshold: usize,
+    /// How many posts of each user have to be approved before they go up right away, 0 approves everything
+    pub approval_posts: usize,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            require_mod_2fa: false,
+            registration_mode: RegistrationMode::Open,
+            invite_quota: 5,
+            report_threshold: 3,
+            approval_posts: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegistrationMode {
+    Open,
+    InviteOnly,
+    Closed,
+}
+
+impl RegistrationMode {
+    pub const ALL: [RegistrationMode; 3] = [RegistrationMode::Open, RegistrationMode::InviteOnly, RegistrationMode::Closed];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RegistrationMode::Open => "open",
+            RegistrationMode::InviteOnly => "invite-only",
+            RegistrationMode::Closed => "closed",
+        }
+    }
+
+    pub fn parse(mode: &str) -> Option<RegistrationMode> {
+        RegistrationMode::ALL.into_iter().find(|x| x.as_str() == mode)
+    }
+}
\ No newline at end of file
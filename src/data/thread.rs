@@ -3,4 +3,8 @@ use super::ReplyID;
 pub struct Thread {
     pub title: String,
     pub replies: Vec<ReplyID>,
+    /// Nobody gets to reply anymore
+    pub locked: bool,
+    /// Stays on top of its topic
+    pub pinned: bool,
 }
\ No newline at end of file
@@ -3,6 +3,10 @@ use super::ThreadID;
 pub struct Topic {
     pub about: String,
     pub threads: Vec<ThreadID>,
+    /// Shown along with the name, as `#rrggbb`
+    pub color: Option<String>,
+    /// Still readable, but nobody can post in it anymore
+    pub archived: bool,
 }
 
 impl Default for Topic {
@@ -10,6 +14,8 @@ impl Default for Topic {
         Self {
             about: Default::default(),
             threads: vec![],
+            color: None,
+            archived: false,
         }
     }
 }
\ No newline at end of file
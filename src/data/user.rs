@@ -1,19 +1,40 @@
-use super::{TopicID, ThreadID};
+use chrono::{DateTime, Utc};
 
+use super::{TopicID, ThreadID, UserID};
+
+#[derive(Clone)]
 pub struct User {
+    /// Shown instead of the handle, which stays the same
+    pub display_name: Option<String>,
     pub about: String,
     pub pronouns: Option<[String; 3]>,
     pub fav_topics: Vec<TopicID>,
     pub fav_threads: Vec<ThreadID>,
+    /// Their replies get collapsed and their threads left out of listings
+    pub blocked: Vec<UserID>,
+    /// Left out of listings that span several topics
+    pub muted_topics: Vec<TopicID>,
+    /// Missing for accounts made before this was kept track of
+    pub joined: Option<DateTime<Utc>>,
 }
 
 impl Default for User {
     fn default() -> Self {
         Self {
+            display_name: None,
             about: Default::default(),
             pronouns: None,
             fav_topics: vec![],
             fav_threads: vec![],
+            blocked: vec![],
+            muted_topics: vec![],
+            joined: None,
         }
     }
+}
+
+impl User {
+    pub fn get_display_name<'a>(&'a self, id: &'a UserID) -> &'a str {
+        self.display_name.as_deref().unwrap_or(id.0.as_str())
+    }
 }
\ No newline at end of file
@@ -0,0 +1,173 @@
+use std::{cmp::Reverse, collections::HashSet};
+
+use chrono::Utc;
+
+use crate::data::{UserID, ThreadID, ReplyID, RoleID, RoleGrant, Roles, Reported, Report, Capability, ModRecord, ModAction};
+
+use super::{DB, store, permissions::Target};
+
+#[derive(thiserror::Error, Debug)]
+pub enum AccountError {
+    #[error("There's no role with this name")]
+    UnknownRole,
+    #[error("There's no topic with this name")]
+    UnknownTopic,
+    #[error("They don't have this role")]
+    NotGranted,
+    #[error("Type their user name to confirm purging their content")]
+    NotConfirmed,
+}
+
+impl DB {
+    /// Moderators may handle anyone but admins, those are only up to other admins
+    pub fn can_manage_account(&self, actor: &UserID, user: &UserID) -> bool {
+        self.can(actor, Capability::ManageUsers, Target::Site) && (!self.is_admin(user) || self.is_admin(actor))
+    }
+
+    pub fn count_replies_by(&self, user: &UserID) -> usize {
+        self.replies.values().filter(|x| &x.user == user).count()
+    }
+
+    pub fn get_grants(&self, user: &UserID) -> &[RoleGrant] {
+        self.permissions.get(user).map_or(&[], |x| x.as_slice())
+    }
+
+    /// Everything that can be granted, overlord included
+    pub fn get_role_ids(&self) -> Vec<&str> {
+        let mut roles = self.roles.roles.keys().map(|x| x.0.as_str()).collect::<Vec<_>>();
+        roles.sort_unstable();
+        roles.insert(0, Roles::OVERLORD);
+        roles
+    }
+
+    pub fn is_role(&self, role: &RoleID) -> bool {
+        role.0 == Roles::OVERLORD || self.roles.roles.contains_key(role)
+    }
+
+    /// Admin forms send free text, so make sure a grant points at things that exist
+    pub fn check_grant(&self, grant: &RoleGrant) -> Result<(), AccountError> {
+        if !self.is_role(&grant.role) {
+            return Err(AccountError::UnknownRole);
+        }
+        if grant.topic.as_ref().is_some_and(|x| !self.topics.contains_key(x)) {
+            return Err(AccountError::UnknownTopic);
+        }
+        Ok(())
+    }
+
+    /// Reports the user filed, newest first
+    pub fn get_reports_by(&self, user: &UserID) -> Vec<(&Reported, &Report)> {
+        let mut reports = self.reports.iter()
+            .flat_map(|(reported, reports)| reports.iter().map(move |x| (reported, x)))
+            .filter(|(_, x)| &x.reporter == user)
+            .collect::<Vec<_>>();
+        reports.sort_unstable_by_key(|(_, x)| Reverse(x.created));
+        reports
+    }
+
+    /// Reports about what the user posted, a thread counts as theirs if they started it
+    pub fn get_reports_against(&self, user: &UserID) -> Vec<(&Reported, &Vec<Report>)> {
+        self.get_sorted_reports().into_iter()
+            .filter(|(reported, _)| {
+                let reply = match reported {
+                    Reported::Thread(thread) => self.threads.get(thread).and_then(|x| x.replies.first()),
+                    Reported::Reply(_, reply) => Some(reply),
+                };
+                reply.and_then(|x| self.replies.get(x)).is_some_and(|x| &x.user == user)
+            })
+            .collect()
+    }
+
+    /// Deletes every reply the user ever posted along with anything still waiting for approval.
+    /// Threads left without any replies go too, returns how many replies were deleted
+    pub fn purge_user_content(&mut self, user: &UserID, actor: &UserID, reason: String) -> usize {
+        let targets = self.threads.iter()
+            .flat_map(|(thread_id, thread)| thread.replies.iter().map(move |x| (thread_id, x)))
+            .filter(|(_, x)| self.replies.get(*x).is_some_and(|x| &x.user == user))
+            .map(|(thread, reply)| (thread.clone(), reply.clone()))
+            .collect::<Vec<(ThreadID, ReplyID)>>();
+        let mut emptied = HashSet::new();
+        for (thread_id, reply_id) in &targets {
+            self.delete_reply(thread_id, reply_id);
+            if self.threads.get(thread_id).is_some_and(|x| x.replies.is_empty()) {
+                emptied.insert(thread_id.clone());
+            }
+        }
+        for thread_id in emptied {
+            if let Some(topic_id) = self.get_thread_topic(&thread_id).cloned() {
+                self.delete_thread(&topic_id, &thread_id);
+            }
+        }
+        let pending = self.pending.iter()
+            .filter(|(_, x)| x.author_id() == Some(user))
+            .map(|(id, _)| id.clone())
+            .collect::<Vec<_>>();
+        for id in &pending {
+            self.pending.remove(id);
+            store::delete_pending_item(id);
+        }
+        self.record_account_action(ModAction::PurgeContent, user, actor, reason,
+            format!("{} replies, {} waiting for approval", targets.len(), pending.len()));
+        targets.len()
+    }
+
+    /// For what happens to an account outside of the database, like ending its sessions
+    pub fn record_account_action(&mut self, action: ModAction, user: &UserID, actor: &UserID, reason: String, before: String) {
+        self.record(ModRecord {
+            created: Utc::now(),
+            actor: actor.clone(),
+            action,
+            user: Some(user.clone()),
+            topic: None,
+            target: format!("@{}", user.0),
+            reason,
+            before,
+            after: String::new(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{data::ReportReason, testing::scratch_store};
+    use super::*;
+
+    fn grant(db: &mut DB, user: &UserID, role: &str) {
+        db.grant_permission(user, RoleGrant { role: RoleID(role.to_string()), topic: None }, user);
+    }
+
+    #[test]
+    fn only_admins_manage_admins() {
+        let _store = scratch_store();
+        std::fs::create_dir_all(store::MOD_PATH).unwrap();
+        std::fs::write(store::MOD_PATH.to_string() + "/roles.json", r#"{ "roles": { "staff": ["manage-users"] } }"#).unwrap();
+        let mut db = DB::load();
+        let [alice, bob, carol, dave] = ["alice", "bob", "carol", "dave"].map(|x| db.create_new_user(x, None));
+        grant(&mut db, &alice, Roles::OVERLORD);
+        grant(&mut db, &bob, Roles::OVERLORD);
+        grant(&mut db, &carol, "staff");
+        assert!(db.can_manage_account(&carol, &dave) && !db.can_manage_account(&carol, &alice));
+        assert!(db.can_manage_account(&alice, &bob) && db.can_manage_account(&alice, &carol));
+        assert!(!db.can_manage_account(&dave, &carol));
+    }
+
+    #[test]
+    fn purging_takes_every_reply_and_emptied_thread() {
+        let _store = scratch_store();
+        let mut db = DB::load();
+        let [alice, bob] = ["alice", "bob"].map(|x| db.create_new_user(x, None));
+        let topic = db.create_new_topic(&alice, "cats", &alice).unwrap();
+        let shared = db.create_new_thread(&topic, "Shared".to_string(), &alice).unwrap();
+        db.try_reply("Hi", &shared, &alice, vec![]).unwrap();
+        db.try_reply("Hey", &shared, &bob, vec![]).unwrap();
+        let own = db.create_new_thread(&topic, "Own".to_string(), &bob).unwrap();
+        db.try_reply("Mine", &own, &bob, vec![]).unwrap();
+        db.report(&alice, Reported::Thread(own.clone()), ReportReason::Spam, String::new()).unwrap();
+        assert_eq!(db.get_reports_by(&alice).len(), 1);
+
+        assert_eq!(db.purge_user_content(&bob, &alice, String::new()), 2);
+        assert_eq!(db.count_replies_by(&bob), 0);
+        assert_eq!(db.get_thread(&shared).unwrap().replies.len(), 1);
+        assert!(db.get_thread(&own).is_none() && db.get_reports_by(&alice).is_empty());
+    }
+}
\ No newline at end of file
@@ -0,0 +1,221 @@
+use std::cmp::Reverse;
+
+use chrono::Utc;
+
+use crate::data::{UserID, ModItem, ModItemID, Moderatable, Appeal, Notice, ModRecord, ModAction};
+
+use super::{DB, store, inspection::RestoreError};
+
+pub const MAX_APPEAL_LENGTH: usize = 2000;
+
+#[derive(thiserror::Error, Debug)]
+pub enum AppealError {
+    #[error("None of your things with this ID are in inspection")]
+    NotFound,
+    #[error("You already appealed this")]
+    AlreadyAppealed,
+    #[error("Say why it should come back")]
+    Empty,
+    #[error("Appeals can't be longer than {MAX_APPEAL_LENGTH} characters")]
+    TooLong,
+    #[error("This appeal was decided already")]
+    NotPending,
+    #[error("{0}")]
+    Restore(#[from] RestoreError),
+}
+
+fn is_pending(item: &ModItem) -> bool {
+    item.appeal.as_ref().is_some_and(|x| x.denied.is_none())
+}
+
+impl DB {
+    /// What the item is, the way its author would put it
+    pub(super) fn describe_own_item(&self, item: &ModItem) -> String {
+        match &item.thing {
+            Moderatable::User => "profile".to_string(),
+            Moderatable::Topic(topic_id, _, _, _) => format!("topic λ/{}", topic_id.0),
+            Moderatable::Thread(_, thread, _, _, _) => format!("thread \"{}\"", thread.title),
+            Moderatable::Reply(_, _, thread_id, _) =>
+                format!("reply in \"{}\"", self.get_thread(thread_id).map_or("a deleted thread", |x| x.title.as_str())),
+        }
+    }
+
+    pub(super) fn notify(&mut self, user: &UserID, text: String) {
+        self.notices.entry(user.clone()).or_default().push(Notice { created: Utc::now(), text, seen: false });
+        store::store_notices(&self.notices);
+    }
+
+    /// Newest first
+    pub fn get_notices(&self, user: &UserID) -> Vec<&Notice> {
+        self.notices.get(user).map_or_else(Vec::new, |x| x.iter().rev().collect())
+    }
+
+    pub fn count_unseen_notices(&self, user: &UserID) -> usize {
+        self.notices.get(user).map_or(0, |x| x.iter().filter(|x| !x.seen).count())
+    }
+
+    pub fn mark_notices_seen(&mut self, user: &UserID) {
+        let Some(notices) = self.notices.get_mut(user).filter(|x| x.iter().any(|x| !x.seen)) else {
+            return;
+        };
+        notices.iter_mut().for_each(|x| x.seen = true);
+        store::store_notices(&self.notices);
+    }
+
+    /// What of theirs is in inspection right now, newest first
+    pub fn get_inspection_by(&self, user: &UserID) -> Vec<(&ModItemID, &ModItem)> {
+        let mut items = self.inspection.iter()
+            .filter(|(_, x)| x.author_id() == Some(user))
+            .collect::<Vec<_>>();
+        items.sort_unstable_by_key(|(_, x)| Reverse(x.moderated));
+        items
+    }
+
+    pub fn appeal(&mut self, id: &ModItemID, user: &UserID, statement: String) -> Result<(), AppealError> {
+        let Some(item) = self.inspection.get_mut(id).filter(|x| x.author_id() == Some(user)) else {
+            return Err(AppealError::NotFound);
+        };
+        if item.appeal.is_some() {
+            return Err(AppealError::AlreadyAppealed);
+        } else if statement.trim().is_empty() {
+            return Err(AppealError::Empty);
+        } else if statement.chars().count() > MAX_APPEAL_LENGTH {
+            return Err(AppealError::TooLong);
+        }
+        item.appeal = Some(Appeal { statement, created: Utc::now(), denied: None });
+        store::store_inspection_item(id, item);
+        Ok(())
+    }
+
+    /// Appeals nobody decided on yet, the ones waiting longest first
+    pub fn get_sorted_appeals(&self) -> Vec<(&ModItemID, &ModItem)> {
+        let mut items = self.inspection.iter()
+            .filter(|(_, x)| is_pending(x))
+            .collect::<Vec<_>>();
+        items.sort_unstable_by_key(|(_, x)| x.appeal.as_ref().map(|x| x.created));
+        items
+    }
+
+    /// Restores the item, which is what lets the author know
+    pub fn accept_appeal(&mut self, id: &ModItemID, target: Option<&str>, actor: &UserID) -> Result<(), AppealError> {
+        let item = self.inspection.get(id).ok_or(AppealError::NotFound)?;
+        if !is_pending(item) {
+            return Err(AppealError::NotPending);
+        }
+        let record = self.appeal_record(item, ModAction::AcceptAppeal, actor, String::new());
+        self.restore_from_inspection(id, target, actor)?;
+        self.record(record);
+        Ok(())
+    }
+
+    /// The item stays in inspection, and can't be appealed again
+    pub fn deny_appeal(&mut self, id: &ModItemID, actor: &UserID, response: String) -> Result<(), AppealError> {
+        let item = self.inspection.get(id).ok_or(AppealError::NotFound)?;
+        if !is_pending(item) {
+            return Err(AppealError::NotPending);
+        }
+        let record = self.appeal_record(item, ModAction::DenyAppeal, actor, response.clone());
+        let text = match response.as_str() {
+            "" => format!("Your appeal for your {} was turned down", self.describe_own_item(item)),
+            response => format!("Your appeal for your {} was turned down: {}", self.describe_own_item(item), response),
+        };
+        let author = item.author_id().cloned();
+        let item = self.inspection.get_mut(id).unwrap();
+        if let Some(appeal) = item.appeal.as_mut() {
+            appeal.denied = Some(response);
+        }
+        store::store_inspection_item(id, item);
+        self.record(record);
+        if let Some(author) = author {
+            self.notify(&author, text);
+        }
+        Ok(())
+    }
+
+    fn appeal_record(&self, item: &ModItem, action: ModAction, actor: &UserID, reason: String) -> ModRecord {
+        let (topic, target, _) = self.describe_item(item);
+        ModRecord {
+            created: Utc::now(),
+            actor: actor.clone(),
+            action,
+            user: item.author_id().cloned(),
+            topic,
+            target,
+            reason,
+            before: item.appeal.as_ref().map_or_else(String::new, |x| x.statement.clone()),
+            after: String::new(),
+        }
+    }
+
+    /// Lets the author know what happened to an item that left inspection, and to their appeal if they made one
+    pub(super) fn notify_item_outcome(&mut self, item: &ModItem, restored: bool) {
+        let Some(author) = item.author_id() else {
+            return;
+        };
+        let what = self.describe_own_item(item);
+        let text = match (is_pending(item), restored) {
+            (true, true) => format!("Your appeal was accepted, your {} is back", what),
+            (false, true) => format!("Moderators restored your {}", what),
+            (true, false) => format!("Your appeal was turned down, your {} was deleted for good", what),
+            (false, false) => format!("Your {} was deleted for good", what),
+        };
+        self.notify(&author.clone(), text);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::scratch_store;
+    use super::*;
+
+    fn thread_in_inspection(db: &mut DB, author: &UserID, moderator: &UserID) -> ModItemID {
+        let topic = db.create_new_topic(moderator, "cats", moderator).unwrap();
+        let thread = db.create_new_thread(&topic, "Hello".to_string(), author).unwrap();
+        db.try_reply("Hi", &thread, author, vec![]).unwrap();
+        db.move_thread_to_inspection(&thread, moderator, "spam".to_string());
+        db.get_inspection_by(author)[0].0.clone()
+    }
+
+    #[test]
+    fn accepted_appeals_bring_things_back() {
+        let _store = scratch_store();
+        let mut db = DB::load();
+        let [alice, bob] = ["alice", "bob"].map(|x| db.create_new_user(x, None));
+        let id = thread_in_inspection(&mut db, &bob, &alice);
+
+        assert!(matches!(db.appeal(&id, &alice, "Mine".to_string()), Err(AppealError::NotFound)));
+        assert!(matches!(db.appeal(&id, &bob, " ".to_string()), Err(AppealError::Empty)));
+        assert!(matches!(db.accept_appeal(&id, None, &alice), Err(AppealError::NotPending)));
+        db.appeal(&id, &bob, "It was a joke".to_string()).unwrap();
+        assert!(matches!(db.appeal(&id, &bob, "Again".to_string()), Err(AppealError::AlreadyAppealed)));
+        assert_eq!(DB::load().get_sorted_appeals().len(), 1);
+
+        db.accept_appeal(&id, None, &alice).unwrap();
+        assert!(db.get_inspection_item(&id).is_none() && db.get_sorted_appeals().is_empty());
+        assert!(db.get_notices(&bob)[0].text.starts_with("Your appeal was accepted"));
+        // Being moved into inspection was a notice of its own
+        assert_eq!(db.count_unseen_notices(&bob), 2);
+        db.mark_notices_seen(&bob);
+        assert_eq!(DB::load().count_unseen_notices(&bob), 0);
+    }
+
+    #[test]
+    fn denied_appeals_stay_denied() {
+        let _store = scratch_store();
+        let mut db = DB::load();
+        let [alice, bob] = ["alice", "bob"].map(|x| db.create_new_user(x, None));
+        let id = thread_in_inspection(&mut db, &bob, &alice);
+        db.appeal(&id, &bob, "It was a joke".to_string()).unwrap();
+
+        db.deny_appeal(&id, &alice, "It wasn't funny".to_string()).unwrap();
+        let mut db = DB::load();
+        assert!(db.get_notices(&bob)[0].text.ends_with("turned down: It wasn't funny"));
+        assert_eq!(db.get_inspection_item(&id).unwrap().appeal.as_ref().unwrap().denied.as_deref(), Some("It wasn't funny"));
+        assert!(matches!(db.deny_appeal(&id, &alice, String::new()), Err(AppealError::NotPending)));
+        assert!(matches!(db.accept_appeal(&id, None, &alice), Err(AppealError::NotPending)));
+        assert!(matches!(db.appeal(&id, &bob, "Please".to_string()), Err(AppealError::AlreadyAppealed)));
+
+        db.delete_from_inspection(&id, &alice);
+        assert!(db.get_notices(&bob)[0].text.ends_with("was deleted for good"));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,214 @@
+use std::collections::HashMap;
+
+use chrono::Utc;
+
+use crate::data::{UserID, TopicID, ThreadID, Thread, Reply, ModItem, ModItemID, Moderatable, Capability, ModRecord, ModAction};
+
+use super::{DB, store, permissions::Target};
+
+#[derive(thiserror::Error, Debug)]
+pub enum ApprovalError {
+    #[error("This post is no longer waiting for approval")]
+    NotFound,
+    #[error("The thread this reply was meant for no longer exists")]
+    ThreadGone,
+    #[error("The topic this thread was meant for no longer exists")]
+    TopicGone,
+    #[error("The thread this reply was meant for has been locked")]
+    ThreadLocked,
+    #[error("The author can't post anymore")]
+    AuthorCantPost,
+}
+
+impl DB {
+    /// A reply that didn't get posted yet, if it could be posted right now
+    pub(super) fn new_reply_item(&self, content: &str, thread_id: &ThreadID, user: &UserID, tags: Vec<String>) -> Option<ModItem> {
+        if self.threads.get(thread_id).is_none_or(|x| x.locked) || !self.users.contains_key(user) || !self.can_post(user) {
+            return None;
+        }
+        let reply = Reply { created: Utc::now(), user: user.clone(), content: content.to_string(), tags, shadowed: self.is_shadow_banned(user) };
+        Some(ModItem {
+            moderated: Utc::now(),
+            author: Some(self.snapshot_author(user)),
+            thing: Moderatable::Reply(store::gen_reply_id(), reply, thread_id.clone(), usize::MAX),
+            reason: String::new(),
+            appeal: None,
+        })
+    }
+
+    /// Same as [DB::new_reply_item] for a new thread along with its first reply
+    pub(super) fn new_thread_item(&self, topic_id: &TopicID, title: String, content: &str, user: &UserID, tags: Vec<String>) -> Option<ModItem> {
+        if !self.topics.contains_key(topic_id) || !self.users.contains_key(user) || !self.can_post(user) {
+            return None;
+        }
+        let reply_id = store::gen_reply_id();
+        let reply = Reply { created: Utc::now(), user: user.clone(), content: content.to_string(), tags, shadowed: self.is_shadow_banned(user) };
+        let thread = Thread { title, replies: vec![reply_id.clone()], locked: false, pinned: false };
+        Some(ModItem {
+            moderated: Utc::now(),
+            author: Some(self.snapshot_author(user)),
+            thing: Moderatable::Thread(store::gen_thread_id(), thread, HashMap::from([(reply_id, reply)]), topic_id.clone(), usize::MAX),
+            reason: String::new(),
+            appeal: None,
+        })
+    }
+
+    /// Until enough of their posts are up, anything new from the user waits in the pending queue.
+    /// Those who may approve posts in the topic never have to
+    pub fn needs_approval(&self, user: &UserID, topic: &TopicID) -> bool {
+        let required = self.settings.approval_posts;
+        required > 0
+            && !self.can(user, Capability::ApprovePosts, Target::Topic(topic))
+            && self.replies.values().filter(|x| &x.user == user).take(required).count() < required
+    }
+
+    pub fn submit_reply(&mut self, content: &str, thread_id: &ThreadID, user: &UserID, tags: Vec<String>) -> bool {
+        let Some(item) = self.new_reply_item(content, thread_id, user, tags) else {
+            return false;
+        };
+        self.insert_pending_item(item);
+        true
+    }
+
+    pub fn submit_thread(&mut self, topic_id: &TopicID, title: String, content: &str, user: &UserID, tags: Vec<String>) -> bool {
+        let Some(item) = self.new_thread_item(topic_id, title, content, user, tags) else {
+            return false;
+        };
+        self.insert_pending_item(item);
+        true
+    }
+
+    fn insert_pending_item(&mut self, item: ModItem) {
+        let id = store::gen_pending_id();
+        store::store_pending_item(&id, &item);
+        self.pending.insert(id, item);
+    }
+
+    /// Oldest first, the way they came in
+    pub fn get_sorted_pending(&self) -> Vec<(&ModItemID, &ModItem)> {
+        let mut items = self.pending.iter().collect::<Vec<_>>();
+        items.sort_unstable_by_key(|(_, x)| x.moderated);
+        items
+    }
+
+    /// What the user is still waiting on, so they can see it wasn't lost
+    pub fn get_pending_by(&self, user: &UserID) -> Vec<&ModItem> {
+        self.get_sorted_pending().into_iter()
+            .map(|(_, x)| x)
+            .filter(|x| x.author_id() == Some(user))
+            .collect()
+    }
+
+    pub fn can_approve(&self, user: &UserID, id: &ModItemID) -> bool {
+        match self.pending.get(id).map(|x| &x.thing) {
+            Some(Moderatable::Reply(_, _, thread_id, _)) => self.can(user, Capability::ApprovePosts, Target::Thread(thread_id)),
+            Some(Moderatable::Thread(_, _, _, topic_id, _)) => self.can(user, Capability::ApprovePosts, Target::Topic(topic_id)),
+            _ => false,
+        }
+    }
+
+    /// Whether there's anywhere at all this user can approve posts
+    pub fn is_approver(&self, user: &UserID) -> bool {
+        self.can(user, Capability::ApprovePosts, Target::Site)
+            || self.topics.keys().any(|x| self.can(user, Capability::ApprovePosts, Target::Topic(x)))
+    }
+
+    /// Posts it where it was meant to go, at the end as if it was posted just now.
+    /// Whatever would stop the author from posting it now stops the approval too, it stays pending until rejected
+    pub fn approve_pending(&mut self, id: &ModItemID, actor: &UserID) -> Result<(), ApprovalError> {
+        let Some(item) = self.pending.get(id) else {
+            return Err(ApprovalError::NotFound);
+        };
+        match &item.thing {
+            Moderatable::Reply(_, _, thread_id, _) => match self.threads.get(thread_id) {
+                None => return Err(ApprovalError::ThreadGone),
+                Some(thread) if thread.locked => return Err(ApprovalError::ThreadLocked),
+                Some(_) => {},
+            },
+            Moderatable::Thread(_, _, _, topic_id, _) if !self.topics.contains_key(topic_id) => return Err(ApprovalError::TopicGone),
+            _ => {},
+        }
+        if item.author_id().is_some_and(|x| !self.can_post(x)) {
+            return Err(ApprovalError::AuthorCantPost);
+        }
+        let shadowed = item.author_id().is_some_and(|x| self.is_shadow_banned(x));
+        let mut item = self.pending.remove(id).unwrap();
+        store::delete_pending_item(id);
+        let replies = match &mut item.thing {
+            Moderatable::Reply(_, reply, _, _) => vec![reply],
+            Moderatable::Thread(_, _, replies, _, _) => replies.values_mut().collect(),
+            _ => vec![],
+        };
+        for reply in replies {
+            reply.created = Utc::now();
+            reply.shadowed = shadowed;
+        }
+        self.record_approval(&item, actor, ModAction::Approve, String::new());
+        self.put_back(item.thing, None);
+        Ok(())
+    }
+
+    /// Gone for good, like it was never posted
+    pub fn reject_pending(&mut self, id: &ModItemID, actor: &UserID, reason: String) -> bool {
+        let Some(item) = self.pending.remove(id) else {
+            return false;
+        };
+        store::delete_pending_item(id);
+        self.record_approval(&item, actor, ModAction::Reject, reason);
+        true
+    }
+
+    fn record_approval(&mut self, item: &ModItem, actor: &UserID, action: ModAction, reason: String) {
+        let (topic, target, snapshot) = self.describe_item(item);
+        self.record(ModRecord {
+            created: Utc::now(),
+            actor: actor.clone(),
+            action,
+            user: item.author_id().cloned(),
+            topic,
+            target,
+            reason,
+            before: String::new(),
+            after: snapshot,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{data::BanKind, testing::scratch_store};
+    use super::*;
+
+    #[test]
+    fn first_posts_wait_until_they_could_be_posted() {
+        let _store = scratch_store();
+        let mut db = DB::load();
+        db.settings.approval_posts = 1;
+        let [alice, bob, carol] = ["alice", "bob", "carol"].map(|x| db.create_new_user(x, None));
+        let topic = db.create_new_topic(&alice, "cats", &alice).unwrap();
+        let thread = db.create_new_thread(&topic, "Hello".to_string(), &alice).unwrap();
+        assert!(!db.needs_approval(&alice, &topic) && db.needs_approval(&bob, &topic));
+
+        assert!(db.submit_reply("Hi", &thread, &bob, vec![]));
+        assert!(db.submit_reply("Hey", &thread, &carol, vec![]));
+        let [bobs, carols] = [&bob, &carol].map(|x| db.get_sorted_pending().into_iter()
+            .find(|(_, item)| item.author_id() == Some(x)).unwrap().0.clone());
+        assert!(db.can_approve(&alice, &bobs) && !db.can_approve(&carol, &bobs));
+
+        db.set_thread_locked(&thread, true, &alice);
+        assert!(matches!(db.approve_pending(&bobs, &alice), Err(ApprovalError::ThreadLocked)));
+        db.set_thread_locked(&thread, false, &alice);
+        db.ban_user(&carol, &alice, BanKind::Suspension, None, String::new()).unwrap();
+        assert!(matches!(db.approve_pending(&carols, &alice), Err(ApprovalError::AuthorCantPost)));
+        assert_eq!(db.get_pending_by(&carol).len(), 1);
+
+        let submitted = Utc::now();
+        db.approve_pending(&bobs, &alice).unwrap();
+        let replies = &db.get_thread(&thread).unwrap().replies;
+        let reply = db.get_reply(replies.last().unwrap()).unwrap();
+        // Dated when it went up, not when it was submitted
+        assert!(reply.user == bob && reply.created >= submitted);
+        assert!(!db.needs_approval(&bob, &topic) && db.get_pending_by(&bob).is_empty());
+        assert!(matches!(db.approve_pending(&bobs, &alice), Err(ApprovalError::NotFound)));
+    }
+}
\ No newline at end of file
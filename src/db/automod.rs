@@ -0,0 +1,92 @@
+use std::cmp::Reverse;
+
+use chrono::Utc;
+
+use crate::data::{UserID, TopicID, ThreadID, ModItem, AutomodRule, AutomodAction, AutomodHit, Capability};
+
+use super::{DB, store, permissions::Target};
+
+const MAX_EXCERPT_LENGTH: usize = 200;
+
+/// What to do with a post after all rules had a look at it
+pub enum Verdict {
+    /// With the tags to put on it
+    Pass(Vec<String>),
+    /// With the names of the rules that held it
+    Hold(Vec<String>),
+    Reject(String),
+}
+
+impl DB {
+    /// Runs every rule that applies in the topic and logs each one that matches.
+    /// Moderators of the topic are never held back
+    pub fn run_automod(&mut self, user: &UserID, topic: &TopicID, thread: Option<&ThreadID>, title: &str, content: &str) -> Verdict {
+        if self.can(user, Capability::ModerateTopic, Target::Topic(topic)) {
+            return Verdict::Pass(vec![]);
+        }
+        let text = format!("{}\n{}", title, content);
+        let joined = self.users.get(user).and_then(|x| x.joined);
+        let hits = self.automod_rules.iter()
+            .filter(|x| x.topic.as_ref().is_none_or(|x| x == topic))
+            .filter(|x| x.conditions.iter().all(|x| x.matches(&text, joined)))
+            .map(|x| (x.name.clone(), x.action.clone()))
+            .collect::<Vec<_>>();
+        for (rule, action) in &hits {
+            let hit = AutomodHit {
+                created: Utc::now(),
+                rule: rule.clone(),
+                user: user.clone(),
+                topic: topic.clone(),
+                thread: thread.cloned(),
+                action: action.describe(),
+                excerpt: text.trim().chars().take(MAX_EXCERPT_LENGTH).collect(),
+            };
+            store::store_automod_hit(&hit);
+            self.automod_hits.push(hit);
+        }
+        match hits.iter().map(|(_, x)| x).max() {
+            Some(AutomodAction::Reject(message)) => Verdict::Reject(message.clone()),
+            Some(AutomodAction::Hold) => Verdict::Hold(hits.into_iter()
+                .filter(|(_, x)| x == &AutomodAction::Hold)
+                .map(|(x, _)| x)
+                .collect()),
+            _ => Verdict::Pass(hits.into_iter()
+                .filter_map(|(_, x)| match x {
+                    AutomodAction::Tag(tag) => Some(tag),
+                    _ => None,
+                })
+                .collect()),
+        }
+    }
+
+    /// Puts the reply straight into inspection, restoring it adds it to the end of the thread
+    pub fn hold_reply(&mut self, content: &str, thread_id: &ThreadID, user: &UserID, rules: &[String]) -> bool {
+        let Some(item) = self.new_reply_item(content, thread_id, user, vec![]) else {
+            return false;
+        };
+        self.insert_inspection_item(ModItem { reason: format!("Held by automod: {}", rules.join(", ")), ..item });
+        true
+    }
+
+    /// Same as [DB::hold_reply] for a new thread along with its first reply
+    pub fn hold_thread(&mut self, topic_id: &TopicID, title: String, content: &str, user: &UserID, rules: &[String]) -> bool {
+        let Some(item) = self.new_thread_item(topic_id, title, content, user, vec![]) else {
+            return false;
+        };
+        self.insert_inspection_item(ModItem { reason: format!("Held by automod: {}", rules.join(", ")), ..item });
+        true
+    }
+
+    pub fn get_automod_rules(&self) -> &[AutomodRule] {
+        &self.automod_rules
+    }
+
+    /// Newest first, only those of one rule if given
+    pub fn get_automod_hits(&self, rule: Option<&str>) -> Vec<&AutomodHit> {
+        let mut hits = self.automod_hits.iter()
+            .filter(|x| rule.is_none_or(|rule| x.rule == rule))
+            .collect::<Vec<_>>();
+        hits.sort_by_key(|x| Reverse(x.created));
+        hits
+    }
+}
\ No newline at end of file
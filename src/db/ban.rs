@@ -0,0 +1,205 @@
+use chrono::{Utc, Duration};
+
+use crate::data::{UserID, Ban, BanKind, ModRecord, ModAction, Reported};
+
+use super::{DB, store};
+
+pub const MAX_BAN_REASON_LENGTH: usize = 500;
+/// Anything longer might as well be permanent
+pub const MAX_BAN_DAYS: i64 = 3650;
+
+#[derive(thiserror::Error, Debug)]
+pub enum BanError {
+    #[error("Duration must be between 1 and {MAX_BAN_DAYS} days, or empty for a permanent one")]
+    InvalidDuration,
+    #[error("The reason can't be longer than {MAX_BAN_REASON_LENGTH} characters")]
+    ReasonTooLong,
+    #[error("You can't ban yourself")]
+    OwnAccount,
+    #[error("There's no such user")]
+    NotFound,
+    #[error("They're already banned or suspended, lift that first to shadow-ban them instead")]
+    AlreadyBanned,
+}
+
+impl DB {
+    /// Replaces whatever ban the user had before, `days` being `None` makes it permanent.
+    /// A shadow-ban would let them post again, so it never replaces a ban or suspension
+    pub fn ban_user(&mut self, user: &UserID, moderator: &UserID, kind: BanKind, days: Option<i64>, reason: String) -> Result<(), BanError> {
+        if user == moderator {
+            return Err(BanError::OwnAccount);
+        } else if !self.users.contains_key(user) {
+            return Err(BanError::NotFound);
+        } else if days.is_some_and(|x| !(1..=MAX_BAN_DAYS).contains(&x)) {
+            return Err(BanError::InvalidDuration);
+        } else if reason.chars().count() > MAX_BAN_REASON_LENGTH {
+            return Err(BanError::ReasonTooLong);
+        }
+        let now = Utc::now();
+        self.bans.retain(|_, x| x.is_active(now));
+        if kind == BanKind::Shadow && self.bans.get(user).is_some_and(|x| x.kind != BanKind::Shadow) {
+            return Err(BanError::AlreadyBanned);
+        }
+        let ban = Ban {
+            moderator: moderator.clone(),
+            issued: now,
+            until: days.map(|x| now + Duration::days(x)),
+            reason,
+            kind,
+        };
+        self.record(ModRecord {
+            created: now,
+            actor: moderator.clone(),
+            action: if kind == BanKind::Shadow { ModAction::ShadowBan } else { ModAction::Ban },
+            user: Some(user.clone()),
+            topic: None,
+            target: format!("@{}", user.0),
+            reason: ban.reason.clone(),
+            before: self.bans.get(user).map_or_else(String::new, |x| x.describe()),
+            after: ban.describe(),
+        });
+        self.bans.insert(user.clone(), ban);
+        store::store_bans(&self.bans);
+        Ok(())
+    }
+
+    pub fn lift_ban(&mut self, user: &UserID, actor: &UserID) {
+        let Some(ban) = self.bans.remove(user) else {
+            return;
+        };
+        if ban.is_active(Utc::now()) {
+            self.record(ModRecord {
+                created: Utc::now(),
+                actor: actor.clone(),
+                action: if ban.kind == BanKind::Shadow { ModAction::LiftShadowBan } else { ModAction::LiftBan },
+                user: Some(user.clone()),
+                topic: None,
+                target: format!("@{}", user.0),
+                reason: String::new(),
+                before: ban.describe(),
+                after: String::new(),
+            });
+        }
+        self.bans.retain(|_, x| x.is_active(Utc::now()));
+        store::store_bans(&self.bans);
+    }
+
+    /// Expired bans are left in place until the next change, they just stop counting
+    pub fn get_ban(&self, user: &UserID) -> Option<&Ban> {
+        self.bans.get(user).filter(|x| x.is_active(Utc::now()))
+    }
+
+    /// Only full bans, suspended users can still log in
+    pub fn is_banned(&self, user: &UserID) -> bool {
+        self.get_ban(user).is_some_and(|x| x.kind == BanKind::Ban)
+    }
+
+    /// Both bans and suspensions take away posting, shadow-bans only make it pointless
+    pub fn can_post(&self, user: &UserID) -> bool {
+        self.get_ban(user).is_none_or(|x| x.kind == BanKind::Shadow)
+    }
+
+    /// Whatever they post from now on gets marked as shadowed
+    pub fn is_shadow_banned(&self, user: &UserID) -> bool {
+        self.get_ban(user).is_some_and(|x| x.kind == BanKind::Shadow)
+    }
+
+    /// Who posted the thread or reply while shadow-banned, if it was posted like that.
+    /// Lifting the ban leaves these alone, they stay hidden
+    pub fn get_shadowed_author(&self, reported: &Reported) -> Option<&UserID> {
+        let reply = match reported {
+            Reported::Thread(thread) => self.threads.get(thread).and_then(|x| x.replies.first()),
+            Reported::Reply(_, reply) => Some(reply),
+        };
+        reply.and_then(|x| self.replies.get(x)).filter(|x| x.shadowed).map(|x| &x.user)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{auth::{Auth, LoginError, LoginStep}, data::{Capability, RoleGrant, RoleID, Roles}, db::permissions::Target, testing::scratch_store};
+    use super::*;
+
+    const PASSWORD: &str = "correct horse battery staple";
+
+    #[test]
+    fn bans_take_everything_and_suspensions_only_posting() {
+        let _store = scratch_store();
+        let mut db = DB::load();
+        let mut auth = Auth::init();
+        let [alice, bob, carol] = ["alice", "bob", "carol"].map(|x| auth.signup(x, PASSWORD, None, "127.0.0.1", &mut db).unwrap());
+        db.grant_permission(&bob, RoleGrant { role: RoleID(Roles::OVERLORD.to_string()), topic: None }, &bob);
+        let topic = db.create_new_topic(&alice, "cats", &alice).unwrap();
+        let thread = db.create_new_thread(&topic, "Hello".to_string(), &alice).unwrap();
+
+        assert!(matches!(db.ban_user(&alice, &alice, BanKind::Ban, None, String::new()), Err(BanError::OwnAccount)));
+        assert!(matches!(db.ban_user(&UserID("nobody".to_string()), &alice, BanKind::Ban, None, String::new()), Err(BanError::NotFound)));
+        assert!(matches!(db.ban_user(&bob, &alice, BanKind::Ban, Some(0), String::new()), Err(BanError::InvalidDuration)));
+
+        db.ban_user(&bob, &alice, BanKind::Ban, Some(7), "spam".to_string()).unwrap();
+        db.ban_user(&carol, &alice, BanKind::Suspension, None, String::new()).unwrap();
+        let db = DB::load();
+        assert!(db.is_banned(&bob) && !db.can_post(&bob));
+        // Not even an overlord role helps, that covers API tokens too
+        assert!(!db.can(&bob, Capability::ManageSite, Target::Site) && !db.is_admin(&bob));
+        assert!(matches!(auth.login("bob", PASSWORD, "127.0.0.1", &db), Err(LoginError::Banned(x)) if x.contains("spam")));
+
+        assert!(!db.is_banned(&carol) && !db.can_post(&carol));
+        assert!(matches!(auth.login("carol", PASSWORD, "127.0.0.1", &db), Ok(LoginStep::Done(_, _))));
+        let mut db = db;
+        assert!(db.try_reply("Hi", &thread, &carol, vec![]).is_none());
+        db.lift_ban(&carol, &alice);
+        assert!(db.try_reply("Hi", &thread, &carol, vec![]).is_some());
+    }
+
+    #[test]
+    fn bans_run_out() {
+        let _store = scratch_store();
+        let mut db = DB::load();
+        let [alice, bob] = ["alice", "bob"].map(|x| db.create_new_user(x, None));
+        db.ban_user(&bob, &alice, BanKind::Ban, Some(1), String::new()).unwrap();
+        db.bans.get_mut(&bob).unwrap().until = Some(Utc::now() - Duration::seconds(1));
+        assert!(db.get_ban(&bob).is_none() && !db.is_banned(&bob) && db.can_post(&bob));
+        assert!(db.can(&bob, Capability::Post, Target::Site));
+    }
+
+    #[test]
+    fn shadowed_posts_only_show_to_their_author_and_moderators() {
+        let _store = scratch_store();
+        let mut db = DB::load();
+        let [alice, bob, carol] = ["alice", "bob", "carol"].map(|x| db.create_new_user(x, None));
+        let topic = db.create_new_topic(&alice, "cats", &alice).unwrap();
+        let thread = db.create_new_thread(&topic, "Hello".to_string(), &alice).unwrap();
+        db.try_reply("First", &thread, &alice, vec![]).unwrap();
+
+        db.ban_user(&bob, &alice, BanKind::Shadow, None, String::new()).unwrap();
+        assert!(db.can_post(&bob) && !db.is_banned(&bob));
+        let reply = Reported::Reply(thread.clone(), db.try_reply("Buy now", &thread, &bob, vec![]).unwrap());
+        assert_eq!(db.get_shadowed_author(&reply), Some(&bob));
+        assert!(db.is_hidden_from(None, &reply) && db.is_hidden_from(Some(&carol), &reply));
+        assert!(!db.is_hidden_from(Some(&bob), &reply) && !db.is_hidden_from(Some(&alice), &reply));
+
+        // Lifting it only lets new posts through
+        db.lift_ban(&bob, &alice);
+        assert!(db.is_hidden_from(Some(&carol), &reply));
+        let later = Reported::Reply(thread.clone(), db.try_reply("Sorry", &thread, &bob, vec![]).unwrap());
+        assert!(!db.is_hidden_from(Some(&carol), &later));
+    }
+
+    #[test]
+    fn shadow_bans_never_replace_a_stronger_one() {
+        let _store = scratch_store();
+        let mut db = DB::load();
+        let [alice, bob] = ["alice", "bob"].map(|x| db.create_new_user(x, None));
+        for kind in [BanKind::Ban, BanKind::Suspension] {
+            db.ban_user(&bob, &alice, kind, None, String::new()).unwrap();
+            assert!(matches!(db.ban_user(&bob, &alice, BanKind::Shadow, None, String::new()), Err(BanError::AlreadyBanned)));
+            assert!(!db.can_post(&bob));
+        }
+        db.lift_ban(&bob, &alice);
+        db.ban_user(&bob, &alice, BanKind::Shadow, Some(1), String::new()).unwrap();
+        db.ban_user(&bob, &alice, BanKind::Shadow, None, String::new()).unwrap();
+        db.ban_user(&bob, &alice, BanKind::Ban, None, String::new()).unwrap();
+        assert!(db.is_banned(&bob));
+    }
+}
\ No newline at end of file
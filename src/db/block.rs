@@ -0,0 +1,148 @@
+use regex::Regex;
+
+use super::{DB, store};
+
+use crate::{data::{UserID, TopicID, ThreadID}, auth::username};
+
+#[derive(thiserror::Error, Debug)]
+pub enum BlockError {
+    #[error("There's no user with this name")]
+    UserNotFound,
+    #[error("There's no topic with this name")]
+    TopicNotFound,
+    #[error("You can't block yourself")]
+    OwnAccount,
+}
+
+impl DB {
+    pub fn block_user(&mut self, user_id: &UserID, other: &UserID, block: bool) -> Result<(), BlockError> {
+        if user_id == other {
+            return Err(BlockError::OwnAccount);
+        } else if block && !self.users.contains_key(other) {
+            return Err(BlockError::UserNotFound);
+        }
+        let user = self.users.get_mut(user_id).unwrap();
+        let i = user.blocked.iter().rposition(|x| x == other);
+        if block {
+            if i.is_none() {
+                user.blocked.push(other.clone());
+            }
+        } else if let Some(i) = i {
+            user.blocked.remove(i);
+        }
+        store::store_user(user_id, user);
+        Ok(())
+    }
+
+    pub fn mute_topic(&mut self, user_id: &UserID, topic: &TopicID, mute: bool) -> Result<(), BlockError> {
+        if mute && !self.topics.contains_key(topic) {
+            return Err(BlockError::TopicNotFound);
+        }
+        let user = self.users.get_mut(user_id).unwrap();
+        let i = user.muted_topics.iter().rposition(|x| x == topic);
+        if mute {
+            if i.is_none() {
+                user.muted_topics.push(topic.clone());
+            }
+        } else if let Some(i) = i {
+            user.muted_topics.remove(i);
+        }
+        store::store_user(user_id, user);
+        Ok(())
+    }
+
+    /// Nobody blocked anyone as far as logged out visitors are concerned
+    pub fn has_blocked(&self, user: Option<&UserID>, other: &UserID) -> bool {
+        user.and_then(|x| self.users.get(x)).is_some_and(|x| x.blocked.contains(other))
+    }
+
+    pub fn has_muted(&self, user: Option<&UserID>, topic: &TopicID) -> bool {
+        user.and_then(|x| self.users.get(x)).is_some_and(|x| x.muted_topics.contains(topic))
+    }
+
+    /// Whether `text` `@`-names anyone who blocked its author, they don't want to hear from them
+    pub fn mentions_blocker(&self, author: &UserID, text: &str) -> bool {
+        let mentioned = Regex::new("(?:^|[^a-zA-Z0-9_-])@([a-zA-Z0-9_-]+)").unwrap()
+            .captures_iter(text)
+            .map(|x| username::fold(&x[1]))
+            .collect::<Vec<_>>();
+        self.users.iter()
+            .filter(|(_, x)| x.blocked.contains(author))
+            .any(|(id, _)| mentioned.contains(&username::fold(&id.0)))
+    }
+
+    /// Whether the thread was started by someone the user blocked
+    pub fn is_thread_blocked(&self, user: Option<&UserID>, thread: &ThreadID) -> bool {
+        self.threads.get(thread)
+            .and_then(|x| x.replies.first())
+            .and_then(|x| self.replies.get(x))
+            .is_some_and(|x| self.has_blocked(user, &x.user))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::scratch_store;
+    use super::*;
+
+    #[test]
+    fn blocks_hide_threads_started_by_the_blocked() {
+        let _store = scratch_store();
+        let mut db = DB::load();
+        let [alice, bob, carol] = ["alice", "bob", "carol"].map(|x| db.create_new_user(x, None));
+        let topic = db.create_new_topic(&alice, "cats", &alice).unwrap();
+        let bobs = db.create_new_thread(&topic, "Bob's".to_string(), &bob).unwrap();
+        db.try_reply("Hi", &bobs, &bob, vec![]).unwrap();
+        let carols = db.create_new_thread(&topic, "Carol's".to_string(), &carol).unwrap();
+        db.try_reply("Hey", &carols, &carol, vec![]).unwrap();
+        db.try_reply("Hi", &carols, &bob, vec![]).unwrap();
+
+        assert!(matches!(db.block_user(&alice, &alice, true), Err(BlockError::OwnAccount)));
+        assert!(matches!(db.block_user(&alice, &UserID("nobody".to_string()), true), Err(BlockError::UserNotFound)));
+        db.block_user(&alice, &bob, true).unwrap();
+        db.block_user(&alice, &bob, true).unwrap();
+        let mut db = DB::load();
+        assert_eq!(db.get_user(&alice).unwrap().blocked, vec![bob.clone()]);
+        assert!(db.has_blocked(Some(&alice), &bob) && !db.has_blocked(Some(&bob), &alice) && !db.has_blocked(None, &bob));
+        // Replying in someone else's thread doesn't make it theirs
+        assert!(db.is_thread_blocked(Some(&alice), &bobs) && !db.is_thread_blocked(Some(&alice), &carols));
+        assert!(!db.is_thread_blocked(Some(&carol), &bobs) && !db.is_thread_blocked(None, &bobs));
+
+        db.block_user(&alice, &bob, false).unwrap();
+        assert!(!db.is_thread_blocked(Some(&alice), &bobs));
+    }
+
+    #[test]
+    fn mutes_only_apply_to_whoever_muted() {
+        let _store = scratch_store();
+        let mut db = DB::load();
+        let [alice, bob] = ["alice", "bob"].map(|x| db.create_new_user(x, None));
+        let topic = db.create_new_topic(&alice, "cats", &alice).unwrap();
+        assert!(matches!(db.mute_topic(&alice, &TopicID("dogs".to_string()), true), Err(BlockError::TopicNotFound)));
+
+        db.mute_topic(&alice, &topic, true).unwrap();
+        let mut db = DB::load();
+        assert!(db.has_muted(Some(&alice), &topic) && !db.has_muted(Some(&bob), &topic) && !db.has_muted(None, &topic));
+        db.mute_topic(&alice, &topic, false).unwrap();
+        assert!(!db.has_muted(Some(&alice), &topic));
+        // Unmuting works even after the topic is gone
+        db.mute_topic(&alice, &TopicID("dogs".to_string()), false).unwrap();
+    }
+    #[test]
+    fn the_blocked_cant_mention_the_blocker() {
+        let _store = scratch_store();
+        let mut db = DB::load();
+        let [alice, bob, carol] = ["alice", "bob", "carol"].map(|x| db.create_new_user(x, None));
+        db.block_user(&alice, &bob, true).unwrap();
+
+        for text in ["@alice", "Hi @Alice!", "<b>@alice</b>", "cc @carol, @alice"] {
+            assert!(db.mentions_blocker(&bob, text), "{}", text);
+        }
+        for text in ["alice", "bob@alice", "@alice_2", "@carol"] {
+            assert!(!db.mentions_blocker(&bob, text), "{}", text);
+        }
+        assert!(!db.mentions_blocker(&carol, "@alice") && !db.mentions_blocker(&alice, "@bob"));
+        db.block_user(&alice, &bob, false).unwrap();
+        assert!(!db.mentions_blocker(&bob, "@alice"));
+    }
+}
\ No newline at end of file
@@ -29,6 +29,14 @@ impl DB {
         store::store_user(user_id, user)
     }
 
+    /// For threads that are gone, the sidebar has nothing left to show for them
+    pub(super) fn forget_favorite_thread(&mut self, thread: &ThreadID) {
+        for (id, user) in self.users.iter_mut().filter(|(_, x)| x.fav_threads.contains(thread)) {
+            user.fav_threads.retain(|x| x != thread);
+            store::store_user(id, user);
+        }
+    }
+
     pub fn is_topic_favorite(&self, user: &UserID, topic: &TopicID) -> bool {
         let user = self.users.get(user).unwrap();
         user.fav_topics.contains(topic)
@@ -1,21 +1,387 @@
+use std::{cmp::Reverse, collections::{HashMap, hash_map::Entry}};
+
 use chrono::Utc;
 
-use crate::data::{ThreadID, ReplyID, ModItem, Moderatable};
+use crate::data::{TopicID, ThreadID, ReplyID, UserID, User, ModItem, ModItemID, Moderatable, Capability, ModRecord, ModAction};
+
+use super::{DB, store, permissions::Target};
 
-use super::{DB, store};
+#[derive(thiserror::Error, Debug)]
+pub enum RestoreError {
+    #[error("This item is no longer in inspection")]
+    NotFound,
+    #[error("The thread this reply was in no longer exists, pick another one to restore it into")]
+    ThreadGone,
+    #[error("The topic this thread was in no longer exists, pick another one to restore it into")]
+    TopicGone,
+    #[error("There's nothing to restore into with that name")]
+    TargetNotFound,
+    #[error("A topic with this name was created in the meantime")]
+    TopicTaken,
+}
+
+/// What it takes to deal with the item, depending on what it is and where it was taken from
+pub fn inspection_requirement(item: &ModItem) -> (Capability, Target<'_>) {
+    match &item.thing {
+        Moderatable::Reply(_, _, thread_id, _) => (Capability::ModerateTopic, Target::Thread(thread_id)),
+        Moderatable::Thread(_, _, _, topic_id, _) => (Capability::ModerateTopic, Target::Topic(topic_id)),
+        Moderatable::Topic(_, _, _, _) => (Capability::ManageTopics, Target::Site),
+        Moderatable::User => (Capability::ManageUsers, Target::Site),
+    }
+}
 
 impl DB {
-    pub fn move_reply_to_inspection(&mut self, thread_id: &ThreadID, reply_id: &ReplyID) {
+    /// How the author looks right now, so the item still makes sense if their profile changes
+    pub(super) fn snapshot_author(&self, user: &UserID) -> (UserID, User) {
+        (user.clone(), self.users.get(user).cloned().unwrap_or_default())
+    }
+
+    fn add_to_inspection(&mut self, item: ModItem, actor: &UserID) {
+        let (topic, target, before) = self.describe_item(&item);
+        if let Some(author) = item.author_id().cloned() {
+            let text = match item.reason.as_str() {
+                "" => format!("Moderators took down your {}", self.describe_own_item(&item)),
+                reason => format!("Moderators took down your {}: {}", self.describe_own_item(&item), reason),
+            };
+            self.notify(&author, text);
+        }
+        self.record(ModRecord {
+            created: item.moderated,
+            actor: actor.clone(),
+            action: ModAction::MoveToInspection,
+            user: item.author_id().cloned(),
+            topic,
+            target,
+            reason: item.reason.clone(),
+            before,
+            after: String::new(),
+        });
+        self.insert_inspection_item(item);
+    }
+
+    /// Without recording it, for when it wasn't a moderator who put it there
+    pub(super) fn insert_inspection_item(&mut self, item: ModItem) {
+        let id = store::gen_inspection_id();
+        store::store_inspection_item(&id, &item);
+        self.inspection.insert(id, item);
+    }
+
+    pub fn move_reply_to_inspection(&mut self, thread_id: &ThreadID, reply_id: &ReplyID, actor: &UserID, reason: String) {
+        let Some(position) = self.threads.get(thread_id)
+            .and_then(|x| x.replies.iter().position(|x| x == reply_id)) else {
+            return;
+        };
         let Some(reply) = self.delete_reply(thread_id, reply_id) else {
             return;
         };
-        let id = store::gen_inspection_id();
-        let Some(user) = self.users.remove(&reply.user) else {
+        self.add_to_inspection(ModItem {
+            moderated: Utc::now(),
+            author: Some(self.snapshot_author(&reply.user)),
+            thing: Moderatable::Reply(reply_id.clone(), reply, thread_id.clone(), position),
+            reason,
+            appeal: None,
+        }, actor);
+    }
+
+    /// Takes all of the thread's replies along, whoever wrote them. The author is whoever started it
+    pub fn move_thread_to_inspection(&mut self, thread_id: &ThreadID, actor: &UserID, reason: String) {
+        let Some((topic_id, position)) = self.topics.iter()
+            .find_map(|(id, x)| x.threads.iter().position(|x| x == thread_id).map(|x| (id.clone(), x))) else {
+            return;
+        };
+        let Some((thread, replies)) = self.delete_thread(&topic_id, thread_id) else {
+            return;
+        };
+        let author = thread.replies.first()
+            .and_then(|x| replies.get(x))
+            .map(|x| self.snapshot_author(&x.user));
+        self.add_to_inspection(ModItem {
+            moderated: Utc::now(),
+            author,
+            thing: Moderatable::Thread(thread_id.clone(), thread, replies, topic_id, position),
+            reason,
+            appeal: None,
+        }, actor);
+    }
+
+    /// Takes all of its threads and their replies along, so none of it can be reached while it's in inspection
+    pub fn move_topic_to_inspection(&mut self, topic_id: &TopicID, actor: &UserID, reason: String) {
+        let Some(topic) = self.topics.remove(topic_id) else {
+            return;
+        };
+        store::delete_topic(topic_id);
+        let mut threads = HashMap::new();
+        let mut replies = HashMap::new();
+        for thread_id in &topic.threads {
+            let Some(thread) = self.threads.remove(thread_id) else {
+                continue;
+            };
+            for reply_id in &thread.replies {
+                if let Some(reply) = self.replies.remove(reply_id) {
+                    store::delete_reply(reply_id);
+                    replies.insert(reply_id.clone(), reply);
+                }
+            }
+            store::delete_thread(thread_id);
+            self.drop_reports_for_thread(thread_id);
+            self.forget_favorite_thread(thread_id);
+            threads.insert(thread_id.clone(), thread);
+        }
+        let author = self.get_topic_owner(topic_id).cloned()
+            .map(|x| self.snapshot_author(&x));
+        self.add_to_inspection(ModItem {
+            moderated: Utc::now(),
+            author,
+            thing: Moderatable::Topic(topic_id.clone(), topic, threads, replies),
+            reason,
+            appeal: None,
+        }, actor);
+    }
+
+    /// Blanks out what others get to see on the profile, the account itself stays usable
+    pub fn move_user_profile_to_inspection(&mut self, user_id: &UserID, actor: &UserID, reason: String) {
+        let Some(user) = self.users.get_mut(user_id) else {
             return;
         };
-        self.inspection.insert(id, ModItem {
+        let snapshot = user.clone();
+        user.display_name = None;
+        user.about = String::new();
+        user.pronouns = None;
+        store::store_user(user_id, user);
+        self.add_to_inspection(ModItem {
             moderated: Utc::now(),
-            thing: Moderatable::Reply(user, reply, thread_id.clone()),
+            author: Some((user_id.clone(), snapshot)),
+            thing: Moderatable::User,
+            reason,
+            appeal: None,
+        }, actor);
+    }
+
+    pub fn get_inspection_item(&self, id: &ModItemID) -> Option<&ModItem> {
+        self.inspection.get(id)
+    }
+
+    /// Newest first
+    pub fn get_sorted_inspection(&self) -> Vec<(&ModItemID, &ModItem)> {
+        let mut items = self.inspection.iter().collect::<Vec<_>>();
+        items.sort_unstable_by_key(|(_, x)| Reverse(x.moderated));
+        items
+    }
+
+    /// Whether the user may restore or delete the item, and put it into `target` if they picked one
+    pub fn can_handle_inspection_item(&self, user: &UserID, id: &ModItemID, target: Option<&str>) -> bool {
+        let Some(item) = self.inspection.get(id) else {
+            return false;
+        };
+        let allowed_into_target = match (&item.thing, target) {
+            (Moderatable::Reply(_, _, _, _), Some(target)) =>
+                self.can(user, Capability::ModerateTopic, Target::Thread(&ThreadID(target.to_string()))),
+            (Moderatable::Thread(_, _, _, _, _), Some(target)) =>
+                self.can(user, Capability::ModerateTopic, Target::Topic(&TopicID(target.to_string()))),
+            _ => true,
+        };
+        let (capability, item_target) = inspection_requirement(item);
+        allowed_into_target && self.can(user, capability, item_target)
+    }
+
+    /// Puts the item back where it was taken from, the author's account comes back with it if it's gone.
+    /// `target` is another thread for a reply or another topic for a thread to go to the end of instead,
+    /// for when the original one was deleted in the meantime
+    pub fn restore_from_inspection(&mut self, id: &ModItemID, target: Option<&str>, actor: &UserID) -> Result<(), RestoreError> {
+        let Some(item) = self.inspection.get(id) else {
+            return Err(RestoreError::NotFound);
+        };
+        match (&item.thing, target) {
+            (Moderatable::Reply(_, _, _, _), Some(target)) if !self.threads.contains_key(&ThreadID(target.to_string())) =>
+                return Err(RestoreError::TargetNotFound),
+            (Moderatable::Reply(_, _, thread_id, _), None) if !self.threads.contains_key(thread_id) =>
+                return Err(RestoreError::ThreadGone),
+            (Moderatable::Thread(_, _, _, _, _), Some(target)) if !self.topics.contains_key(&TopicID(target.to_string())) =>
+                return Err(RestoreError::TargetNotFound),
+            (Moderatable::Thread(_, _, _, topic_id, _), None) if !self.topics.contains_key(topic_id) =>
+                return Err(RestoreError::TopicGone),
+            (Moderatable::Topic(topic_id, _, _, _), _) if self.topics.contains_key(topic_id) =>
+                return Err(RestoreError::TopicTaken),
+            _ => {},
+        }
+        let item = self.inspection.remove(id).unwrap();
+        store::delete_inspection_item(id);
+        self.notify_item_outcome(&item, true);
+        let (topic, description, after) = self.describe_item(&item);
+        self.record(ModRecord {
+            created: Utc::now(),
+            actor: actor.clone(),
+            action: ModAction::Restore,
+            user: item.author_id().cloned(),
+            topic,
+            target: match target {
+                Some(target) => format!("{} into {}", description, target),
+                None => description,
+            },
+            reason: String::new(),
+            before: String::new(),
+            after,
         });
+        if let Some((user_id, user)) = item.author {
+            match self.users.entry(user_id) {
+                Entry::Vacant(entry) => {
+                    store::store_user(entry.key(), &user);
+                    entry.insert(user);
+                },
+                Entry::Occupied(mut entry) if matches!(item.thing, Moderatable::User) => {
+                    let current = entry.get_mut();
+                    current.display_name = user.display_name;
+                    current.about = user.about;
+                    current.pronouns = user.pronouns;
+                    store::store_user(entry.key(), entry.get());
+                },
+                Entry::Occupied(_) => {},
+            }
+        }
+        self.put_back(item.thing, target);
+        Ok(())
+    }
+
+    /// Where it came from or into `target` instead, which has to exist
+    pub(super) fn put_back(&mut self, thing: Moderatable, target: Option<&str>) {
+        match thing {
+            Moderatable::User => {},
+            Moderatable::Topic(topic_id, topic, threads, replies) => {
+                for (reply_id, reply) in replies {
+                    store::store_reply(&reply_id, &reply);
+                    self.replies.insert(reply_id, reply);
+                }
+                for (thread_id, thread) in threads {
+                    store::store_thread(&thread_id, &thread);
+                    self.threads.insert(thread_id, thread);
+                }
+                store::store_topic(&topic_id, &topic);
+                self.topics.insert(topic_id, topic);
+            },
+            Moderatable::Thread(thread_id, thread, replies, topic_id, position) => {
+                let (topic_id, position) = match target {
+                    Some(target) => (TopicID(target.to_string()), usize::MAX),
+                    None => (topic_id, position),
+                };
+                for (reply_id, reply) in replies {
+                    store::store_reply(&reply_id, &reply);
+                    self.replies.insert(reply_id, reply);
+                }
+                store::store_thread(&thread_id, &thread);
+                self.threads.insert(thread_id.clone(), thread);
+                let topic = self.topics.get_mut(&topic_id).unwrap();
+                topic.threads.insert(position.min(topic.threads.len()), thread_id);
+                store::store_topic(&topic_id, topic);
+            },
+            Moderatable::Reply(reply_id, reply, thread_id, position) => {
+                let (thread_id, position) = match target {
+                    Some(target) => (ThreadID(target.to_string()), usize::MAX),
+                    None => (thread_id, position),
+                };
+                let thread = self.threads.get_mut(&thread_id).unwrap();
+                thread.replies.insert(position.min(thread.replies.len()), reply_id.clone());
+                store::store_thread(&thread_id, thread);
+                store::store_reply(&reply_id, &reply);
+                self.replies.insert(reply_id, reply);
+            },
+        }
+    }
+
+    /// Gone for good, there's no copy left anywhere else.
+    /// Topics that went into inspection before their threads went along with them take those along now
+    pub fn delete_from_inspection(&mut self, id: &ModItemID, actor: &UserID) -> Option<ModItem> {
+        let item = self.inspection.remove(id);
+        store::delete_inspection_item(id);
+        if let Some(item) = &item {
+            self.notify_item_outcome(item, false);
+            let (topic, target, before) = self.describe_item(item);
+            self.record(ModRecord {
+                created: Utc::now(),
+                actor: actor.clone(),
+                action: ModAction::Purge,
+                user: item.author_id().cloned(),
+                topic,
+                target,
+                reason: String::new(),
+                before,
+                after: String::new(),
+            });
+        }
+        if let Some(ModItem { thing: Moderatable::Topic(_, topic, _, _), .. }) = &item {
+            for thread_id in &topic.threads {
+                let Some(thread) = self.threads.remove(thread_id) else {
+                    continue;
+                };
+                for reply_id in &thread.replies {
+                    self.replies.remove(reply_id);
+                    store::delete_reply(reply_id);
+                }
+                store::delete_thread(thread_id);
+                self.drop_reports_for_thread(thread_id);
+                self.forget_favorite_thread(thread_id);
+            }
+        }
+        item
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::scratch_store;
+    use super::*;
+
+    #[test]
+    fn topics_take_their_threads_into_inspection_and_back() {
+        let _store = scratch_store();
+        let mut db = DB::load();
+        let alice = db.create_new_user("alice", None);
+        let topic_id = db.create_new_topic(&alice, "cats", &alice).unwrap();
+        let thread_id = db.create_new_thread(&topic_id, "Hello".to_string(), &alice).unwrap();
+        let reply_id = db.try_reply("First", &thread_id, &alice, vec![]).unwrap();
+
+        db.move_topic_to_inspection(&topic_id, &alice, "spam".to_string());
+        let reloaded = DB::load();
+        for db in [&db, &reloaded] {
+            assert!(db.get_topic(&topic_id).is_none());
+            assert!(db.get_thread(&thread_id).is_none() && db.get_reply(&reply_id).is_none());
+        }
+
+        let mut db = reloaded;
+        let (item_id, _) = db.get_sorted_inspection()[0];
+        let item_id = item_id.clone();
+        db.restore_from_inspection(&item_id, None, &alice).unwrap();
+        let reloaded = DB::load();
+        for db in [&db, &reloaded] {
+            assert_eq!(db.get_topic(&topic_id).unwrap().threads, vec![thread_id.clone()]);
+            assert_eq!(db.get_thread(&thread_id).unwrap().replies, vec![reply_id.clone()]);
+            assert_eq!(db.get_reply(&reply_id).unwrap().content, "First");
+            assert!(db.get_inspection_item(&item_id).is_none());
+        }
+    }
+
+    #[test]
+    fn threads_go_back_where_they_were_or_into_another_topic() {
+        let _store = scratch_store();
+        let mut db = DB::load();
+        let alice = db.create_new_user("alice", None);
+        let cats = db.create_new_topic(&alice, "cats", &alice).unwrap();
+        let dogs = db.create_new_topic(&alice, "dogs", &alice).unwrap();
+        let threads = ["One", "Two", "Three"].map(|x| db.create_new_thread(&cats, x.to_string(), &alice).unwrap());
+        let order = db.get_topic(&cats).unwrap().threads.clone();
+
+        db.move_thread_to_inspection(&threads[1], &alice, String::new());
+        assert!(!db.get_topic(&cats).unwrap().threads.contains(&threads[1]));
+        let item_id = db.get_sorted_inspection()[0].0.clone();
+        db.restore_from_inspection(&item_id, None, &alice).unwrap();
+        assert_eq!(db.get_topic(&cats).unwrap().threads, order);
+        assert!(matches!(db.restore_from_inspection(&item_id, None, &alice), Err(RestoreError::NotFound)));
+
+        db.move_thread_to_inspection(&threads[1], &alice, String::new());
+        let item_id = db.get_sorted_inspection()[0].0.clone();
+        db.move_topic_to_inspection(&cats, &alice, String::new());
+        assert!(matches!(db.restore_from_inspection(&item_id, None, &alice), Err(RestoreError::TopicGone)));
+        assert!(matches!(db.restore_from_inspection(&item_id, Some("birds"), &alice), Err(RestoreError::TargetNotFound)));
+        db.restore_from_inspection(&item_id, Some("dogs"), &alice).unwrap();
+        assert_eq!(DB::load().get_topic(&dogs).unwrap().threads, vec![threads[1].clone()]);
     }
 }
\ No newline at end of file
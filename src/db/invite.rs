@@ -0,0 +1,68 @@
+use std::collections::HashMap;
+
+use chrono::Utc;
+
+use crate::data::{UserID, InviteID, Invite};
+
+use super::{DB, store};
+
+#[derive(thiserror::Error, Debug)]
+pub enum InviteError {
+    #[error("You've used up all your invites")]
+    QuotaExhausted,
+}
+
+impl DB {
+    pub fn create_invite(&mut self, user: &UserID) -> Result<InviteID, InviteError> {
+        if !self.is_admin(user) && self.invites_left(user) == 0 {
+            return Err(InviteError::QuotaExhausted);
+        }
+        let id = store::gen_invite_id();
+        let invite = Invite { inviter: user.clone(), created: Utc::now(), used_by: None };
+        store::store_invite(&id, &invite);
+        self.invites.insert(id.clone(), invite);
+        Ok(id)
+    }
+
+    pub fn invites_left(&self, user: &UserID) -> usize {
+        let used = self.invites.values().filter(|x| &x.inviter == user).count();
+        self.settings.invite_quota.saturating_sub(used)
+    }
+
+    pub fn get_invites_for_user(&self, user: &UserID) -> Vec<(&InviteID, &Invite)> {
+        let mut invites = self.invites.iter()
+            .filter(|(_, x)| &x.inviter == user)
+            .collect::<Vec<_>>();
+        invites.sort_unstable_by_key(|(_, x)| x.created);
+        invites
+    }
+
+    pub fn is_invite_usable(&self, id: &InviteID) -> bool {
+        self.invites.get(id).is_some_and(|x| x.used_by.is_none())
+    }
+
+    pub fn use_invite(&mut self, id: &InviteID, user: &UserID) {
+        if let Some(invite) = self.invites.get_mut(id) {
+            invite.used_by = Some(user.clone());
+            store::store_invite(id, invite);
+        }
+    }
+
+    /// Who invited whom, keyed by inviter. Users that joined without an invite aren't anyone's child
+    pub fn get_invite_tree(&self) -> HashMap<&UserID, Vec<&UserID>> {
+        let mut tree: HashMap<&UserID, Vec<&UserID>> = HashMap::new();
+        for invite in self.invites.values() {
+            if let Some(user) = &invite.used_by {
+                tree.entry(&invite.inviter).or_default().push(user);
+            }
+        }
+        tree.values_mut().for_each(|x| x.sort_unstable_by_key(|x| x.0.as_str()));
+        tree
+    }
+
+    pub fn get_inviter(&self, user: &UserID) -> Option<&UserID> {
+        self.invites.values()
+            .find(|x| x.used_by.as_ref() == Some(user))
+            .map(|x| &x.inviter)
+    }
+}
\ No newline at end of file
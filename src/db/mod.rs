@@ -2,13 +2,27 @@ use std::{collections::{HashMap, HashSet}};
 
 use chrono::Utc;
 
-use crate::{data::{Topic, User, UserID, TopicID, ThreadID, Thread, ReplyID, Reply, ModItemID, ModItem}, auth::PasswordStore};
-
+use crate::{data::{Topic, User, UserID, TopicID, ThreadID, Thread, ReplyID, Reply, ModItemID, ModItem, Ban, Settings, InviteID, Invite, RoleGrant, Roles, Reported, Report, NoteSubject, ModNote, Notice, ModRecord, ModAction, AutomodRule, AutomodHit}, auth::PasswordStore};
+
+pub mod account;
+pub mod appeal;
+pub mod approval;
+pub mod automod;
+pub mod ban;
+pub mod block;
 pub mod favorite;
 pub mod inspection;
+pub mod invite;
+pub mod note;
 pub mod permissions;
+pub mod record;
+pub mod rename;
+pub mod report;
 pub mod search;
 pub mod sequence;
+pub mod thread;
+pub mod topic;
+pub mod settings;
 pub mod store;
 
 #[derive(Default)]
@@ -18,15 +32,29 @@ pub struct DB {
     threads: HashMap<ThreadID, Thread>,
     replies: HashMap<ReplyID, Reply>,
 
-    permissions: HashMap<UserID, Vec<Permission>>,
+    permissions: HashMap<UserID, Vec<RoleGrant>>,
+    roles: Roles,
 
     inspection: HashMap<ModItemID, ModItem>,
-}
-
-#[derive(Debug, PartialEq, Eq)]
-pub enum Permission {
-    Overlord,
-    TopicOwner(TopicID),
+    /// First posts of new users, waiting to be approved
+    pending: HashMap<ModItemID, ModItem>,
+    bans: HashMap<UserID, Ban>,
+    reports: HashMap<Reported, Vec<Report>>,
+    notes: HashMap<NoteSubject, Vec<ModNote>>,
+    notices: HashMap<UserID, Vec<Notice>>,
+    records: Vec<ModRecord>,
+    /// Records are numbered in the order they're written, this is what the next one is called
+    next_record: usize,
+    automod_rules: Vec<AutomodRule>,
+    automod_hits: Vec<AutomodHit>,
+
+    invites: HashMap<InviteID, Invite>,
+
+    rename_requests: HashMap<UserID, UserID>,
+    user_redirects: HashMap<UserID, UserID>,
+    topic_redirects: HashMap<TopicID, TopicID>,
+
+    settings: Settings,
 }
 
 impl DB {
@@ -42,6 +70,22 @@ impl DB {
         self.threads = store::load_threads();
         self.replies = store::load_replies();
         self.permissions = store::load_permissions();
+        self.roles = store::load_roles();
+        self.inspection = store::load_inspection();
+        self.pending = store::load_pending();
+        self.bans = store::load_bans();
+        self.reports = store::load_reports();
+        self.notes = store::load_notes();
+        self.notices = store::load_notices();
+        self.records = store::load_records();
+        self.next_record = store::count_records();
+        self.automod_rules = store::load_automod_rules();
+        self.automod_hits = store::load_automod_hits();
+        self.invites = store::load_invites();
+        self.rename_requests = rename::load_rename_requests();
+        self.user_redirects = rename::load_user_redirects();
+        self.topic_redirects = store::load_topic_redirects();
+        self.settings = store::load_settings();
     }
 
     pub fn get_topic(&self, name: &TopicID) -> Option<&Topic> {
@@ -59,42 +103,37 @@ impl DB {
     pub fn get_reply(&self, name: &ReplyID) -> Option<&Reply> {
         self.replies.get(name)
     }
-}
 
-impl DB {
-    pub fn create_new_topic(&mut self, owner: &UserID, name: &str) -> Result<TopicID, ()> {
-        let id = TopicID(name.to_string());
-        if self.topics.contains_key(&id) {
-            Err(())
-        } else {
-            let topic = Topic::default();
-            store::store_topic(&id, &topic);
-            self.topics.insert(id.clone(), topic);
-            if let Some(permissions) = self.permissions.get_mut(owner) {
-                permissions.push(Permission::TopicOwner(id.clone()));
-            } else {
-                self.permissions.insert(owner.clone(), vec![Permission::TopicOwner(id.clone())]);
-            }
-            Ok(id)
-        }
+    pub fn get_thread_topic(&self, thread: &ThreadID) -> Option<&TopicID> {
+        self.topics.iter()
+            .find(|(_, x)| x.threads.contains(thread))
+            .map(|(id, _)| id)
     }
+}
 
-    pub fn create_new_user(&mut self, name: &str, password_store: &PasswordStore) -> UserID {
+impl DB {
+    /// Accounts made through single sign-on have no password
+    pub fn create_new_user(&mut self, name: &str, password_store: Option<&PasswordStore>) -> UserID {
         let id = UserID(name.to_string());
         if self.users.contains_key(&id) {
             panic!("User already exists")
         } else {
-            let user = User::default();
+            let user = User { joined: Some(Utc::now()), ..Default::default() };
             store::store_user(&id, &user);
-            store::store_user_auth(name, password_store);
+            if let Some(password_store) = password_store {
+                store::store_user_auth(name, password_store);
+            }
             self.users.insert(id.clone(), user);
             id
         }
     }
 
-    pub fn create_new_thread(&mut self, topic_id: &TopicID, title: String) -> Option<ThreadID> {
+    pub fn create_new_thread(&mut self, topic_id: &TopicID, title: String, author: &UserID) -> Option<ThreadID> {
+        if !self.can_post(author) {
+            return None;
+        }
         let id = store::gen_thread_id();
-        let thread = Thread { title, replies: vec![] };
+        let thread = Thread { title, replies: vec![], locked: false, pinned: false };
         self.topics.get_mut(topic_id).map(|topic| {
             store::store_thread(&id, &thread);
             self.threads.insert(id.clone(), thread);
@@ -104,9 +143,9 @@ impl DB {
         })
     }
 
-    pub fn try_reply(&mut self, content: &str, thread_id: &ThreadID, user: &UserID) -> Option<ReplyID> {
-        if self.threads.contains_key(thread_id) && self.users.contains_key(user) {
-            let reply = Reply { created: Utc::now(), user: user.clone(), content: content.to_string() };
+    pub fn try_reply(&mut self, content: &str, thread_id: &ThreadID, user: &UserID, tags: Vec<String>) -> Option<ReplyID> {
+        if self.threads.get(thread_id).is_some_and(|x| !x.locked) && self.users.contains_key(user) && self.can_post(user) {
+            let reply = Reply { created: Utc::now(), user: user.clone(), content: content.to_string(), tags, shadowed: self.is_shadow_banned(user) };
             let id = store::gen_reply_id();
             store::store_reply(&id, &reply);
             self.replies.insert(id.clone(), reply);
@@ -119,8 +158,17 @@ impl DB {
         }
     }
 
-    pub fn update_user(&mut self, user_id: &UserID, about: String, pronouns: Option<[String; 3]>) {
+    pub fn update_topic_about(&mut self, topic_id: &TopicID, about: String) {
+        let Some(topic) = self.topics.get_mut(topic_id) else {
+            return;
+        };
+        topic.about = about;
+        store::store_topic(topic_id, topic);
+    }
+
+    pub fn update_user(&mut self, user_id: &UserID, display_name: Option<String>, about: String, pronouns: Option<[String; 3]>) {
         let user = self.users.get_mut(user_id).unwrap();
+        user.display_name = display_name;
         user.about = about;
         user.pronouns = pronouns;
         store::store_user(user_id, user)
@@ -139,6 +187,28 @@ impl DB {
         };
         store::store_thread(thread_id, thread);
         store::delete_reply(reply_id);
+        self.forget_reports(&Reported::Reply(thread_id.clone(), reply_id.clone()));
+        Some(reply)
+    }
+
+    /// Deleting someone else's reply is moderation and gets recorded, taking back your own isn't
+    pub fn delete_reply_by(&mut self, thread_id: &ThreadID, reply_id: &ReplyID, actor: &UserID) -> Option<Reply> {
+        let topic = self.get_thread_topic(thread_id).cloned();
+        let target = format!("in \"{}\"", self.get_thread(thread_id).map_or("", |x| x.title.as_str()));
+        let reply = self.delete_reply(thread_id, reply_id)?;
+        if &reply.user != actor {
+            self.record(ModRecord {
+                created: Utc::now(),
+                actor: actor.clone(),
+                action: ModAction::DeleteReply,
+                user: Some(reply.user.clone()),
+                topic,
+                target,
+                reason: String::new(),
+                before: reply.content.clone(),
+                after: String::new(),
+            });
+        }
         Some(reply)
     }
 
@@ -163,6 +233,8 @@ impl DB {
         }
         store::store_topic(topic_id, topic);
         store::delete_thread(thread_id);
+        self.drop_reports_for_thread(thread_id);
+        self.forget_favorite_thread(thread_id);
         Some((thread, replies))
     }
 }
\ No newline at end of file
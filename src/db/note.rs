@@ -0,0 +1,94 @@
+use chrono::Utc;
+
+use crate::data::{UserID, NoteSubject, ModNote, NoteRevision, Capability};
+
+use super::{DB, store, permissions::Target};
+
+pub const MAX_NOTE_LENGTH: usize = 2000;
+
+#[derive(thiserror::Error, Debug)]
+pub enum NoteError {
+    #[error("Notes can't be empty")]
+    Empty,
+    #[error("Notes can't be longer than {MAX_NOTE_LENGTH} characters")]
+    TooLong,
+    #[error("There's no such note")]
+    NotFound,
+}
+
+fn validate_note(content: &str) -> Result<(), NoteError> {
+    if content.trim().is_empty() {
+        Err(NoteError::Empty)
+    } else if content.chars().count() > MAX_NOTE_LENGTH {
+        Err(NoteError::TooLong)
+    } else {
+        Ok(())
+    }
+}
+
+impl DB {
+    /// Notes about accounts are for whoever manages users, the ones about posts for whoever moderates them
+    pub fn can_see_notes(&self, user: &UserID, subject: &NoteSubject) -> bool {
+        match subject {
+            NoteSubject::User(_) => self.can(user, Capability::ManageUsers, Target::Site),
+            NoteSubject::Post(reported) => self.can(user, Capability::ModerateTopic, Target::Thread(reported.thread())),
+        }
+    }
+
+    /// Oldest first
+    pub fn get_notes(&self, subject: &NoteSubject) -> &[ModNote] {
+        self.notes.get(subject).map_or(&[], |x| x.as_slice())
+    }
+
+    pub fn add_note(&mut self, subject: NoteSubject, content: String, author: &UserID) -> Result<(), NoteError> {
+        validate_note(&content)?;
+        self.notes.entry(subject).or_default().push(ModNote {
+            revisions: vec![NoteRevision { author: author.clone(), created: Utc::now(), content }],
+        });
+        store::store_notes(&self.notes);
+        Ok(())
+    }
+
+    /// The earlier text stays around in the note's history
+    pub fn edit_note(&mut self, subject: &NoteSubject, index: usize, content: String, author: &UserID) -> Result<(), NoteError> {
+        validate_note(&content)?;
+        let note = self.notes.get_mut(subject).and_then(|x| x.get_mut(index)).ok_or(NoteError::NotFound)?;
+        if note.current().content != content {
+            note.revisions.push(NoteRevision { author: author.clone(), created: Utc::now(), content });
+            store::store_notes(&self.notes);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{data::{Reported, RoleGrant, RoleID, Roles}, testing::scratch_store};
+    use super::*;
+
+    #[test]
+    fn notes_keep_their_history_and_stay_among_moderators() {
+        let _store = scratch_store();
+        let mut db = DB::load();
+        let [alice, bob, carol] = ["alice", "bob", "carol"].map(|x| db.create_new_user(x, None));
+        db.grant_permission(&alice, RoleGrant { role: RoleID(Roles::OVERLORD.to_string()), topic: None }, &alice);
+        let topic = db.create_new_topic(&bob, "cats", &alice).unwrap();
+        let thread = db.create_new_thread(&topic, "Hello".to_string(), &carol).unwrap();
+        let about_carol = NoteSubject::User(carol.clone());
+        let about_thread = NoteSubject::Post(Reported::Thread(thread));
+
+        assert!(db.can_see_notes(&alice, &about_carol) && db.can_see_notes(&alice, &about_thread));
+        // Owning the topic is enough for posts in it but not for accounts
+        assert!(db.can_see_notes(&bob, &about_thread) && !db.can_see_notes(&bob, &about_carol));
+        assert!(!db.can_see_notes(&carol, &about_thread));
+
+        assert!(matches!(db.add_note(about_carol.clone(), " ".to_string(), &alice), Err(NoteError::Empty)));
+        db.add_note(about_carol.clone(), "Keeps posting links".to_string(), &alice).unwrap();
+        db.edit_note(&about_carol, 0, "Keeps posting spam".to_string(), &bob).unwrap();
+        assert!(matches!(db.edit_note(&about_carol, 1, "x".to_string(), &bob), Err(NoteError::NotFound)));
+        let db = DB::load();
+        let note = &db.get_notes(&about_carol)[0];
+        assert_eq!(note.revisions.len(), 2);
+        assert!(note.current().content == "Keeps posting spam" && note.current().author == bob);
+    }
+}
\ No newline at end of file
@@ -1,28 +1,226 @@
-use crate::{data::{UserID, TopicID}, db::Permission};
+use chrono::Utc;
 
-use super::DB;
+use crate::data::{UserID, TopicID, ThreadID, ReplyID, RoleID, RoleGrant, Roles, Capability, ModRecord, ModAction};
+
+use super::{DB, store};
+
+/// Where a [Capability] gets used, a thread counts as part of the topic it's in
+#[derive(Debug, Clone, Copy)]
+pub enum Target<'a> {
+    Site,
+    Topic(&'a TopicID),
+    Thread(&'a ThreadID),
+}
 
 impl DB {
-    pub fn is_admin(&self, user: &UserID) -> bool {
-        let Some(permissions) = self.permissions.get(user) else {
-            return false
+    /// Site-wide roles apply everywhere, the ones granted in a topic only inside of it.
+    /// Banned users can't do anything, whichever way they got in
+    pub fn can(&self, user: &UserID, capability: Capability, target: Target) -> bool {
+        if self.is_banned(user) {
+            return false;
+        }
+        let topic = match target {
+            Target::Site => None,
+            Target::Topic(topic) => Some(topic),
+            Target::Thread(thread) => self.get_thread_topic(thread),
         };
-        permissions.iter().any(|x| matches!(x, Permission::Overlord))
-    }
-    pub fn grant_permission(&mut self, user: &UserID, permission: Permission) {
-        if let Some(permissions) = self.permissions.get_mut(user) {
-            permissions.push(permission);
-        } else {
-            self.permissions.insert(user.clone(), vec![permission]);
+        let archived = topic.and_then(|x| self.topics.get(x)).is_some_and(|x| x.archived);
+        if archived && matches!(capability, Capability::Post | Capability::CreateThread) {
+            return false;
         }
+        self.get_roles(user, topic).into_iter().any(|x| self.roles.allows(x, capability))
     }
-    pub fn revoke_permission(&mut self, user: &UserID, permission: Permission) -> bool {
-        if let Some(permissions) = self.permissions.get_mut(user) {
-            if let Some(p) = permissions.iter().position(|p| p == &permission) {
-                permissions.remove(p);
-                return true;
-            }
+
+    /// Everyone gets the default role unless they were granted a site-wide one.
+    /// Moderators that didn't enroll in 2FA while it's mandatory are left with just that
+    fn get_roles(&self, user: &UserID, topic: Option<&TopicID>) -> Vec<&RoleID> {
+        let grants = match self.permissions.get(user) {
+            Some(grants) if !self.lacks_required_2fa(user) => grants.as_slice(),
+            _ => &[],
+        };
+        let mut roles = grants.iter()
+            .filter(|x| x.topic.is_none() || x.topic.as_ref() == topic)
+            .map(|x| &x.role)
+            .collect::<Vec<_>>();
+        if grants.iter().all(|x| x.topic.is_some()) {
+            roles.push(&self.roles.default);
         }
-        false
+        roles
+    }
+
+    /// Authors may take back what they said, moderators may take away anything in their topic
+    pub fn can_delete_reply(&self, user: &UserID, thread: &ThreadID, reply: &ReplyID) -> bool {
+        let is_author = self.get_reply(reply).is_some_and(|x| &x.user == user);
+        (is_author && self.can(user, Capability::Post, Target::Thread(thread)))
+            || self.can(user, Capability::ModerateTopic, Target::Thread(thread))
+    }
+
+    pub fn is_admin(&self, user: &UserID) -> bool {
+        self.can(user, Capability::ManageSite, Target::Site)
+    }
+
+    /// Whether the user holds a role with moderation powers anywhere, 2FA or not
+    fn has_moderation_role(&self, user: &UserID) -> bool {
+        self.permissions.get(user).is_some_and(|grants| grants.iter()
+            .any(|x| Capability::ALL.into_iter().any(|c| c.is_moderation() && self.roles.allows(&x.role, c))))
+    }
+
+    /// Whether there's anywhere at all this user can moderate
+    pub fn is_moderator(&self, user: &UserID) -> bool {
+        self.has_moderation_role(user) && !self.lacks_required_2fa(user)
+    }
+
+    /// Whether the site requires this user to have 2FA because of their moderation powers
+    pub fn requires_2fa(&self, user: &UserID) -> bool {
+        self.settings.require_mod_2fa && self.has_moderation_role(user)
+    }
+
+    /// Moderators that didn't enroll in 2FA while it's mandatory don't get to use their powers
+    pub fn lacks_required_2fa(&self, user: &UserID) -> bool {
+        self.requires_2fa(user) && store::load_user_totp(&user.0).is_none()
+    }
+
+    /// Topics made by hand in the store have nobody
+    pub fn get_topic_owner(&self, topic: &TopicID) -> Option<&UserID> {
+        self.permissions.iter()
+            .find(|(_, x)| x.iter().any(|x| x.role.0 == Roles::OWNER && x.topic.as_ref() == Some(topic)))
+            .map(|(user, _)| user)
+    }
+
+    pub fn grant_permission(&mut self, user: &UserID, grant: RoleGrant, actor: &UserID) {
+        let before = self.describe_grants(user);
+        self.add_grant(user, grant.clone());
+        self.record_grant_change(ModAction::Grant, user, actor, &grant, before);
+    }
+
+    /// Without a record, for grants that come with something else that gets recorded
+    pub(super) fn add_grant(&mut self, user: &UserID, grant: RoleGrant) {
+        self.permissions.entry(user.clone()).or_default().push(grant);
+        store::store_permissions(&self.permissions);
+    }
+
+    pub fn revoke_permission(&mut self, user: &UserID, grant: RoleGrant, actor: &UserID) -> bool {
+        let before = self.describe_grants(user);
+        let Some(permissions) = self.permissions.get_mut(user) else {
+            return false;
+        };
+        let Some(p) = permissions.iter().position(|p| p == &grant) else {
+            return false;
+        };
+        permissions.remove(p);
+        store::store_permissions(&self.permissions);
+        self.record_grant_change(ModAction::Revoke, user, actor, &grant, before);
+        true
+    }
+
+    fn describe_grants(&self, user: &UserID) -> String {
+        self.permissions.get(user).map_or_else(String::new, |grants| grants.iter()
+            .map(|x| match &x.topic {
+                Some(topic) => format!("{} of λ/{}", x.role.0, topic.0),
+                None => x.role.0.clone(),
+            })
+            .collect::<Vec<_>>().join(", "))
+    }
+
+    fn record_grant_change(&mut self, action: ModAction, user: &UserID, actor: &UserID, grant: &RoleGrant, before: String) {
+        self.record(ModRecord {
+            created: Utc::now(),
+            actor: actor.clone(),
+            action,
+            user: Some(user.clone()),
+            topic: grant.topic.clone(),
+            target: format!("{} for @{}", grant.role.0, user.0),
+            reason: String::new(),
+            before,
+            after: self.describe_grants(user),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{data::Capability::*, testing::scratch_store};
+    use super::*;
+
+    #[test]
+    fn owners_only_moderate_their_own_topic() {
+        let _store = scratch_store();
+        let mut db = DB::load();
+        let alice = db.create_new_user("alice", None);
+        let bob = db.create_new_user("bob", None);
+        let cats = db.create_new_topic(&alice, "cats", &alice).unwrap();
+        let dogs = db.create_new_topic(&bob, "dogs", &bob).unwrap();
+        let thread = db.create_new_thread(&cats, "Hello".to_string(), &bob).unwrap();
+
+        assert_eq!(db.get_topic_owner(&cats), Some(&alice));
+        assert!(db.can(&alice, ModerateTopic, Target::Topic(&cats)));
+        assert!(db.can(&alice, ModerateTopic, Target::Thread(&thread)));
+        assert!(!db.can(&alice, ModerateTopic, Target::Topic(&dogs)));
+        assert!(!db.can(&alice, ModerateTopic, Target::Site));
+        assert!(!db.can(&bob, ModerateTopic, Target::Thread(&thread)));
+        // Starting the thread doesn't make anyone a moderator of it
+        let reply = db.try_reply("Hi", &thread, &alice, vec![]).unwrap();
+        assert!(!db.can_delete_reply(&bob, &thread, &reply));
+        let reply = db.try_reply("Hi", &thread, &bob, vec![]).unwrap();
+        assert!(db.can_delete_reply(&bob, &thread, &reply) && db.can_delete_reply(&alice, &thread, &reply));
+    }
+
+    fn grant(db: &mut DB, user: &UserID, role: &str, topic: Option<&TopicID>) {
+        let grant = RoleGrant { role: RoleID(role.to_string()), topic: topic.cloned() };
+        db.grant_permission(user, grant, user);
+    }
+
+    #[test]
+    fn site_wide_roles_replace_the_default_one() {
+        let _store = scratch_store();
+        let mut db = DB::load();
+        let [alice, bob, carol, dave] = ["alice", "bob", "carol", "dave"].map(|x| db.create_new_user(x, None));
+        let cats = db.create_new_topic(&alice, "cats", &alice).unwrap();
+        grant(&mut db, &alice, Roles::OVERLORD, None);
+        grant(&mut db, &bob, "bot", None);
+        grant(&mut db, &carol, "moderator", None);
+        grant(&mut db, &dave, "trusted", Some(&cats));
+
+        assert!(Capability::ALL.into_iter().all(|x| db.can(&alice, x, Target::Site)));
+        assert!(db.can(&bob, Post, Target::Topic(&cats)) && !db.can(&bob, CreateThread, Target::Topic(&cats)));
+        assert!(db.can(&carol, ModerateTopic, Target::Topic(&cats)) && db.can(&carol, ManageUsers, Target::Site));
+        assert!(!db.can(&carol, ManageTopics, Target::Site) && !db.can(&carol, ManageSite, Target::Site));
+        assert!(db.is_moderator(&carol) && !db.is_admin(&carol));
+        // A role inside of a topic comes on top of the default one
+        assert!(db.can(&dave, TrustedReports, Target::Topic(&cats)) && !db.can(&dave, TrustedReports, Target::Site));
+        assert!(db.can(&dave, CreateThread, Target::Site));
+
+        db.set_topic_archived(&cats, true, &alice).unwrap();
+        assert!(!db.can(&alice, Post, Target::Topic(&cats)) && db.can(&alice, ModerateTopic, Target::Topic(&cats)));
+    }
+
+    #[test]
+    fn roles_come_from_the_store() {
+        let _store = scratch_store();
+        std::fs::create_dir_all(store::MOD_PATH).unwrap();
+        std::fs::write(store::MOD_PATH.to_string() + "/roles.json", r#"{
+            "default": "reader",
+            "roles": { "reader": [], "writer": ["post", "upload"] }
+        }"#).unwrap();
+        let mut db = DB::load();
+        let [alice, bob] = ["alice", "bob"].map(|x| db.create_new_user(x, None));
+        grant(&mut db, &bob, "writer", None);
+        assert!(!db.can(&alice, Post, Target::Site));
+        assert!(db.can(&bob, Post, Target::Site) && db.can(&bob, Upload, Target::Site) && !db.can(&bob, CreateThread, Target::Site));
+        // Roles nobody configured don't allow anything
+        grant(&mut db, &alice, "moderator", None);
+        assert!(!db.can(&alice, ModerateTopic, Target::Site) && !db.can(&alice, Post, Target::Site));
+    }
+
+    #[test]
+    fn moderators_without_required_2fa_fall_back_to_the_default_role() {
+        let _store = scratch_store();
+        let mut db = DB::load();
+        let alice = db.create_new_user("alice", None);
+        grant(&mut db, &alice, "moderator", None);
+        db.settings.require_mod_2fa = true;
+        assert!(db.requires_2fa(&alice) && db.lacks_required_2fa(&alice));
+        assert!(!db.is_moderator(&alice) && !db.can(&alice, ModerateTopic, Target::Site));
+        assert!(db.can(&alice, Post, Target::Site));
     }
 }
\ No newline at end of file
@@ -0,0 +1,70 @@
+use std::cmp::Reverse;
+
+use crate::data::{TopicID, ModItem, Moderatable, ModRecord, RecordFilter};
+
+use super::{DB, store};
+
+impl DB {
+    pub(super) fn record(&mut self, record: ModRecord) {
+        store::store_record(self.next_record, &record);
+        self.next_record += 1;
+        self.records.push(record);
+    }
+
+    /// Newest first
+    pub fn get_records(&self, filter: &RecordFilter) -> Vec<&ModRecord> {
+        let mut records = self.records.iter()
+            .filter(|x| filter.matches(x))
+            .collect::<Vec<_>>();
+        records.sort_by_key(|x| Reverse(x.created));
+        records
+    }
+
+    /// Where the item was taken from, what to call it and what it said
+    pub(super) fn describe_item(&self, item: &ModItem) -> (Option<TopicID>, String, String) {
+        match &item.thing {
+            Moderatable::User => (
+                None,
+                format!("profile of @{}", item.author_id().map_or("", |x| x.0.as_str())),
+                item.author.as_ref().map_or_else(String::new, |(_, user)| format!("{}\n{}", user.display_name.as_deref().unwrap_or_default(), user.about)),
+            ),
+            Moderatable::Topic(topic_id, topic, _, _) => (Some(topic_id.clone()), "topic".to_string(), topic.about.clone()),
+            Moderatable::Thread(_, thread, replies, topic_id, _) => (
+                Some(topic_id.clone()),
+                format!("thread \"{}\"", thread.title),
+                thread.replies.first().and_then(|x| replies.get(x)).map_or_else(String::new, |x| x.content.clone()),
+            ),
+            Moderatable::Reply(_, reply, thread_id, _) => (
+                self.get_thread_topic(thread_id).cloned(),
+                format!("reply in \"{}\"", self.get_thread(thread_id).map_or(thread_id.0.as_str(), |x| x.title.as_str())),
+                reply.content.clone(),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{data::{ModAction, RoleGrant, RoleID}, testing::scratch_store};
+    use super::*;
+
+    #[test]
+    fn records_are_kept_in_order_across_restarts() {
+        let _store = scratch_store();
+        let mut db = DB::load();
+        let [alice, bob] = ["alice", "bob"].map(|x| db.create_new_user(x, None));
+        let topic = db.create_new_topic(&bob, "cats", &alice).unwrap();
+        db.grant_permission(&bob, RoleGrant { role: RoleID("trusted".to_string()), topic: None }, &alice);
+        db.set_topic_archived(&topic, true, &alice).unwrap();
+
+        let mut db = DB::load();
+        let actions = |db: &DB| db.get_records(&RecordFilter::default()).into_iter()
+            .rev().map(|x| x.action).collect::<Vec<_>>();
+        // Owning the new topic is part of creating it, not a grant of its own
+        assert_eq!(actions(&db), vec![ModAction::CreateTopic, ModAction::Grant, ModAction::Archive]);
+        assert_eq!(db.get_topic_owner(&topic), Some(&bob));
+        db.set_topic_archived(&topic, false, &alice).unwrap();
+        assert_eq!(DB::load().records.len(), 4);
+        assert_eq!(db.get_records(&RecordFilter { topic: Some(topic.clone()), ..Default::default() }).len(), 3);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,165 @@
+use crate::{data::{UserID, Moderatable, NoteSubject}, auth::username::{self, UserNameError}};
+
+use super::{DB, store};
+
+const RENAME_REQUESTS: &str = "rename-requests";
+const USER_REDIRECTS: &str = "user-redirects";
+
+impl DB {
+    /// Handles that are in use, or were in use and still redirect to someone.
+    /// `ignore` lets a user change the capitalization of their own handle
+    pub fn is_user_name_taken(&self, name: &str, ignore: Option<&UserID>) -> bool {
+        let folded = username::fold(name);
+        self.users.keys()
+            .chain(self.user_redirects.keys())
+            .filter(|x| Some(*x) != ignore)
+            .any(|x| username::fold(&x.0) == folded)
+    }
+
+    pub fn request_rename(&mut self, user: &UserID, new_name: &str) -> Result<(), UserNameError> {
+        username::validate_user_name_format(new_name)?;
+        if self.is_user_name_taken(new_name, Some(user)) {
+            return Err(UserNameError::Taken);
+        }
+        self.rename_requests.insert(user.clone(), UserID(new_name.to_string()));
+        store::store_user_name_map(RENAME_REQUESTS, &self.rename_requests);
+        Ok(())
+    }
+
+    pub fn get_rename_request(&self, user: &UserID) -> Option<&UserID> {
+        self.rename_requests.get(user)
+    }
+
+    pub fn get_rename_requests(&self) -> Vec<(&UserID, &UserID)> {
+        let mut requests = self.rename_requests.iter().collect::<Vec<_>>();
+        requests.sort_unstable_by_key(|(x, _)| x.0.as_str());
+        requests
+    }
+
+    pub fn cancel_rename_request(&mut self, user: &UserID) -> Option<UserID> {
+        let request = self.rename_requests.remove(user);
+        store::store_user_name_map(RENAME_REQUESTS, &self.rename_requests);
+        request
+    }
+
+    pub fn get_user_redirect(&self, old_name: &UserID) -> Option<&UserID> {
+        self.user_redirects.get(old_name)
+    }
+
+    /// Moves everything that refers to the user over to the new handle and leaves a redirect behind.
+    /// Passwords, sessions and API tokens are moved separately by [crate::auth::Auth::rename_user]
+    pub fn rename_user(&mut self, old: &UserID, new: &UserID) -> Result<(), UserNameError> {
+        username::validate_user_name_format(&new.0)?;
+        if !self.users.contains_key(old) {
+            return Err(UserNameError::NotFound);
+        } else if self.is_user_name_taken(&new.0, Some(old)) {
+            return Err(UserNameError::Taken);
+        }
+        let user = self.users.remove(old).unwrap();
+        store::delete_user(old);
+        store::store_user(new, &user);
+        self.users.insert(new.clone(), user);
+        for (id, user) in self.users.iter_mut().filter(|(_, x)| x.blocked.contains(old)) {
+            user.blocked.iter_mut()
+                .filter(|x| *x == old)
+                .for_each(|x| *x = new.clone());
+            store::store_user(id, user);
+        }
+
+        for (id, reply) in self.replies.iter_mut().filter(|(_, x)| &x.user == old) {
+            reply.user = new.clone();
+            store::store_reply(id, reply);
+        }
+        if let Some(permissions) = self.permissions.remove(old) {
+            self.permissions.insert(new.clone(), permissions);
+            store::store_permissions(&self.permissions);
+        }
+        if let Some(notices) = self.notices.remove(old) {
+            self.notices.insert(new.clone(), notices);
+            store::store_notices(&self.notices);
+        }
+        if let Some(notes) = self.notes.remove(&NoteSubject::User(old.clone())) {
+            self.notes.insert(NoteSubject::User(new.clone()), notes);
+            store::store_notes(&self.notes);
+        }
+        for (id, invite) in self.invites.iter_mut() {
+            let mut changed = false;
+            if &invite.inviter == old {
+                invite.inviter = new.clone();
+                changed = true;
+            }
+            if invite.used_by.as_ref() == Some(old) {
+                invite.used_by = Some(new.clone());
+                changed = true;
+            }
+            if changed {
+                store::store_invite(id, invite);
+            }
+        }
+        for (id, item) in self.inspection.iter_mut() {
+            let mut changed = false;
+            if let Some((author, _)) = item.author.as_mut().filter(|(x, _)| x == old) {
+                *author = new.clone();
+                changed = true;
+            }
+            let replies: Vec<_> = match &mut item.thing {
+                Moderatable::Reply(_, reply, _, _) => vec![reply],
+                Moderatable::Thread(_, _, replies, _, _) | Moderatable::Topic(_, _, _, replies) => replies.values_mut().collect(),
+                _ => vec![],
+            };
+            for reply in replies.into_iter().filter(|x| &x.user == old) {
+                reply.user = new.clone();
+                changed = true;
+            }
+            if changed {
+                store::store_inspection_item(id, item);
+            }
+        }
+
+        self.rename_requests.remove(old);
+        store::store_user_name_map(RENAME_REQUESTS, &self.rename_requests);
+        // Old links keep working, and earlier renames point straight at the newest handle
+        self.user_redirects.remove(new);
+        self.user_redirects.values_mut()
+            .filter(|x| *x == old)
+            .for_each(|x| *x = new.clone());
+        if old != new {
+            self.user_redirects.insert(old.clone(), new.clone());
+        }
+        store::store_user_name_map(USER_REDIRECTS, &self.user_redirects);
+        Ok(())
+    }
+}
+
+pub(super) fn load_rename_requests() -> std::collections::HashMap<UserID, UserID> {
+    store::load_user_name_map(RENAME_REQUESTS)
+}
+
+pub(super) fn load_user_redirects() -> std::collections::HashMap<UserID, UserID> {
+    store::load_user_name_map(USER_REDIRECTS)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::scratch_store;
+    use super::*;
+
+    #[test]
+    fn renames_need_an_existing_user_and_a_free_name() {
+        let _store = scratch_store();
+        let mut db = DB::load();
+        let alice = db.create_new_user("alice", None);
+        let bob = db.create_new_user("bob", None);
+        let nobody = UserID("nobody".to_string());
+        assert!(matches!(db.rename_user(&nobody, &UserID("somebody".to_string())), Err(UserNameError::NotFound)));
+        assert!(matches!(db.rename_user(&alice, &UserID("Bob".to_string())), Err(UserNameError::Taken)));
+
+        let alicia = UserID("alicia".to_string());
+        db.rename_user(&alice, &alicia).unwrap();
+        assert!(db.get_user(&alice).is_none() && db.get_user(&alicia).is_some());
+        assert_eq!(db.get_user_redirect(&alice), Some(&alicia));
+        // The old handle stays reserved for the redirect
+        assert!(matches!(db.rename_user(&bob, &alice), Err(UserNameError::Taken)));
+        assert_eq!(DB::load().get_user_redirect(&alice), Some(&alicia));
+    }
+}
\ No newline at end of file
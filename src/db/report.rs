@@ -0,0 +1,149 @@
+use std::cmp::Reverse;
+
+use chrono::Utc;
+
+use crate::data::{UserID, ThreadID, Reported, Report, ReportReason, Capability, ModRecord, ModAction};
+
+use super::{DB, store, permissions::Target};
+
+pub const MAX_REPORT_NOTE_LENGTH: usize = 1000;
+
+#[derive(thiserror::Error, Debug)]
+pub enum ReportError {
+    #[error("There's nothing like that to report")]
+    NotFound,
+    #[error("You can't report what you wrote yourself")]
+    OwnContent,
+    #[error("The note can't be longer than {MAX_REPORT_NOTE_LENGTH} characters")]
+    NoteTooLong,
+}
+
+impl DB {
+    /// Reporting the same thing again replaces the earlier report, so every reporter is only counted once
+    pub fn report(&mut self, reporter: &UserID, reported: Reported, reason: ReportReason, note: String) -> Result<(), ReportError> {
+        if note.chars().count() > MAX_REPORT_NOTE_LENGTH {
+            return Err(ReportError::NoteTooLong);
+        }
+        let Some(thread) = self.threads.get(reported.thread()) else {
+            return Err(ReportError::NotFound);
+        };
+        let author = match &reported {
+            Reported::Thread(_) => thread.replies.first(),
+            Reported::Reply(_, reply) => thread.replies.iter().find(|x| *x == reply),
+        }.and_then(|x| self.replies.get(x)).map(|x| &x.user);
+        match author {
+            None if matches!(reported, Reported::Reply(_, _)) => return Err(ReportError::NotFound),
+            Some(author) if author == reporter => return Err(ReportError::OwnContent),
+            _ => {},
+        }
+        let reports = self.reports.entry(reported).or_default();
+        reports.retain(|x| &x.reporter != reporter);
+        reports.push(Report { reporter: reporter.clone(), reason, note, created: Utc::now() });
+        store::store_reports(&self.reports);
+        Ok(())
+    }
+
+    /// Enough trusted people reported it and no moderator dealt with it yet
+    pub fn is_hidden(&self, reported: &Reported) -> bool {
+        let threshold = self.settings.report_threshold;
+        let Some(reports) = self.reports.get(reported) else {
+            return false;
+        };
+        threshold > 0 && reports.iter()
+            .filter(|x| self.can(&x.reporter, Capability::TrustedReports, Target::Thread(reported.thread())))
+            .count() >= threshold
+    }
+
+    /// Moderators still get to see hidden things, to decide what to do about them.
+    /// Shadowed posts are hidden the same way, except from their own author
+    pub fn is_hidden_from(&self, viewer: Option<&UserID>, reported: &Reported) -> bool {
+        (self.is_hidden(reported) || self.get_shadowed_author(reported).is_some_and(|x| viewer != Some(x)))
+            && !viewer.is_some_and(|x| self.can(x, Capability::ModerateTopic, Target::Thread(reported.thread())))
+    }
+
+    /// Most reported first
+    pub fn get_sorted_reports(&self) -> Vec<(&Reported, &Vec<Report>)> {
+        let mut reports = self.reports.iter().collect::<Vec<_>>();
+        reports.sort_unstable_by_key(|(_, x)| (Reverse(x.len()), Reverse(x.iter().map(|x| x.created).max())));
+        reports
+    }
+
+    /// Nothing wrong with it after all, it shows up again if it was hidden
+    pub fn dismiss_reports(&mut self, reported: &Reported, actor: &UserID) {
+        let Some(reports) = self.reports.remove(reported) else {
+            return;
+        };
+        store::store_reports(&self.reports);
+        let thread = self.threads.get(reported.thread());
+        let reply = match reported {
+            Reported::Thread(_) => thread.and_then(|x| x.replies.first()),
+            Reported::Reply(_, reply) => Some(reply),
+        }.and_then(|x| self.replies.get(x));
+        self.record(ModRecord {
+            created: Utc::now(),
+            actor: actor.clone(),
+            action: ModAction::DismissReports,
+            user: reply.map(|x| x.user.clone()),
+            topic: self.get_thread_topic(reported.thread()).cloned(),
+            target: format!("{} in \"{}\"", reported.kind(), thread.map_or(reported.thread().0.as_str(), |x| x.title.as_str())),
+            reason: String::new(),
+            before: reports.iter()
+                .map(|x| format!("{}: {} {}", x.reporter.0, x.reason.label(), x.note))
+                .collect::<Vec<_>>().join("\n"),
+            after: String::new(),
+        });
+    }
+
+    /// For when what was reported is gone
+    pub(super) fn forget_reports(&mut self, reported: &Reported) {
+        if self.reports.remove(reported).is_some() {
+            store::store_reports(&self.reports);
+        }
+    }
+
+    /// Reports about the thread and everything in it have been dealt with once it's gone
+    pub(super) fn drop_reports_for_thread(&mut self, thread: &ThreadID) {
+        let before = self.reports.len();
+        self.reports.retain(|x, _| x.thread() != thread);
+        if self.reports.len() != before {
+            store::store_reports(&self.reports);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{data::{RoleGrant, RoleID}, testing::scratch_store};
+    use super::*;
+
+    #[test]
+    fn enough_trusted_reports_hide_a_thread_until_dismissed() {
+        let _store = scratch_store();
+        let mut db = DB::load();
+        let alice = db.create_new_user("alice", None);
+        let topic = db.create_new_topic(&alice, "cats", &alice).unwrap();
+        let author = db.create_new_user("author", None);
+        let thread_id = db.create_new_thread(&topic, "Hello".to_string(), &author).unwrap();
+        db.try_reply("First", &thread_id, &author, vec![]).unwrap();
+        let thread = Reported::Thread(thread_id.clone());
+
+        assert!(matches!(db.report(&author, thread.clone(), ReportReason::Spam, String::new()), Err(ReportError::OwnContent)));
+        let reporters = ["a", "b", "c"].map(|x| db.create_new_user(x, None));
+        for reporter in &reporters {
+            db.report(reporter, thread.clone(), ReportReason::Spam, String::new()).unwrap();
+        }
+        // Reports from people without trusted-reports don't count
+        assert!(!db.is_hidden(&thread));
+        for reporter in &reporters[..2] {
+            db.grant_permission(reporter, RoleGrant { role: RoleID("trusted".to_string()), topic: None }, &alice);
+        }
+        db.report(&reporters[0], thread.clone(), ReportReason::Harassment, String::new()).unwrap();
+        assert!(!db.is_hidden(&thread));
+        db.grant_permission(&reporters[2], RoleGrant { role: RoleID("trusted".to_string()), topic: None }, &alice);
+        assert!(db.is_hidden(&thread));
+        assert!(db.is_hidden_from(Some(&author), &thread) && !db.is_hidden_from(Some(&alice), &thread));
+
+        db.dismiss_reports(&thread, &alice);
+        assert!(!db.is_hidden_from(None, &thread) && db.get_sorted_reports().is_empty());
+    }
+}
\ No newline at end of file
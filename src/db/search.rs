@@ -1,4 +1,4 @@
-use crate::data::{TopicID, ThreadID};
+use crate::data::{TopicID, ThreadID, UserID};
 
 use super::DB;
 
@@ -16,6 +16,18 @@ impl DB {
             .collect()
     }
 
+    /// Handles and display names, case doesn't matter
+    pub fn search_users(&self, query: &str) -> Vec<&UserID> {
+        let query = query.trim().to_lowercase();
+        let mut users = self.users.iter()
+            .filter(|(id, user)| id.0.to_lowercase().contains(&query)
+                || user.display_name.as_ref().is_some_and(|x| x.to_lowercase().contains(&query)))
+            .map(|(id, _)| id)
+            .collect::<Vec<_>>();
+        users.sort_unstable_by_key(|x| x.0.as_str());
+        users
+    }
+
     fn match_title_to_query(title: &str, query: &str) -> bool {
         title.contains(query)
     }
@@ -1,35 +1,45 @@
-use std::{cmp::Ordering, collections::HashMap};
+use std::cmp::Ordering;
 
 use super::DB;
 
-use crate::data::{UserID, TopicID, ThreadID, Thread, Reply, ModItemID, ModItem};
+use crate::data::{UserID, TopicID, ThreadID, Thread, ReplyID, Reply};
 
 impl DB {
-    pub fn collect_replies_for_user<T, M>(&self, user_id: &UserID, transform: M) -> Vec<T> where M: Fn(&ThreadID, &Thread, &Reply) -> T {
+    pub fn collect_replies_for_user<T, M>(&self, user_id: &UserID, transform: M) -> Vec<T> where M: Fn(&ThreadID, &Thread, &ReplyID, &Reply) -> T {
         self.topics.iter().flat_map(|(_, t)|
             t.threads.iter().filter_map(|k| self.threads.get_key_value(k)).flat_map(|(thread_id, thread)|
-                thread.replies.iter().filter_map(|t| self.replies.get(t))
-                    .filter(|p| &p.user == user_id)
-                    .map(|p| transform(thread_id, thread, p))
+                thread.replies.iter().filter_map(|t| self.replies.get_key_value(t))
+                    .filter(|(_, p)| &p.user == user_id)
+                    .map(|(id, p)| transform(thread_id, thread, id, p))
             ).collect::<Vec<_>>()
         ).collect()
     }
 
+    /// Pinned ones first, then whichever had a reply last
     pub fn get_sorted_threads(&self, topic: &TopicID) -> Vec<&ThreadID> {
         let mut threads = self.get_topic(topic).unwrap().threads.iter()
             .map(|k| (k, self.get_thread(k).unwrap()))
             .collect::<Vec<_>>();
         threads.sort_unstable_by(|(_, a), (_, b)| {
+            let pinned = b.pinned.cmp(&a.pinned);
             let a = a.replies.last().and_then(|x| self.get_reply(x));
             let b = b.replies.last().and_then(|x| self.get_reply(x));
-            a.map_or(Ordering::Greater, |a|
+            pinned.then_with(|| a.map_or(Ordering::Greater, |a|
                 b.map_or(Ordering::Less, |b| 
-                    b.created.cmp(&a.created)))
+                    b.created.cmp(&a.created))))
         });
         threads.into_iter().map(|(n, _)| n).collect()
     }
 
-    pub fn get_inspection(&self) -> &HashMap<ModItemID, ModItem> {
-        &self.inspection
+    pub fn get_sorted_user_ids(&self) -> Vec<&UserID> {
+        let mut users = self.users.keys().collect::<Vec<_>>();
+        users.sort_unstable_by_key(|x| x.0.as_str());
+        users
+    }
+
+    pub fn get_sorted_topic_ids(&self) -> Vec<&TopicID> {
+        let mut topics = self.topics.keys().collect::<Vec<_>>();
+        topics.sort_unstable_by_key(|x| x.0.as_str());
+        topics
     }
 }
\ No newline at end of file
@@ -0,0 +1,30 @@
+use crate::data::{Settings, RegistrationMode};
+
+use super::{DB, store};
+
+impl DB {
+    pub fn get_settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    pub fn set_require_mod_2fa(&mut self, require: bool) {
+        self.settings.require_mod_2fa = require;
+        store::store_settings(&self.settings)
+    }
+
+    pub fn set_registration(&mut self, mode: RegistrationMode, invite_quota: usize) {
+        self.settings.registration_mode = mode;
+        self.settings.invite_quota = invite_quota;
+        store::store_settings(&self.settings)
+    }
+
+    pub fn set_report_threshold(&mut self, threshold: usize) {
+        self.settings.report_threshold = threshold;
+        store::store_settings(&self.settings)
+    }
+
+    pub fn set_approval_posts(&mut self, posts: usize) {
+        self.settings.approval_posts = posts;
+        store::store_settings(&self.settings)
+    }
+}
\ No newline at end of file
@@ -1,21 +1,100 @@
 use std::{collections::HashMap, fs::{read_dir, read_to_string, create_dir_all}};
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
 use json::{JsonValue, object};
 use rand::distributions::{Alphanumeric, DistString};
+use regex::Regex;
 
-use crate::{data::{Topic, User, UserID, TopicID, ThreadID, Thread, ReplyID, Reply, ModItemID}, auth::PasswordStore};
+use crate::{data::{Topic, User, UserID, TopicID, ThreadID, Thread, ReplyID, Reply, ModItemID, ModItem, Moderatable, Ban, BanKind, Settings, RegistrationMode, InviteID, Invite, RoleID, RoleGrant, Roles, Capability, Reported, Report, ReportReason, NoteSubject, ModNote, NoteRevision, ModRecord, ModAction, Appeal, Notice, AutomodRule, AutomodAction, AutomodHit, Condition}, auth::{PasswordStore, TotpStore, token::{ApiToken, ApiTokenID, Scope}, oidc::{OidcConfig, ExternalIdentity}}};
 
-use super::Permission;
 
 pub(super) const USERS_PATH: &str = "store/users";
 pub(super) const TOPICS_PATH: &str = "store/topics";
 pub(super) const THREADS_PATH: &str = "store/threads";
 pub(super) const REPLIES_PATH: &str = "store/replies";
+pub(super) const INVITES_PATH: &str = "store/invites";
 pub(super) const AUTH_PATH: &str = "store/auth";
+pub(super) const TOTP_PATH: &str = "store/auth-totp";
+pub(super) const API_TOKENS_PATH: &str = "store/auth-tokens";
+pub(super) const OIDC_PATH: &str = "store/auth-oidc";
 pub(super) const MOD_PATH: &str = "store/mod";
 pub(super) const MOD_INSPECTION_PATH: &str = "store/mod/inspection";
+pub(super) const MOD_PENDING_PATH: &str = "store/mod/pending";
 pub(super) const MOD_RECORD_PATH: &str = "store/mod/record";
+pub(super) const MOD_AUTOMOD_PATH: &str = "store/mod/automod";
+
+fn parse_user(json: &JsonValue) -> User {
+    let about = json["about"].to_string();
+    let display_name = json["display-name"].as_str().map(|x| x.to_string());
+    let pronouns = match &json["pronouns"] {
+        JsonValue::Array(pronouns) => Some([
+            pronouns.get(0).map_or_else(|| "null".to_string(), |x| x.to_string()),
+            pronouns.get(1).map_or_else(|| "null".to_string(), |x| x.to_string()),
+            pronouns.get(2).map_or_else(|| "null".to_string(), |x| x.to_string()),
+        ]),
+        _ => None
+    };
+    let fav_topics = match &json["fav-topics"] {
+        JsonValue::Array(topics) => topics.iter()
+            .map(|x| TopicID(x.as_str().unwrap().to_string()))
+            .collect(),
+        _ => vec![],
+    };
+    let fav_threads = match &json["fav-threads"] {
+        JsonValue::Array(threads) => threads.iter()
+            .map(|x| ThreadID(x.as_str().unwrap().to_string()))
+            .collect(),
+        _ => vec![],
+    };
+    let blocked = json["blocked"].members()
+        .filter_map(|x| x.as_str().map(|x| UserID(x.to_string())))
+        .collect();
+    let muted_topics = json["muted-topics"].members()
+        .filter_map(|x| x.as_str().map(|x| TopicID(x.to_string())))
+        .collect();
+    let joined = json["joined"].as_str().and_then(|x| x.parse::<DateTime<Utc>>().ok());
+    User { display_name, about, pronouns, fav_topics, fav_threads, blocked, muted_topics, joined }
+}
+
+fn parse_topic(json: &JsonValue) -> Topic {
+    let about = json["about"].to_string();
+    let threads = match &json["threads"] {
+        JsonValue::Array(threads) => threads.iter()
+            .map(|x| ThreadID(x.as_str().unwrap().to_string()))
+            .collect(),
+        _ => vec![],
+    };
+    let color = json["color"].as_str().map(|x| x.to_string());
+    let archived = json["archived"].as_bool().unwrap_or(false);
+    Topic { about, threads, color, archived }
+}
+
+fn parse_thread(json: &JsonValue) -> Thread {
+    let title = json["title"].to_string();
+    let replies = match &json["replies"] {
+        JsonValue::Array(replies) => replies.iter()
+            .map(|x| ReplyID(x.as_str().unwrap().to_string()))
+            .collect(),
+        _ => vec![],
+    };
+    let locked = json["locked"].as_bool().unwrap_or(false);
+    let pinned = json["pinned"].as_bool().unwrap_or(false);
+    Thread { title, replies, locked, pinned }
+}
+
+fn parse_reply(json: &JsonValue) -> Reply {
+    let created = json["created"].as_str().and_then(|x| x.parse::<DateTime<Utc>>().ok()).unwrap();
+    let user = UserID(json["user"].to_string());
+    let content = json["content"].to_string();
+    let tags = match &json["tags"] {
+        JsonValue::Array(tags) => tags.iter()
+            .filter_map(|x| x.as_str().map(|x| x.to_string()))
+            .collect(),
+        _ => vec![],
+    };
+    let shadowed = json["shadowed"].as_bool().unwrap_or(false);
+    Reply { created, user, content, tags, shadowed }
+}
 
 pub(super) fn load_users() -> HashMap<UserID, User> {
     match read_dir(USERS_PATH) {
@@ -24,28 +103,7 @@ pub(super) fn load_users() -> HashMap<UserID, User> {
             let name = file.file_name().into_string().unwrap();
             let name = name[0..name.find('.').unwrap_or_else(|| name.len())].to_string();
             let json = json::parse(&read_to_string(file.path()).unwrap()).unwrap();
-            let about = json["about"].to_string();
-            let pronouns = match &json["pronouns"] {
-                JsonValue::Array(pronouns) => Some([
-                    pronouns.get(0).map_or_else(|| "null".to_string(), |x| x.to_string()),
-                    pronouns.get(1).map_or_else(|| "null".to_string(), |x| x.to_string()),
-                    pronouns.get(2).map_or_else(|| "null".to_string(), |x| x.to_string()),
-                ]),
-                _ => None
-            };
-            let fav_topics = match &json["fav-topics"] {
-                JsonValue::Array(topics) => topics.iter()
-                    .map(|x| TopicID(x.as_str().unwrap().to_string()))
-                    .collect(),
-                _ => vec![],
-            };
-            let fav_threads = match &json["fav-threads"] {
-                JsonValue::Array(threads) => threads.iter()
-                    .map(|x| ThreadID(x.as_str().unwrap().to_string()))
-                    .collect(),
-                _ => vec![],
-            };
-            (UserID(name.clone()), User { about, pronouns, fav_topics, fav_threads })
+            (UserID(name.clone()), parse_user(&json))
         }).collect(),
         Err(_) => HashMap::new(),
     }
@@ -58,14 +116,7 @@ pub(super) fn load_topics() -> HashMap<TopicID, Topic> {
             let name = file.file_name().into_string().unwrap();
             let name = name[0..name.find('.').unwrap_or_else(|| name.len())].to_string();
             let json = json::parse(&read_to_string(file.path()).unwrap()).unwrap();
-            let about = json["about"].to_string();
-            let threads = match &json["threads"] {
-                JsonValue::Array(threads) => threads.iter()
-                    .map(|x| ThreadID(x.as_str().unwrap().to_string()))
-                    .collect(),
-                _ => vec![],
-            };
-            (TopicID(name.clone()), Topic { about, threads })
+            (TopicID(name.clone()), parse_topic(&json))
         }).collect(),
         Err(_) => HashMap::new(),
     }
@@ -78,14 +129,7 @@ pub(super) fn load_threads() -> HashMap<ThreadID, Thread> {
             let name = file.file_name().into_string().unwrap();
             let name = name[0..name.find('.').unwrap_or_else(|| name.len())].to_string();
             let json = json::parse(&read_to_string(file.path()).unwrap()).unwrap();
-            let title = json["title"].to_string();
-            let replies = match &json["replies"] {
-                JsonValue::Array(replies) => replies.iter()
-                    .map(|x| ReplyID(x.as_str().unwrap().to_string()))
-                    .collect(),
-                _ => vec![],
-            };
-            (ThreadID(name.clone()), Thread { title, replies })
+            (ThreadID(name.clone()), parse_thread(&json))
         }).collect(),
         Err(_) => HashMap::new(),
     }
@@ -98,16 +142,15 @@ pub(super) fn load_replies() -> HashMap<ReplyID, Reply> {
             let name = file.file_name().into_string().unwrap();
             let name = name[0..name.find('.').unwrap_or_else(|| name.len())].to_string();
             let json = json::parse(&read_to_string(file.path()).unwrap()).unwrap();
-            let created = json["created"].as_str().and_then(|x| x.parse::<DateTime<Utc>>().ok()).unwrap();
-            let user = UserID(json["user"].to_string());
-            let content = json["content"].to_string();
-            (ReplyID(name), Reply { created, user, content })
+            (ReplyID(name), parse_reply(&json))
         }).collect(),
         Err(_) => HashMap::new(),
     }
 }
 
-pub(super) fn load_permissions() -> HashMap<UserID, Vec<Permission>> {
+/// Grants are written `role` for the whole site or `role:topic` for a single topic,
+/// `mod:topic` is what topic owners used to be written as
+pub(super) fn load_permissions() -> HashMap<UserID, Vec<RoleGrant>> {
     let json = read_to_string(MOD_PATH.to_string() + "/permissions.json")
         .ok().and_then(|j| json::parse(&j).ok());
     match json {
@@ -116,14 +159,15 @@ pub(super) fn load_permissions() -> HashMap<UserID, Vec<Permission>> {
                 let user_id = UserID(x.0.to_string());
                 let permissions = if let JsonValue::Array(permissions) = x.1 {
                     permissions.iter().filter_map(|x| {
-                        match x.as_str() {
-                            Some("overlord") => Some(Permission::Overlord),
-                            Some(x) if x.starts_with("mod:") =>
-                                Some(Permission::TopicOwner(
-                                    TopicID(x.replacen("mod:", "", 1))
-                                )),
-                            _ => None,
-                        }
+                        let (role, topic) = match x.as_str()?.split_once(':') {
+                            Some(("mod", topic)) => (Roles::OWNER, Some(topic)),
+                            Some((role, topic)) => (role, Some(topic)),
+                            None => (x.as_str()?, None),
+                        };
+                        Some(RoleGrant {
+                            role: RoleID(role.to_string()),
+                            topic: topic.map(|x| TopicID(x.to_string())),
+                        })
                     }).collect()
                 } else { vec![] };
                 (user_id, permissions)
@@ -133,61 +177,624 @@ pub(super) fn load_permissions() -> HashMap<UserID, Vec<Permission>> {
     }
 }
 
+pub(super) fn load_roles() -> Roles {
+    let json = read_to_string(MOD_PATH.to_string() + "/roles.json")
+        .ok().and_then(|j| json::parse(&j).ok());
+    match json {
+        Some(json) => {
+            let default = Roles::default();
+            Roles {
+                default: json["default"].as_str().map_or(default.default, |x| RoleID(x.to_string())),
+                roles: if json["roles"].is_object() {
+                    json["roles"].entries()
+                        .map(|(role, capabilities)| (
+                            RoleID(role.to_string()),
+                            capabilities.members().filter_map(|x| x.as_str().and_then(Capability::parse)).collect(),
+                        ))
+                        .collect()
+                } else {
+                    default.roles
+                },
+            }
+        },
+        None => Roles::default(),
+    }
+}
 
-pub(super) fn store_user(id: &UserID, user: &User) {
-    let _ = create_dir_all(USERS_PATH);
-    let json = object! {
+pub(super) fn load_invites() -> HashMap<InviteID, Invite> {
+    match read_dir(INVITES_PATH) {
+        Ok(x) => x.map(|x| {
+            let file = x.unwrap();
+            let name = file.file_name().into_string().unwrap();
+            let name = name[0..name.find('.').unwrap_or(name.len())].to_string();
+            let json = json::parse(&read_to_string(file.path()).unwrap()).unwrap();
+            let inviter = UserID(json["inviter"].to_string());
+            let created = json["created"].as_str().and_then(|x| x.parse::<DateTime<Utc>>().ok()).unwrap();
+            let used_by = json["used-by"].as_str().map(|x| UserID(x.to_string()));
+            (InviteID(name), Invite { inviter, created, used_by })
+        }).collect(),
+        Err(_) => HashMap::new(),
+    }
+}
+
+/// For the small `old name -> new name` maps kept in the mod folder
+pub(super) fn load_user_name_map(name: &str) -> HashMap<UserID, UserID> {
+    let json = read_to_string(MOD_PATH.to_string() + "/" + name + ".json")
+        .ok().and_then(|j| json::parse(&j).ok());
+    match json {
+        Some(JsonValue::Object(json)) => json.iter()
+            .filter_map(|(k, v)| v.as_str().map(|v| (UserID(k.to_string()), UserID(v.to_string()))))
+            .collect(),
+        _ => HashMap::new(),
+    }
+}
+
+pub(super) fn load_topic_redirects() -> HashMap<TopicID, TopicID> {
+    let json = read_to_string(MOD_PATH.to_string() + "/topic-redirects.json")
+        .ok().and_then(|j| json::parse(&j).ok());
+    match json {
+        Some(JsonValue::Object(json)) => json.iter()
+            .filter_map(|(k, v)| v.as_str().map(|v| (TopicID(k.to_string()), TopicID(v.to_string()))))
+            .collect(),
+        _ => HashMap::new(),
+    }
+}
+
+pub(super) fn load_inspection() -> HashMap<ModItemID, ModItem> {
+    load_mod_items(MOD_INSPECTION_PATH)
+}
+
+pub(super) fn load_pending() -> HashMap<ModItemID, ModItem> {
+    load_mod_items(MOD_PENDING_PATH)
+}
+
+fn load_mod_items(path: &str) -> HashMap<ModItemID, ModItem> {
+    match read_dir(path) {
+        Ok(x) => x.filter_map(|x| {
+            let file = x.unwrap();
+            let name = file.file_name().into_string().unwrap();
+            let name = name[0..name.find('.').unwrap_or(name.len())].to_string();
+            let json = json::parse(&read_to_string(file.path()).unwrap()).unwrap();
+            let moderated = json["moderated"].as_str().and_then(|x| x.parse::<DateTime<Utc>>().ok())?;
+            let author = json["author"].as_str().map(|x| (UserID(x.to_string()), parse_user(&json["user"])));
+            let thing = match json["kind"].as_str()? {
+                "user" => Moderatable::User,
+                "topic" => Moderatable::Topic(
+                    TopicID(json["topic-id"].as_str()?.to_string()),
+                    parse_topic(&json["topic"]),
+                    json["threads"].entries()
+                        .map(|(k, v)| (ThreadID(k.to_string()), parse_thread(v)))
+                        .collect(),
+                    json["replies"].entries()
+                        .map(|(k, v)| (ReplyID(k.to_string()), parse_reply(v)))
+                        .collect(),
+                ),
+                "thread" => Moderatable::Thread(
+                    ThreadID(json["thread-id"].as_str()?.to_string()),
+                    parse_thread(&json["thread"]),
+                    json["replies"].entries()
+                        .map(|(k, v)| (ReplyID(k.to_string()), parse_reply(v)))
+                        .collect(),
+                    TopicID(json["topic-id"].as_str()?.to_string()),
+                    json["position"].as_usize().unwrap_or(usize::MAX),
+                ),
+                "reply" => Moderatable::Reply(
+                    ReplyID(json["reply-id"].as_str()?.to_string()),
+                    parse_reply(&json["reply"]),
+                    ThreadID(json["thread-id"].as_str()?.to_string()),
+                    json["position"].as_usize().unwrap_or(usize::MAX),
+                ),
+                _ => return None,
+            };
+            let reason = json["reason"].as_str().unwrap_or_default().to_string();
+            let appeal = json["appeal"]["created"].as_str()
+                .and_then(|x| x.parse::<DateTime<Utc>>().ok())
+                .map(|created| Appeal {
+                    statement: json["appeal"]["statement"].as_str().unwrap_or_default().to_string(),
+                    created,
+                    denied: json["appeal"]["denied"].as_str().map(|x| x.to_string()),
+                });
+            Some((ModItemID(name), ModItem { moderated, author, thing, reason, appeal }))
+        }).collect(),
+        Err(_) => HashMap::new(),
+    }
+}
+
+/// Every key that's there adds a condition, rules that don't make sense are left out
+pub(super) fn load_automod_rules() -> Vec<AutomodRule> {
+    let json = read_to_string(MOD_PATH.to_string() + "/automod.json")
+        .ok().and_then(|j| json::parse(&j).ok());
+    let Some(JsonValue::Array(rules)) = json else {
+        return vec![];
+    };
+    rules.iter().filter_map(|x| {
+        let mut conditions = vec![];
+        if let Some(regex) = x["regex"].as_str() {
+            conditions.push(Condition::Regex(Regex::new(regex).ok()?));
+        }
+        if let JsonValue::Array(words) = &x["words"] {
+            conditions.push(Condition::Words(words.iter().filter_map(|x| x.as_str().map(|x| x.to_lowercase())).collect()));
+        }
+        if let Some(links) = x["links"].as_usize() {
+            conditions.push(Condition::Links(links));
+        }
+        if let Some(caps) = x["caps"].as_f64() {
+            conditions.push(Condition::Caps(caps));
+        }
+        if let Some(hours) = x["account-age-hours"].as_i64() {
+            conditions.push(Condition::AccountAge(Duration::hours(hours)));
+        }
+        let name = x["name"].as_str()?.to_string();
+        let action = match x["action"].as_str()? {
+            "reject" => AutomodAction::Reject(x["message"].as_str().unwrap_or("Your post was rejected").to_string()),
+            "hold" => AutomodAction::Hold,
+            "tag" => AutomodAction::Tag(x["tag"].as_str().unwrap_or(&name).to_string()),
+            _ => return None,
+        };
+        Some(AutomodRule {
+            name,
+            topic: x["topic"].as_str().map(|x| TopicID(x.to_string())),
+            conditions,
+            action,
+        })
+    }).collect()
+}
+
+/// The rules are written by hand, so only their topics get changed
+pub(super) fn rename_automod_topic(old: &TopicID, new: &TopicID) {
+    let path = MOD_PATH.to_string() + "/automod.json";
+    let json = read_to_string(&path)
+        .ok().and_then(|j| json::parse(&j).ok());
+    let Some(JsonValue::Array(mut rules)) = json else {
+        return;
+    };
+    let mut renamed = false;
+    for rule in rules.iter_mut().filter(|x| x["topic"].as_str() == Some(old.0.as_str())) {
+        rule["topic"] = new.0.as_str().into();
+        renamed = true;
+    }
+    if renamed {
+        let _ = std::fs::write(path, json::stringify_pretty(JsonValue::Array(rules), 4));
+    }
+}
+
+/// Oldest first
+pub(super) fn load_automod_hits() -> Vec<AutomodHit> {
+    let mut hits = match read_dir(MOD_AUTOMOD_PATH) {
+        Ok(x) => x.filter_map(|x| {
+            let json = json::parse(&read_to_string(x.unwrap().path()).ok()?).ok()?;
+            Some(AutomodHit {
+                created: json["created"].as_str().and_then(|x| x.parse::<DateTime<Utc>>().ok())?,
+                rule: json["rule"].as_str()?.to_string(),
+                user: UserID(json["user"].as_str()?.to_string()),
+                topic: TopicID(json["topic"].as_str()?.to_string()),
+                thread: json["thread"].as_str().map(|x| ThreadID(x.to_string())),
+                action: json["action"].as_str().unwrap_or_default().to_string(),
+                excerpt: json["excerpt"].as_str().unwrap_or_default().to_string(),
+            })
+        }).collect::<Vec<_>>(),
+        Err(_) => vec![],
+    };
+    hits.sort_by_key(|x| x.created);
+    hits
+}
+
+/// Oldest first
+pub(super) fn load_records() -> Vec<ModRecord> {
+    let mut records = match read_dir(MOD_RECORD_PATH) {
+        Ok(x) => x.filter_map(|x| {
+            let json = json::parse(&read_to_string(x.unwrap().path()).ok()?).ok()?;
+            Some(ModRecord {
+                created: json["created"].as_str().and_then(|x| x.parse::<DateTime<Utc>>().ok())?,
+                actor: UserID(json["actor"].as_str()?.to_string()),
+                action: json["action"].as_str().and_then(ModAction::parse)?,
+                user: json["user"].as_str().map(|x| UserID(x.to_string())),
+                topic: json["topic"].as_str().map(|x| TopicID(x.to_string())),
+                target: json["target"].as_str().unwrap_or_default().to_string(),
+                reason: json["reason"].as_str().unwrap_or_default().to_string(),
+                before: json["before"].as_str().unwrap_or_default().to_string(),
+                after: json["after"].as_str().unwrap_or_default().to_string(),
+            })
+        }).collect::<Vec<_>>(),
+        Err(_) => vec![],
+    };
+    records.sort_by_key(|x| x.created);
+    records
+}
+
+pub(super) fn load_bans() -> HashMap<UserID, Ban> {
+    let json = read_to_string(MOD_PATH.to_string() + "/bans.json")
+        .ok().and_then(|j| json::parse(&j).ok());
+    match json {
+        Some(JsonValue::Object(json)) => json.iter()
+            .filter_map(|(user, x)| Some((UserID(user.to_string()), Ban {
+                moderator: UserID(x["moderator"].as_str()?.to_string()),
+                issued: x["issued"].as_str().and_then(|x| x.parse::<DateTime<Utc>>().ok())?,
+                until: x["until"].as_str().and_then(|x| x.parse::<DateTime<Utc>>().ok()),
+                reason: x["reason"].as_str().unwrap_or_default().to_string(),
+                kind: x["kind"].as_str().and_then(BanKind::parse).unwrap_or(BanKind::Ban),
+            })))
+            .collect(),
+        _ => HashMap::new(),
+    }
+}
+
+pub(super) fn load_reports() -> HashMap<Reported, Vec<Report>> {
+    let json = read_to_string(MOD_PATH.to_string() + "/reports.json")
+        .ok().and_then(|j| json::parse(&j).ok());
+    match json {
+        Some(JsonValue::Array(json)) => json.iter()
+            .filter_map(|x| {
+                let thread = ThreadID(x["thread"].as_str()?.to_string());
+                let reported = match x["reply"].as_str() {
+                    Some(reply) => Reported::Reply(thread, ReplyID(reply.to_string())),
+                    None => Reported::Thread(thread),
+                };
+                let reports = x["reports"].members()
+                    .filter_map(|x| Some(Report {
+                        reporter: UserID(x["reporter"].as_str()?.to_string()),
+                        reason: x["reason"].as_str().and_then(ReportReason::parse)?,
+                        note: x["note"].as_str().unwrap_or_default().to_string(),
+                        created: x["created"].as_str().and_then(|x| x.parse::<DateTime<Utc>>().ok())?,
+                    }))
+                    .collect();
+                Some((reported, reports))
+            })
+            .collect(),
+        _ => HashMap::new(),
+    }
+}
+
+/// Subjects are written like reports, with a `user` instead for notes about an account
+pub(super) fn load_notes() -> HashMap<NoteSubject, Vec<ModNote>> {
+    let json = read_to_string(MOD_PATH.to_string() + "/notes.json")
+        .ok().and_then(|j| json::parse(&j).ok());
+    match json {
+        Some(JsonValue::Array(json)) => json.iter()
+            .filter_map(|x| {
+                let subject = match (x["user"].as_str(), x["thread"].as_str(), x["reply"].as_str()) {
+                    (Some(user), _, _) => NoteSubject::User(UserID(user.to_string())),
+                    (None, Some(thread), Some(reply)) => NoteSubject::Post(Reported::Reply(ThreadID(thread.to_string()), ReplyID(reply.to_string()))),
+                    (None, Some(thread), None) => NoteSubject::Post(Reported::Thread(ThreadID(thread.to_string()))),
+                    (None, None, _) => return None,
+                };
+                let notes = x["notes"].members()
+                    .map(|x| ModNote {
+                        revisions: x.members()
+                            .filter_map(|x| Some(NoteRevision {
+                                author: UserID(x["author"].as_str()?.to_string()),
+                                created: x["created"].as_str().and_then(|x| x.parse::<DateTime<Utc>>().ok())?,
+                                content: x["content"].as_str().unwrap_or_default().to_string(),
+                            }))
+                            .collect(),
+                    })
+                    .filter(|x| !x.revisions.is_empty())
+                    .collect();
+                Some((subject, notes))
+            })
+            .collect(),
+        _ => HashMap::new(),
+    }
+}
+
+pub(super) fn load_notices() -> HashMap<UserID, Vec<Notice>> {
+    let json = read_to_string(MOD_PATH.to_string() + "/notices.json")
+        .ok().and_then(|j| json::parse(&j).ok());
+    match json {
+        Some(JsonValue::Object(json)) => json.iter()
+            .map(|(user, notices)| (UserID(user.to_string()), notices.members()
+                .filter_map(|x| Some(Notice {
+                    created: x["created"].as_str().and_then(|x| x.parse::<DateTime<Utc>>().ok())?,
+                    text: x["text"].as_str().unwrap_or_default().to_string(),
+                    seen: x["seen"].as_bool().unwrap_or(false),
+                }))
+                .collect()))
+            .collect(),
+        _ => HashMap::new(),
+    }
+}
+
+pub(super) fn load_settings() -> Settings {
+    let json = read_to_string(MOD_PATH.to_string() + "/settings.json")
+        .ok().and_then(|j| json::parse(&j).ok());
+    match json {
+        Some(json) => {
+            let default = Settings::default();
+            Settings {
+                require_mod_2fa: json["require-mod-2fa"].as_bool().unwrap_or(default.require_mod_2fa),
+                registration_mode: json["registration-mode"].as_str()
+                    .and_then(RegistrationMode::parse)
+                    .unwrap_or(default.registration_mode),
+                invite_quota: json["invite-quota"].as_usize().unwrap_or(default.invite_quota),
+                report_threshold: json["report-threshold"].as_usize().unwrap_or(default.report_threshold),
+                approval_posts: json["approval-posts"].as_usize().unwrap_or(default.approval_posts),
+            }
+        },
+        None => Settings::default(),
+    }
+}
+
+
+fn user_json(user: &User) -> JsonValue {
+    object! {
+        "display-name": user.display_name.as_deref(),
         about: user.about.as_str(),
         pronouns: user.pronouns.as_ref().and_then(|x| Some(x.as_slice())),
         "fav-topics": user.fav_topics.iter().map(|x| x.0.as_str()).collect::<Vec<_>>(),
         "fav-threads": user.fav_threads.iter().map(|x| x.0.as_str()).collect::<Vec<_>>(),
-    };
-    let _ = std::fs::write(USERS_PATH.to_string() + "/" + id.0.as_str() + ".json", json.to_string());
+        blocked: user.blocked.iter().map(|x| x.0.as_str()).collect::<Vec<_>>(),
+        "muted-topics": user.muted_topics.iter().map(|x| x.0.as_str()).collect::<Vec<_>>(),
+        joined: user.joined.map(|x| x.to_string()),
+    }
 }
 
-pub(super) fn store_topic(id: &TopicID, topic: &Topic) {
-    let _ = create_dir_all(TOPICS_PATH);
-    let json = object! {
+fn topic_json(topic: &Topic) -> JsonValue {
+    object! {
         about: topic.about.as_str(),
         threads: topic.threads.iter().map(|x| x.0.as_str()).collect::<Vec<_>>(),
-    };
-    let _ = std::fs::write(TOPICS_PATH.to_string() + "/" + id.0.as_str() + ".json", json.to_string());
+        color: topic.color.as_deref(),
+        archived: topic.archived,
+    }
 }
 
-pub(super) fn store_thread(id: &ThreadID, thread: &Thread) {
-    let _ = create_dir_all(THREADS_PATH);
-    let json = object! {
+fn thread_json(thread: &Thread) -> JsonValue {
+    object! {
         title: thread.title.as_str(),
         replies: thread.replies.iter().map(|x| x.0.as_str()).collect::<Vec<_>>(),
-    };
-    let _ = std::fs::write(THREADS_PATH.to_string() + "/" + id.0.as_str() + ".json", json.to_string());
+        locked: thread.locked,
+        pinned: thread.pinned,
+    }
 }
 
-pub(super) fn store_reply(id: &ReplyID, reply: &Reply) {
-    let _ = create_dir_all(REPLIES_PATH);
-    let json = object! {
+fn reply_json(reply: &Reply) -> JsonValue {
+    object! {
         created: reply.created.to_string().as_str(),
         user: reply.user.0.as_str(),
         content: reply.content.as_str(),
-    };
-    let _ = std::fs::write(REPLIES_PATH.to_string() + "/" + id.0.as_str() + ".json", json.to_string());
+        tags: reply.tags.iter().map(|x| x.as_str()).collect::<Vec<_>>(),
+        shadowed: reply.shadowed,
+    }
+}
+
+pub(super) fn store_user(id: &UserID, user: &User) {
+    let _ = create_dir_all(USERS_PATH);
+    let _ = std::fs::write(USERS_PATH.to_string() + "/" + id.0.as_str() + ".json", user_json(user).to_string());
+}
+
+pub(super) fn store_topic(id: &TopicID, topic: &Topic) {
+    let _ = create_dir_all(TOPICS_PATH);
+    let _ = std::fs::write(TOPICS_PATH.to_string() + "/" + id.0.as_str() + ".json", topic_json(topic).to_string());
+}
+
+pub(super) fn store_thread(id: &ThreadID, thread: &Thread) {
+    let _ = create_dir_all(THREADS_PATH);
+    let _ = std::fs::write(THREADS_PATH.to_string() + "/" + id.0.as_str() + ".json", thread_json(thread).to_string());
 }
 
-pub(super) fn store_permissions(permissions: &HashMap<UserID, Vec<Permission>>) {
+pub(super) fn store_reply(id: &ReplyID, reply: &Reply) {
+    let _ = create_dir_all(REPLIES_PATH);
+    let _ = std::fs::write(REPLIES_PATH.to_string() + "/" + id.0.as_str() + ".json", reply_json(reply).to_string());
+}
+
+pub(super) fn store_permissions(permissions: &HashMap<UserID, Vec<RoleGrant>>) {
     let _ = create_dir_all(MOD_PATH);
     let mut obj = JsonValue::new_object();
     for (user, permissions) in permissions {
-        let string = permissions.iter().map(|permission| {
-            let permission = match permission {
-                Permission::Overlord => "overlord".to_string(),
-                Permission::TopicOwner(topic) =>
-                    "mod:".to_string() + topic.0.as_str(),
+        let string = permissions.iter().map(|grant| {
+            let grant = match &grant.topic {
+                Some(topic) => grant.role.0.clone() + ":" + topic.0.as_str(),
+                None => grant.role.0.clone(),
             };
-            JsonValue::String(permission)
+            JsonValue::String(grant)
         }).collect::<Vec<_>>();
         obj[&user.0] = JsonValue::Array(string);
     }
-    let _ = std::fs::write(MOD_PATH.to_string() + "/overlords.json", obj.to_string());
+    let _ = std::fs::write(MOD_PATH.to_string() + "/permissions.json", obj.to_string());
+}
+
+pub(super) fn store_invite(id: &InviteID, invite: &Invite) {
+    let _ = create_dir_all(INVITES_PATH);
+    let json = object! {
+        inviter: invite.inviter.0.as_str(),
+        created: invite.created.to_string().as_str(),
+        "used-by": invite.used_by.as_ref().map(|x| x.0.as_str()),
+    };
+    let _ = std::fs::write(INVITES_PATH.to_string() + "/" + id.0.as_str() + ".json", json.to_string());
+}
+
+pub(super) fn store_inspection_item(id: &ModItemID, item: &ModItem) {
+    store_mod_item(MOD_INSPECTION_PATH, id, item)
+}
+
+pub(super) fn store_pending_item(id: &ModItemID, item: &ModItem) {
+    store_mod_item(MOD_PENDING_PATH, id, item)
+}
+
+fn store_mod_item(path: &str, id: &ModItemID, item: &ModItem) {
+    let _ = create_dir_all(path);
+    let mut json = object! {
+        moderated: item.moderated.to_string().as_str(),
+        author: item.author_id().map(|x| x.0.as_str()),
+        kind: item.thing.kind(),
+        reason: item.reason.as_str(),
+    };
+    if let Some((_, user)) = &item.author {
+        json["user"] = user_json(user);
+    }
+    if let Some(appeal) = &item.appeal {
+        json["appeal"] = object! {
+            statement: appeal.statement.as_str(),
+            created: appeal.created.to_string().as_str(),
+            denied: appeal.denied.as_deref(),
+        };
+    }
+    match &item.thing {
+        Moderatable::User => {},
+        Moderatable::Topic(topic_id, topic, threads, replies) => {
+            json["topic-id"] = topic_id.0.as_str().into();
+            json["topic"] = topic_json(topic);
+            json["threads"] = JsonValue::new_object();
+            for (thread_id, thread) in threads {
+                json["threads"][&thread_id.0] = thread_json(thread);
+            }
+            json["replies"] = JsonValue::new_object();
+            for (reply_id, reply) in replies {
+                json["replies"][&reply_id.0] = reply_json(reply);
+            }
+        },
+        Moderatable::Thread(thread_id, thread, replies, topic_id, position) => {
+            json["thread-id"] = thread_id.0.as_str().into();
+            json["thread"] = thread_json(thread);
+            json["replies"] = JsonValue::new_object();
+            for (reply_id, reply) in replies {
+                json["replies"][&reply_id.0] = reply_json(reply);
+            }
+            json["topic-id"] = topic_id.0.as_str().into();
+            json["position"] = (*position).into();
+        },
+        Moderatable::Reply(reply_id, reply, thread_id, position) => {
+            json["reply-id"] = reply_id.0.as_str().into();
+            json["reply"] = reply_json(reply);
+            json["thread-id"] = thread_id.0.as_str().into();
+            json["position"] = (*position).into();
+        },
+    }
+    let _ = std::fs::write(path.to_string() + "/" + id.0.as_str() + ".json", json.to_string());
+}
+
+pub(super) fn store_automod_hit(hit: &AutomodHit) {
+    let json = object! {
+        created: hit.created.to_string().as_str(),
+        rule: hit.rule.as_str(),
+        user: hit.user.0.as_str(),
+        topic: hit.topic.0.as_str(),
+        thread: hit.thread.as_ref().map(|x| x.0.as_str()),
+        action: hit.action.as_str(),
+        excerpt: hit.excerpt.as_str(),
+    };
+    let _ = std::fs::write(MOD_AUTOMOD_PATH.to_string() + "/" + gen_id(MOD_AUTOMOD_PATH).as_str() + ".json", json.to_string());
+}
+
+/// Every file counts, even the ones that can't be read anymore, so the next number is always free
+pub(super) fn count_records() -> usize {
+    read_dir(MOD_RECORD_PATH).map_or(0, |x| x.count())
+}
+
+/// Records are never changed or deleted once written
+pub(super) fn store_record(number: usize, record: &ModRecord) {
+    let json = object! {
+        created: record.created.to_string().as_str(),
+        actor: record.actor.0.as_str(),
+        action: record.action.as_str(),
+        user: record.user.as_ref().map(|x| x.0.as_str()),
+        topic: record.topic.as_ref().map(|x| x.0.as_str()),
+        target: record.target.as_str(),
+        reason: record.reason.as_str(),
+        before: record.before.as_str(),
+        after: record.after.as_str(),
+    };
+    let _ = create_dir_all(MOD_RECORD_PATH);
+    let _ = std::fs::write(format!("{}/{}.json", MOD_RECORD_PATH, number), json.to_string());
+}
+
+pub(super) fn store_bans(bans: &HashMap<UserID, Ban>) {
+    let _ = create_dir_all(MOD_PATH);
+    let mut obj = JsonValue::new_object();
+    for (user, ban) in bans {
+        obj[&user.0] = object! {
+            moderator: ban.moderator.0.as_str(),
+            issued: ban.issued.to_string().as_str(),
+            until: ban.until.map(|x| x.to_string()),
+            reason: ban.reason.as_str(),
+            kind: ban.kind.as_str(),
+        };
+    }
+    let _ = std::fs::write(MOD_PATH.to_string() + "/bans.json", obj.to_string());
+}
+
+pub(super) fn store_reports(reports: &HashMap<Reported, Vec<Report>>) {
+    let _ = create_dir_all(MOD_PATH);
+    let json = reports.iter().map(|(reported, reports)| object! {
+        thread: reported.thread().0.as_str(),
+        reply: match reported {
+            Reported::Reply(_, reply) => Some(reply.0.as_str()),
+            Reported::Thread(_) => None,
+        },
+        reports: reports.iter().map(|x| object! {
+            reporter: x.reporter.0.as_str(),
+            reason: x.reason.as_str(),
+            note: x.note.as_str(),
+            created: x.created.to_string().as_str(),
+        }).collect::<Vec<_>>(),
+    }).collect::<Vec<_>>();
+    let _ = std::fs::write(MOD_PATH.to_string() + "/reports.json", JsonValue::Array(json).to_string());
+}
+
+pub(super) fn store_notes(notes: &HashMap<NoteSubject, Vec<ModNote>>) {
+    let _ = create_dir_all(MOD_PATH);
+    let json = notes.iter().map(|(subject, notes)| {
+        let mut json = object! {
+            notes: notes.iter().map(|x| x.revisions.iter().map(|x| object! {
+                author: x.author.0.as_str(),
+                created: x.created.to_string().as_str(),
+                content: x.content.as_str(),
+            }).collect::<Vec<_>>()).collect::<Vec<_>>(),
+        };
+        match subject {
+            NoteSubject::User(user) => json["user"] = user.0.as_str().into(),
+            NoteSubject::Post(reported) => {
+                json["thread"] = reported.thread().0.as_str().into();
+                if let Reported::Reply(_, reply) = reported {
+                    json["reply"] = reply.0.as_str().into();
+                }
+            },
+        }
+        json
+    }).collect::<Vec<_>>();
+    let _ = std::fs::write(MOD_PATH.to_string() + "/notes.json", JsonValue::Array(json).to_string());
+}
+
+pub(super) fn store_notices(notices: &HashMap<UserID, Vec<Notice>>) {
+    let _ = create_dir_all(MOD_PATH);
+    let mut obj = JsonValue::new_object();
+    for (user, notices) in notices {
+        obj[&user.0] = notices.iter().map(|x| object! {
+            created: x.created.to_string().as_str(),
+            text: x.text.as_str(),
+            seen: x.seen,
+        }).collect::<Vec<_>>().into();
+    }
+    let _ = std::fs::write(MOD_PATH.to_string() + "/notices.json", obj.to_string());
+}
+
+pub(super) fn store_user_name_map(name: &str, map: &HashMap<UserID, UserID>) {
+    let _ = create_dir_all(MOD_PATH);
+    let mut obj = JsonValue::new_object();
+    for (k, v) in map {
+        obj[&k.0] = JsonValue::String(v.0.clone());
+    }
+    let _ = std::fs::write(MOD_PATH.to_string() + "/" + name + ".json", obj.to_string());
+}
+
+pub(super) fn store_topic_redirects(map: &HashMap<TopicID, TopicID>) {
+    let _ = create_dir_all(MOD_PATH);
+    let mut obj = JsonValue::new_object();
+    for (k, v) in map {
+        obj[&k.0] = JsonValue::String(v.0.clone());
+    }
+    let _ = std::fs::write(MOD_PATH.to_string() + "/topic-redirects.json", obj.to_string());
+}
+
+pub(super) fn store_settings(settings: &Settings) {
+    let _ = create_dir_all(MOD_PATH);
+    let json = object! {
+        "require-mod-2fa": settings.require_mod_2fa,
+        "registration-mode": settings.registration_mode.as_str(),
+        "invite-quota": settings.invite_quota,
+        "report-threshold": settings.report_threshold,
+        "approval-posts": settings.approval_posts,
+    };
+    let _ = std::fs::write(MOD_PATH.to_string() + "/settings.json", json.to_string());
 }
 
 
@@ -207,6 +814,14 @@ pub(super) fn delete_reply(id: &ReplyID) {
     let _ = std::fs::remove_file(REPLIES_PATH.to_string() + "/" + id.0.as_str() + ".json");
 }
 
+pub(super) fn delete_inspection_item(id: &ModItemID) {
+    let _ = std::fs::remove_file(MOD_INSPECTION_PATH.to_string() + "/" + id.0.as_str() + ".json");
+}
+
+pub(super) fn delete_pending_item(id: &ModItemID) {
+    let _ = std::fs::remove_file(MOD_PENDING_PATH.to_string() + "/" + id.0.as_str() + ".json");
+}
+
 fn gen_id(path: &'static str) -> String {
     let _ = create_dir_all(path);
     let id = Alphanumeric.sample_string(&mut rand::thread_rng(), 24);
@@ -233,10 +848,18 @@ pub(super) fn gen_thread_id() -> ThreadID {
     ThreadID(gen_id(THREADS_PATH))
 }
 
+pub(super) fn gen_invite_id() -> InviteID {
+    InviteID(gen_id(INVITES_PATH))
+}
+
 pub(super) fn gen_inspection_id() -> ModItemID {
     ModItemID(gen_id(MOD_INSPECTION_PATH))
 }
 
+pub(super) fn gen_pending_id() -> ModItemID {
+    ModItemID(gen_id(MOD_PENDING_PATH))
+}
+
 
 pub fn store_user_auth(user_name: &str, password_store: &PasswordStore) {
     let _ = create_dir_all(AUTH_PATH);
@@ -259,4 +882,122 @@ pub fn load_user_auth(user_name: &str) -> Option<PasswordStore> {
         },
         Err(_) => None,
     }
-}
\ No newline at end of file
+}
+pub fn store_user_totp(user_name: &str, totp_store: &TotpStore) {
+    let _ = create_dir_all(TOTP_PATH);
+    let json = object! {
+        secret: totp_store.secret.as_str(),
+        "recovery-codes": totp_store.recovery_codes.iter().map(|x| x.as_str()).collect::<Vec<_>>(),
+    };
+    let _ = std::fs::write(TOTP_PATH.to_string() + "/" + user_name + ".json", json.to_string());
+}
+
+pub fn load_user_totp(user_name: &str) -> Option<TotpStore> {
+    let json = read_to_string(TOTP_PATH.to_string() + "/" + user_name + ".json");
+    match json {
+        Ok(json) => {
+            let json = json::parse(&json).unwrap();
+            Some(TotpStore {
+                secret: json["secret"].as_str().unwrap().to_string(),
+                recovery_codes: match &json["recovery-codes"] {
+                    JsonValue::Array(codes) => codes.iter()
+                        .filter_map(|x| x.as_str().map(|x| x.to_string()))
+                        .collect(),
+                    _ => vec![],
+                },
+            })
+        },
+        Err(_) => None,
+    }
+}
+
+pub fn delete_user_totp(user_name: &str) {
+    let _ = std::fs::remove_file(TOTP_PATH.to_string() + "/" + user_name + ".json");
+}
+
+pub fn store_api_token(id: &ApiTokenID, token: &ApiToken) {
+    let _ = create_dir_all(API_TOKENS_PATH);
+    let json = object! {
+        user: token.user.0.as_str(),
+        name: token.name.as_str(),
+        scopes: token.scopes.iter().map(|x| x.as_str()).collect::<Vec<_>>(),
+        created: token.created.to_string().as_str(),
+        "hashed-secret": token.hashed_secret.as_str(),
+    };
+    let _ = std::fs::write(API_TOKENS_PATH.to_string() + "/" + id.0.as_str() + ".json", json.to_string());
+}
+
+pub fn load_api_tokens() -> HashMap<ApiTokenID, ApiToken> {
+    match read_dir(API_TOKENS_PATH) {
+        Ok(x) => x.map(|x| {
+            let file = x.unwrap();
+            let name = file.file_name().into_string().unwrap();
+            let name = name[0..name.find('.').unwrap_or(name.len())].to_string();
+            let json = json::parse(&read_to_string(file.path()).unwrap()).unwrap();
+            let scopes = match &json["scopes"] {
+                JsonValue::Array(scopes) => scopes.iter()
+                    .filter_map(|x| x.as_str().and_then(Scope::parse))
+                    .collect(),
+                _ => vec![],
+            };
+            (ApiTokenID(name), ApiToken {
+                user: UserID(json["user"].to_string()),
+                name: json["name"].to_string(),
+                scopes,
+                created: json["created"].as_str().and_then(|x| x.parse::<DateTime<Utc>>().ok()).unwrap(),
+                hashed_secret: json["hashed-secret"].to_string(),
+            })
+        }).collect(),
+        Err(_) => HashMap::new(),
+    }
+}
+
+pub fn delete_api_token(id: &ApiTokenID) {
+    let _ = std::fs::remove_file(API_TOKENS_PATH.to_string() + "/" + id.0.as_str() + ".json");
+}
+
+/// Written by hand, single sign-on stays off until it exists
+pub fn load_oidc_config() -> Option<OidcConfig> {
+    let json = read_to_string(OIDC_PATH.to_string() + "/config.json")
+        .ok().and_then(|j| json::parse(&j).ok())?;
+    Some(OidcConfig {
+        issuer: json["issuer"].as_str()?.to_string(),
+        client_id: json["client-id"].as_str()?.to_string(),
+        client_secret: json["client-secret"].as_str().map(|x| x.to_string()),
+        redirect_uri: json["redirect-uri"].as_str()?.to_string(),
+        label: json["label"].as_str().unwrap_or("Single sign-on").to_string(),
+    })
+}
+
+pub fn store_oidc_identities(identities: &HashMap<ExternalIdentity, UserID>) {
+    let _ = create_dir_all(OIDC_PATH);
+    let json = identities.iter().map(|(identity, user)| object! {
+        issuer: identity.issuer.as_str(),
+        subject: identity.subject.as_str(),
+        user: user.0.as_str(),
+    }).collect::<Vec<_>>();
+    let _ = std::fs::write(OIDC_PATH.to_string() + "/identities.json", JsonValue::Array(json).to_string());
+}
+
+pub fn load_oidc_identities() -> HashMap<ExternalIdentity, UserID> {
+    let json = read_to_string(OIDC_PATH.to_string() + "/identities.json")
+        .ok().and_then(|j| json::parse(&j).ok());
+    match json {
+        Some(JsonValue::Array(json)) => json.iter()
+            .filter_map(|x| Some((
+                ExternalIdentity {
+                    issuer: x["issuer"].as_str()?.to_string(),
+                    subject: x["subject"].as_str()?.to_string(),
+                },
+                UserID(x["user"].as_str()?.to_string()),
+            )))
+            .collect(),
+        _ => HashMap::new(),
+    }
+}
+
+/// Moves the password and 2FA records over to a new user name
+pub fn rename_user_auth(old_name: &str, new_name: &str) {
+    let _ = std::fs::rename(AUTH_PATH.to_string() + "/" + old_name + ".json", AUTH_PATH.to_string() + "/" + new_name + ".json");
+    let _ = std::fs::rename(TOTP_PATH.to_string() + "/" + old_name + ".json", TOTP_PATH.to_string() + "/" + new_name + ".json");
+}
@@ -0,0 +1,165 @@
+use chrono::Utc;
+
+use crate::data::{UserID, TopicID, ThreadID, ModRecord, ModAction};
+
+use super::{DB, store};
+
+#[derive(thiserror::Error, Debug)]
+pub enum MoveThreadError {
+    #[error("This thread no longer exists")]
+    NotFound,
+    #[error("There's no topic with that name")]
+    TopicNotFound,
+    #[error("The thread is in that topic already")]
+    SameTopic,
+}
+
+impl DB {
+    fn record_thread_change(&mut self, thread_id: &ThreadID, action: ModAction, actor: &UserID, target: String, before: String, after: String) {
+        let Some(thread) = self.threads.get(thread_id) else {
+            return;
+        };
+        self.record(ModRecord {
+            created: Utc::now(),
+            actor: actor.clone(),
+            action,
+            user: thread.replies.first().and_then(|x| self.replies.get(x)).map(|x| x.user.clone()),
+            topic: self.get_thread_topic(thread_id).cloned(),
+            target,
+            reason: String::new(),
+            before,
+            after,
+        });
+    }
+
+    pub fn set_thread_locked(&mut self, thread_id: &ThreadID, locked: bool, actor: &UserID) {
+        let Some(thread) = self.threads.get_mut(thread_id).filter(|x| x.locked != locked) else {
+            return;
+        };
+        thread.locked = locked;
+        store::store_thread(thread_id, thread);
+        let target = format!("thread \"{}\"", thread.title);
+        let action = if locked { ModAction::Lock } else { ModAction::Unlock };
+        self.record_thread_change(thread_id, action, actor, target, String::new(), String::new());
+    }
+
+    pub fn set_thread_pinned(&mut self, thread_id: &ThreadID, pinned: bool, actor: &UserID) {
+        let Some(thread) = self.threads.get_mut(thread_id).filter(|x| x.pinned != pinned) else {
+            return;
+        };
+        thread.pinned = pinned;
+        store::store_thread(thread_id, thread);
+        let target = format!("thread \"{}\"", thread.title);
+        let action = if pinned { ModAction::Pin } else { ModAction::Unpin };
+        self.record_thread_change(thread_id, action, actor, target, String::new(), String::new());
+    }
+
+    /// Everything is checked before either topic is touched, so the thread is never in both or neither.
+    /// It's recorded as happening in the topic it came from
+    pub fn move_thread(&mut self, thread_id: &ThreadID, to: &TopicID, actor: &UserID) -> Result<(), MoveThreadError> {
+        let Some(from) = self.get_thread_topic(thread_id).cloned() else {
+            return Err(MoveThreadError::NotFound);
+        };
+        if &from == to {
+            return Err(MoveThreadError::SameTopic);
+        } else if !self.topics.contains_key(to) {
+            return Err(MoveThreadError::TopicNotFound);
+        }
+        let target = format!("thread \"{}\" to λ/{}", self.threads.get(thread_id).map_or("", |x| x.title.as_str()), to.0);
+        self.record_thread_change(thread_id, ModAction::MoveThread, actor, target, format!("λ/{}", from.0), format!("λ/{}", to.0));
+        let source = self.topics.get_mut(&from).unwrap();
+        source.threads.retain(|x| x != thread_id);
+        store::store_topic(&from, source);
+        let destination = self.topics.get_mut(to).unwrap();
+        destination.threads.push(thread_id.clone());
+        store::store_topic(to, destination);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{data::RecordFilter, testing::scratch_store};
+    use super::*;
+
+    #[test]
+    fn locked_threads_take_no_replies() {
+        let _store = scratch_store();
+        let mut db = DB::load();
+        let [alice, bob] = ["alice", "bob"].map(|x| db.create_new_user(x, None));
+        let cats = db.create_new_topic(&alice, "cats", &alice).unwrap();
+        let thread = db.create_new_thread(&cats, "Hello".to_string(), &bob).unwrap();
+        db.try_reply("Opening", &thread, &bob, vec![]).unwrap();
+
+        db.set_thread_locked(&thread, true, &alice);
+        assert!(DB::load().get_thread(&thread).unwrap().locked);
+        assert!(db.try_reply("Hi", &thread, &bob, vec![]).is_none());
+        assert!(!db.submit_reply("Hi", &thread, &bob, vec![]));
+        assert_eq!(db.get_thread(&thread).unwrap().replies.len(), 1);
+        db.set_thread_locked(&thread, false, &alice);
+        assert!(db.try_reply("Hi", &thread, &bob, vec![]).is_some());
+    }
+
+    #[test]
+    fn pinned_threads_come_first() {
+        let _store = scratch_store();
+        let mut db = DB::load();
+        let alice = db.create_new_user("alice", None);
+        let cats = db.create_new_topic(&alice, "cats", &alice).unwrap();
+        let [old, new] = ["Old", "New"].map(|x| {
+            let thread = db.create_new_thread(&cats, x.to_string(), &alice).unwrap();
+            db.try_reply(x, &thread, &alice, vec![]).unwrap();
+            thread
+        });
+        assert_eq!(db.get_sorted_threads(&cats), vec![&new, &old]);
+        db.set_thread_pinned(&old, true, &alice);
+        assert_eq!(db.get_sorted_threads(&cats), vec![&old, &new]);
+    }
+
+    #[test]
+    fn moving_to_a_missing_topic_leaves_both_alone() {
+        let _store = scratch_store();
+        let mut db = DB::load();
+        let alice = db.create_new_user("alice", None);
+        let cats = db.create_new_topic(&alice, "cats", &alice).unwrap();
+        let thread = db.create_new_thread(&cats, "Hello".to_string(), &alice).unwrap();
+        let nowhere = TopicID("nowhere".to_string());
+
+        assert!(matches!(db.move_thread(&thread, &nowhere, &alice), Err(MoveThreadError::TopicNotFound)));
+        assert!(matches!(db.move_thread(&thread, &cats, &alice), Err(MoveThreadError::SameTopic)));
+        let reloaded = DB::load();
+        for db in [&db, &reloaded] {
+            assert_eq!(db.get_topic(&cats).unwrap().threads, vec![thread.clone()]);
+            assert!(db.get_topic(&nowhere).is_none());
+        }
+        assert!(db.get_records(&RecordFilter::default()).iter().all(|x| x.action != ModAction::MoveThread));
+    }
+
+    #[test]
+    fn every_change_is_recorded() {
+        let _store = scratch_store();
+        let mut db = DB::load();
+        let [alice, bob] = ["alice", "bob"].map(|x| db.create_new_user(x, None));
+        let cats = db.create_new_topic(&alice, "cats", &alice).unwrap();
+        let dogs = db.create_new_topic(&alice, "dogs", &alice).unwrap();
+        let thread = db.create_new_thread(&cats, "Hello".to_string(), &bob).unwrap();
+        db.try_reply("Opening", &thread, &bob, vec![]).unwrap();
+        let filter = RecordFilter { actor: Some(alice.clone()), user: Some(bob.clone()), ..Default::default() };
+        assert!(db.get_records(&filter).is_empty());
+
+        db.set_thread_locked(&thread, true, &alice);
+        db.set_thread_pinned(&thread, true, &alice);
+        // Nothing changes, so there's nothing to record
+        db.set_thread_pinned(&thread, true, &alice);
+        db.move_thread(&thread, &dogs, &alice).unwrap();
+        assert_eq!(DB::load().get_topic(&dogs).unwrap().threads, vec![thread.clone()]);
+        let records = db.get_records(&filter);
+        assert_eq!(records.len(), 3);
+        for action in [ModAction::Lock, ModAction::Pin, ModAction::MoveThread] {
+            assert!(records.iter().any(|x| x.action == action), "{}", action.as_str());
+        }
+        let moved = records.iter().find(|x| x.action == ModAction::MoveThread).unwrap();
+        assert_eq!(moved.topic.as_ref(), Some(&cats));
+        assert_eq!((moved.before.as_str(), moved.after.as_str()), ("λ/cats", "λ/dogs"));
+    }
+}
\ No newline at end of file
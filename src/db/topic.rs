@@ -0,0 +1,258 @@
+use chrono::Utc;
+use regex::Regex;
+
+use crate::data::{UserID, TopicID, Topic, RoleID, RoleGrant, Roles, Moderatable, ModRecord, ModAction};
+
+use super::{DB, store};
+
+pub const MAX_TOPIC_NAME_LENGTH: usize = 32;
+
+#[derive(thiserror::Error, Debug)]
+pub enum TopicError {
+    #[error("Topic names can only have lowercase ASCII letters, digits, '_' & '-'")]
+    InvalidName,
+    #[error("Topic names can't be longer than {MAX_TOPIC_NAME_LENGTH} characters")]
+    NameTooLong,
+    #[error("A topic with this name already exists")]
+    Taken,
+    #[error("There's no topic with this name")]
+    NotFound,
+    #[error("Colors have to look like #1a2b3c")]
+    InvalidColor,
+    #[error("There's no user with this name to own the topic")]
+    OwnerNotFound,
+    #[error("Type the topic's name to confirm deleting it")]
+    NotConfirmed,
+}
+
+/// Topic names end up in `/λ/{topic}` and as file names in the store,
+/// so nothing that would need escaping in either gets through
+pub fn validate_topic_name(name: &str) -> Result<(), TopicError> {
+    if name.len() > MAX_TOPIC_NAME_LENGTH {
+        return Err(TopicError::NameTooLong);
+    }
+    if !Regex::new("^[a-z0-9_-]+$").unwrap().is_match(name) {
+        return Err(TopicError::InvalidName);
+    }
+    Ok(())
+}
+
+/// Empty means no color at all
+fn parse_color(color: &str) -> Result<Option<String>, TopicError> {
+    match color.trim() {
+        "" => Ok(None),
+        x if Regex::new("^#[0-9a-fA-F]{6}$").unwrap().is_match(x) => Ok(Some(x.to_ascii_lowercase())),
+        _ => Err(TopicError::InvalidColor),
+    }
+}
+
+impl DB {
+    /// A name that used to redirect somewhere belongs to the new topic from now on
+    pub fn create_new_topic(&mut self, owner: &UserID, name: &str, actor: &UserID) -> Result<TopicID, TopicError> {
+        validate_topic_name(name)?;
+        let id = TopicID(name.to_string());
+        if self.topics.contains_key(&id) {
+            return Err(TopicError::Taken);
+        }
+        if !self.users.contains_key(owner) {
+            return Err(TopicError::OwnerNotFound);
+        }
+        let topic = Topic::default();
+        store::store_topic(&id, &topic);
+        self.topics.insert(id.clone(), topic);
+        if self.topic_redirects.remove(&id).is_some() {
+            store::store_topic_redirects(&self.topic_redirects);
+        }
+        self.record_topic_change(ModAction::CreateTopic, &id, actor, String::new(), format!("owned by @{}", owner.0));
+        self.add_grant(owner, RoleGrant { role: RoleID(Roles::OWNER.to_string()), topic: Some(id.clone()) });
+        Ok(id)
+    }
+
+    pub fn edit_topic(&mut self, topic_id: &TopicID, about: String, color: &str, actor: &UserID) -> Result<(), TopicError> {
+        let color = parse_color(color)?;
+        let Some(topic) = self.topics.get_mut(topic_id) else {
+            return Err(TopicError::NotFound);
+        };
+        let before = topic.about.clone();
+        topic.about = about;
+        topic.color = color;
+        store::store_topic(topic_id, topic);
+        let after = topic.about.clone();
+        self.record_topic_change(ModAction::EditTopic, topic_id, actor, before, after);
+        Ok(())
+    }
+
+    pub fn get_topic_redirect(&self, old_name: &TopicID) -> Option<&TopicID> {
+        self.topic_redirects.get(old_name)
+    }
+
+    /// Moves everything that refers to the topic over to the new name and leaves a redirect behind
+    pub fn rename_topic(&mut self, old: &TopicID, new: &str, actor: &UserID) -> Result<TopicID, TopicError> {
+        validate_topic_name(new)?;
+        let new = TopicID(new.to_string());
+        if self.topics.contains_key(&new) {
+            return Err(TopicError::Taken);
+        }
+        let Some(topic) = self.topics.remove(old) else {
+            return Err(TopicError::NotFound);
+        };
+        store::delete_topic(old);
+        store::store_topic(&new, &topic);
+        self.topics.insert(new.clone(), topic);
+
+        for grant in self.permissions.values_mut().flatten().filter(|x| x.topic.as_ref() == Some(old)) {
+            grant.topic = Some(new.clone());
+        }
+        store::store_permissions(&self.permissions);
+        for rule in self.automod_rules.iter_mut().filter(|x| x.topic.as_ref() == Some(old)) {
+            rule.topic = Some(new.clone());
+        }
+        store::rename_automod_topic(old, &new);
+        for (id, user) in self.users.iter_mut().filter(|(_, x)| x.fav_topics.contains(old) || x.muted_topics.contains(old)) {
+            user.fav_topics.iter_mut()
+                .chain(user.muted_topics.iter_mut())
+                .filter(|x| *x == old)
+                .for_each(|x| *x = new.clone());
+            store::store_user(id, user);
+        }
+        for (id, item) in self.inspection.iter_mut() {
+            if let Moderatable::Thread(_, _, _, topic_id, _) = &mut item.thing {
+                if topic_id == old {
+                    *topic_id = new.clone();
+                    store::store_inspection_item(id, item);
+                }
+            }
+        }
+        for (id, item) in self.pending.iter_mut() {
+            if let Moderatable::Thread(_, _, _, topic_id, _) = &mut item.thing {
+                if topic_id == old {
+                    *topic_id = new.clone();
+                    store::store_pending_item(id, item);
+                }
+            }
+        }
+
+        // Same as with users, earlier renames point straight at the newest name
+        self.topic_redirects.remove(&new);
+        self.topic_redirects.values_mut()
+            .filter(|x| *x == old)
+            .for_each(|x| *x = new.clone());
+        self.topic_redirects.insert(old.clone(), new.clone());
+        store::store_topic_redirects(&self.topic_redirects);
+        self.record_topic_change(ModAction::RenameTopic, &new, actor, old.0.clone(), new.0.clone());
+        Ok(new)
+    }
+
+    pub fn set_topic_archived(&mut self, topic_id: &TopicID, archived: bool, actor: &UserID) -> Result<(), TopicError> {
+        let Some(topic) = self.topics.get_mut(topic_id) else {
+            return Err(TopicError::NotFound);
+        };
+        if topic.archived == archived {
+            return Ok(());
+        }
+        topic.archived = archived;
+        store::store_topic(topic_id, topic);
+        let action = if archived { ModAction::Archive } else { ModAction::Unarchive };
+        self.record_topic_change(action, topic_id, actor, String::new(), String::new());
+        Ok(())
+    }
+
+    /// Gone for good along with every thread in it, unlike moving it to inspection.
+    /// Roles granted in it are revoked too, so a new topic with the same name starts out clean
+    pub fn delete_topic(&mut self, topic_id: &TopicID, actor: &UserID) -> Result<(), TopicError> {
+        let Some(topic) = self.topics.get(topic_id) else {
+            return Err(TopicError::NotFound);
+        };
+        let before = format!("{}\n{} threads", topic.about, topic.threads.len());
+        for thread_id in topic.threads.clone() {
+            self.delete_thread(topic_id, &thread_id);
+        }
+        self.topics.remove(topic_id);
+        store::delete_topic(topic_id);
+
+        let grants = self.permissions.iter()
+            .flat_map(|(user, grants)| grants.iter()
+                .filter(|x| x.topic.as_ref() == Some(topic_id))
+                .map(move |x| (user.clone(), x.clone())))
+            .collect::<Vec<_>>();
+        for (user, grant) in grants {
+            self.revoke_permission(&user, grant, actor);
+        }
+        self.permissions.retain(|_, x| !x.is_empty());
+        store::store_permissions(&self.permissions);
+        for (id, user) in self.users.iter_mut().filter(|(_, x)| x.fav_topics.contains(topic_id) || x.muted_topics.contains(topic_id)) {
+            user.fav_topics.retain(|x| x != topic_id);
+            user.muted_topics.retain(|x| x != topic_id);
+            store::store_user(id, user);
+        }
+        self.topic_redirects.retain(|_, x| x != topic_id);
+        store::store_topic_redirects(&self.topic_redirects);
+        self.record_topic_change(ModAction::DeleteTopic, topic_id, actor, before, String::new());
+        Ok(())
+    }
+
+    fn record_topic_change(&mut self, action: ModAction, topic_id: &TopicID, actor: &UserID, before: String, after: String) {
+        self.record(ModRecord {
+            created: Utc::now(),
+            actor: actor.clone(),
+            action,
+            user: None,
+            topic: Some(topic_id.clone()),
+            target: "topic".to_string(),
+            reason: String::new(),
+            before,
+            after,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{data::RecordFilter, testing::scratch_store};
+    use super::*;
+
+    #[test]
+    fn renames_take_everything_along() {
+        let _store = scratch_store();
+        std::fs::create_dir_all(store::MOD_PATH).unwrap();
+        std::fs::write(store::MOD_PATH.to_string() + "/automod.json", r#"[
+            { "name": "links", "topic": "cats", "links": 2, "action": "hold" },
+            { "name": "caps", "caps": 0.8, "action": "reject" }
+        ]"#).unwrap();
+        let mut db = DB::load();
+        let alice = db.create_new_user("alice", None);
+        let cats = db.create_new_topic(&alice, "cats", &alice).unwrap();
+        assert!(matches!(db.rename_topic(&cats, "Cats", &alice), Err(TopicError::InvalidName)));
+
+        let kittens = db.rename_topic(&cats, "kittens", &alice).unwrap();
+        for db in [&db, &DB::load()] {
+            assert!(db.get_topic(&cats).is_none() && db.get_topic(&kittens).is_some());
+            assert_eq!(db.get_topic_redirect(&cats), Some(&kittens));
+            assert_eq!(db.get_topic_owner(&kittens), Some(&alice));
+            let topics = db.get_automod_rules().iter().map(|x| x.topic.clone()).collect::<Vec<_>>();
+            assert_eq!(topics, vec![Some(kittens.clone()), None]);
+        }
+    }
+
+    #[test]
+    fn deleting_revokes_the_roles_granted_in_it() {
+        let _store = scratch_store();
+        let mut db = DB::load();
+        let [alice, bob] = ["alice", "bob"].map(|x| db.create_new_user(x, None));
+        let cats = db.create_new_topic(&bob, "cats", &alice).unwrap();
+        db.grant_permission(&alice, RoleGrant { role: RoleID("trusted".to_string()), topic: Some(cats.clone()) }, &alice);
+        let thread = db.create_new_thread(&cats, "Hello".to_string(), &bob).unwrap();
+        db.favorite_thread(&alice, &thread, true);
+
+        db.delete_topic(&cats, &alice).unwrap();
+        let db = DB::load();
+        assert!(db.get_topic(&cats).is_none() && db.get_topic_owner(&cats).is_none());
+        assert!(!db.is_thread_favorite(&alice, &thread));
+        let revoked = db.get_records(&RecordFilter::default()).into_iter()
+            .filter(|x| x.action == ModAction::Revoke)
+            .filter_map(|x| x.user.as_ref())
+            .collect::<Vec<_>>();
+        assert_eq!(revoked.len(), 2);
+        assert!(revoked.contains(&&alice) && revoked.contains(&&bob));
+    }
+}
\ No newline at end of file
@@ -2,6 +2,7 @@ use std::{sync::Mutex, io, fs::read_to_string};
 use actix_web::{web::{self, Data}, App, HttpServer, Responder, Result, middleware::Logger, http::{Method, StatusCode}, HttpResponse, Either};
 
 use auth::{Auth, UserSession};
+use csrf::{CsrfProtection, CsrfToken};
 use db::DB;
 
 use render::render_page;
@@ -12,13 +13,16 @@ mod routes;
 mod data;
 
 mod auth;
+mod csrf;
 mod db;
+#[cfg(test)]
+mod testing;
 
-async fn default_handler(req: Method, db: Data<Mutex<DB>>, user: Option<UserSession>) -> Result<impl Responder> {
+async fn default_handler(req: Method, db: Data<Mutex<DB>>, user: Option<UserSession>, csrf: CsrfToken) -> Result<impl Responder> {
     match req {
         Method::GET => {
             let db = db.lock().unwrap();
-            let response = render_page(&db, user.as_ref(), || {
+            let response = render_page(&db, user.as_ref(), &csrf, || {
                 read_to_string("assets/page/404.html").unwrap()
             })
                 .customize()
@@ -37,7 +41,12 @@ async fn main() -> io::Result<()> {
         App::new()
             .service(auth_signup)
             .service(auth_login)
+            .service(auth_login_totp)
             .service(auth_logout)
+            .service(auth_oidc_start)
+            .service(auth_oidc_link)
+            .service(auth_oidc_unlink)
+            .service(auth_oidc_callback)
 
             .service(page_home)
             .service(page_user)
@@ -45,21 +54,83 @@ async fn main() -> io::Result<()> {
             .service(page_thread)
 
             .service(page_settings)
+            .service(page_settings_totp)
+            .service(page_settings_tokens)
+            .service(page_settings_invites)
+            .service(page_admin_settings)
+            .service(page_admin_lockouts)
+            .service(page_admin_invites)
+            .service(page_admin_renames)
+            .service(page_admin_topics)
+            .service(page_admin_topic)
+            .service(page_admin_users)
+            .service(page_admin_user)
+            .service(grant_role)
+            .service(revoke_role)
+            .service(force_logout)
+            .service(reset_password)
+            .service(purge_user)
+            .service(add_note)
+            .service(edit_note)
+            .service(block_user)
+            .service(mute_topic)
             .service(page_login)
+            .service(page_login_totp)
             .service(page_signup)
             .service(page_create_thread)
 
             .service(page_search)
             .service(page_inspection)
+            .service(page_appeals)
+            .service(page_notices)
+            .service(page_approvals)
+            .service(page_reports)
+            .service(page_mod_log)
+            .service(page_transparency)
+            .service(page_automod)
 
             .service(make_reply)
             .service(make_thread)
             .service(favorite_topic)
             .service(favorite_thread)
             .service(update_settings)
+            .service(enable_totp)
+            .service(disable_totp)
+            .service(create_api_token)
+            .service(revoke_api_token)
+            .service(create_invite)
+            .service(update_site_settings)
+            .service(unlock)
+            .service(request_rename)
+            .service(approve_rename)
+            .service(reject_rename)
 
             .service(delete_reply)
+            .service(make_report)
+            .service(dismiss_reports)
             .service(move_reply_to_inspection)
+            .service(move_thread_to_inspection)
+            .service(move_topic_to_inspection)
+            .service(lock_thread)
+            .service(pin_thread)
+            .service(move_thread)
+            .service(move_user_profile_to_inspection)
+            .service(update_topic_about)
+            .service(restore_from_inspection)
+            .service(delete_from_inspection)
+            .service(make_appeal)
+            .service(accept_appeal)
+            .service(deny_appeal)
+            .service(create_topic)
+            .service(edit_topic)
+            .service(rename_topic)
+            .service(archive_topic)
+            .service(delete_topic)
+            .service(approve_pending)
+            .service(reject_pending)
+            .service(ban_author)
+            .service(ban_user)
+            .service(unban_user)
 
             .service(css_layout)
             .service(css_theme)
@@ -69,6 +140,7 @@ async fn main() -> io::Result<()> {
             .service(auth_signup)
             .app_data(auth.clone())
             .app_data(db.clone())
+            .wrap(CsrfProtection)
             .wrap(Logger::default())
             .default_service(web::to(default_handler))
     })
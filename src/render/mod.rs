@@ -1,15 +1,15 @@
-use std::{fs::read_to_string, collections::HashSet};
+use std::{fs::read_to_string, collections::{HashSet, HashMap}};
 
 use actix_web::{HttpResponse, http::{header::ContentType, StatusCode}};
 use ammonia::Builder;
 
-use crate::{db::DB, data::{TopicID, UserID, Reply, Thread, ThreadID}, auth::UserSession};
+use crate::{db::{DB, permissions::Target}, data::{Capability, TopicID, UserID, User, Reply, ReplyID, Thread, ThreadID, ModItemID, ModItem, Moderatable, Reported, Report, ReportReason, BanKind, NoteSubject, ModNote, ModRecord, AutomodRule, AutomodHit, Appeal}, auth::UserSession, csrf::CsrfToken};
 
-use self::format::format_date_time;
+pub use self::format::format_date_time;
 
 mod format;
 
-pub fn render_page<R>(db: &DB, user_session: Option<&UserSession>, render_content: R) -> HttpResponse
+pub fn render_page<R>(db: &DB, user_session: Option<&UserSession>, csrf: &CsrfToken, render_content: R) -> HttpResponse
     where R: FnOnce() -> String {
     let user = user_session.map(|x| &x.user);
     let html = read_to_string("assets/index.html").unwrap();
@@ -29,10 +29,10 @@ pub fn render_page<R>(db: &DB, user_session: Option<&UserSession>, render_conten
     let thread_list = match user.and_then(|x| db.get_user(x)) {
         Some(user) => {
             let threads = user.fav_threads.iter()
-                .map(|id| {
-                    let title = db.get_thread(id).unwrap();
+                .filter_map(|id| db.get_thread(id).map(|x| (id, x)))
+                .map(|(id, thread)| {
                     read_to_string("assets/element/side-bar/item.html").unwrap()
-                        .replace("{{text}}", title.title.as_str())
+                        .replace("{{text}}", thread.title.as_str())
                         .replace("{{url}}", (String::from("/t/") + id.0.as_str()).as_str())
                 })
                 .collect::<Vec<_>>().join("");
@@ -43,39 +43,78 @@ pub fn render_page<R>(db: &DB, user_session: Option<&UserSession>, render_conten
     };
     let admin_tools = match user {
         Some(user) if db.is_admin(user) => read_to_string("assets/element/side-bar/admin-tools.html").unwrap(),
+        Some(user) if db.is_moderator(user) => read_to_string("assets/element/side-bar/mod-tools.html").unwrap()
+            .replace("{{manage-users}}", if db.can(user, Capability::ManageUsers, Target::Site) {
+                read_to_string("assets/element/side-bar/manage-users.html").unwrap()
+            } else { "".to_string() }.as_str())
+            .replace("{{manage-topics}}", if db.can(user, Capability::ManageTopics, Target::Site) {
+                "<li><a class=sidebar-item href=/admin/topics>Topics</a></li>"
+            } else { "" }),
+        Some(user) if db.is_approver(user) => read_to_string("assets/element/side-bar/approvals.html").unwrap(),
         _ => "".to_string(),
     };
+    let ban_notice = match user.and_then(|x| db.get_ban(x)).filter(|x| x.kind != BanKind::Shadow) {
+        Some(ban) => read_to_string("assets/element/ban-notice.html").unwrap()
+            .replace("{{description}}", html_escape::encode_text(&ban.describe()).as_ref()),
+        None => "".to_string(),
+    };
+    let unseen = user.map_or(0, |x| db.count_unseen_notices(x));
+    let moderation_notice = if unseen > 0 {
+        read_to_string("assets/element/moderation-notice.html").unwrap()
+            .replace("{{count}}", unseen.to_string().as_str())
+    } else { "".to_string() };
     let html = html
+        .replace("{{ban-notice}}", ban_notice.as_str())
+        .replace("{{moderation-notice}}", moderation_notice.as_str())
         .replace("{{content}}", render_content().as_str())
         .replace("{{fav-topic-list}}", topic_list.as_str())
         .replace("{{fav-thread-list}}", thread_list.as_str())
         .replace("{{admin-tools}}", admin_tools.as_str())
         .replace("{{session-area}}", match user {
             Some(user) => read_to_string("assets/element/top-bar/logged-in.html").unwrap()
+                .replace("{{notices}}", if unseen > 0 { format!("notices ({})", unseen) } else { "notices".to_string() }.as_str())
                 .replace("{{current-user-name}}", user.0.as_str()),
             None => read_to_string("assets/element/top-bar/logged-out.html").unwrap(),
-        }.as_str());
+        }.as_str())
+        .replace("{{csrf-token}}", csrf.0.as_str());
     let mut builder = HttpResponse::build(StatusCode::OK);
     builder.content_type(ContentType::html());
     user_session.map(|x| x.keep(&mut builder));
     builder.body(html)
 }
 
-pub fn render_thread(db: &DB, preloaded_html: &str, thread_id: &ThreadID) -> String {
+pub fn render_display_name(user: Option<&User>, user_id: &UserID) -> String {
+    user.map_or_else(
+        || "[user not found]".to_string(),
+        |x| html_escape::encode_text(x.get_display_name(user_id)).to_string())
+}
+
+/// Previews the last reply the viewer gets to see and didn't block the author of.
+/// When there's no such reply there's nothing to show, not even the title
+pub fn render_thread(db: &DB, viewer: Option<&UserID>, preloaded_html: &str, thread_id: &ThreadID) -> Option<String> {
     let thread = db.get_thread(thread_id).unwrap();
-    let last_reply = thread.replies.last()
-        .and_then(|x| db.get_reply(x)).unwrap();
+    let last_reply = thread.replies.iter().rev()
+        .filter(|x| !db.is_hidden_from(viewer, &Reported::Reply(thread_id.clone(), (*x).clone())))
+        .filter_map(|x| db.get_reply(x))
+        .find(|x| !db.has_blocked(viewer, &x.user))?;
+    let shadowed = db.get_shadowed_author(&Reported::Thread(thread_id.clone())).is_some()
+        && viewer.is_some_and(|x| db.can(x, Capability::ModerateTopic, Target::Thread(thread_id)));
     let last_reply_content = Builder::new()
         .tags(HashSet::from(["b", "i", "em", "q", "u", "var"]))
         .clean(last_reply.content.as_str())
         .to_string();
     let user = db.get_user(&last_reply.user);
-    preloaded_html
+    Some(preloaded_html
         .replace("{{created-time}}", format_date_time(&last_reply.created).as_str())
         .replace("{{thread-id}}", thread_id.0.as_str())
-        .replace("{{user-name}}", user.map_or_else(|| "[user not found]", |_| last_reply.user.0.as_str()))
+        .replace("{{user-name}}", last_reply.user.0.as_str())
+        .replace("{{display-name}}", render_display_name(user, &last_reply.user).as_str())
+        .replace("{{flags}}", [(thread.pinned, "Pinned"), (thread.locked, "Locked"), (shadowed, "Shadowed")].into_iter()
+            .filter(|(x, _)| *x)
+            .map(|(_, x)| format!("<span class=thread-flag>{}</span>", x))
+            .collect::<Vec<_>>().join("").as_str())
         .replace("{{title}}", thread.title.as_str())
-        .replace("{{content}}", last_reply_content.as_str())
+        .replace("{{content}}", last_reply_content.as_str()))
 }
 
 pub fn render_reply(db: &DB, preloaded_html: &str, reply: &Reply) -> String {
@@ -83,10 +122,22 @@ pub fn render_reply(db: &DB, preloaded_html: &str, reply: &Reply) -> String {
     preloaded_html
         .replace("{{created-time}}", format_date_time(&reply.created).as_str())
         .replace("{{pronouns}}", user.and_then(|x| x.pronouns.as_ref()).map_or_else(|| "unknown pronouns".to_string(), |x| x.join("/")).as_str())
-        .replace("{{user-name}}", user.map_or_else(|| "[user not found]", |_| reply.user.0.as_str()))
+        .replace("{{user-name}}", reply.user.0.as_str())
+        .replace("{{display-name}}", render_display_name(user, &reply.user).as_str())
         .replace("{{content}}", reply.content.as_str())
 }
 
+/// What automod put on a reply and whether it's shadowed, only moderators get to see it
+pub fn render_reply_tags(db: &DB, user: Option<&UserID>, thread_id: &ThreadID, reply: &Reply) -> String {
+    if !user.is_some_and(|x| db.can(x, Capability::ModerateTopic, Target::Thread(thread_id))) {
+        return "".to_string();
+    }
+    let shadowed = reply.shadowed.then(|| "<span class=\"reply-tag shadowed\">Shadowed</span>".to_string());
+    shadowed.into_iter()
+        .chain(reply.tags.iter().map(|x| format!("<span class=reply-tag>{}</span>", html_escape::encode_text(x))))
+        .collect::<Vec<_>>().join("")
+}
+
 pub fn render_user_reply(preloaded_html: &str, thread_id: &ThreadID, thread: &Thread, reply: &Reply) -> String {
     preloaded_html
         .replace("{{created-time}}", format_date_time(&reply.created).as_str())
@@ -121,13 +172,408 @@ pub fn render_thread_fav(db: &DB, user: Option<&UserID>, thread: &ThreadID) -> S
     }
 }
 
-pub fn render_inspection_reply(db: &DB, preloaded_html: &str, thread_id: &ThreadID, thread: &Thread, reply: &Reply) -> String {
-    let user = db.get_user(&reply.user);
+/// Lock, pin and move buttons for those who moderate the thread, moving only offers topics they moderate too
+pub fn render_thread_tools(db: &DB, user: Option<&UserID>, thread_id: &ThreadID, thread: &Thread) -> String {
+    let Some(user) = user.filter(|x| db.can(x, Capability::ModerateTopic, Target::Thread(thread_id))) else {
+        return "".to_string();
+    };
+    let current = db.get_thread_topic(thread_id);
+    let topics = db.get_sorted_topic_ids().into_iter()
+        .filter(|x| Some(*x) != current && db.can(user, Capability::ModerateTopic, Target::Topic(x)))
+        .map(|x| format!("<option value=\"{}\">λ/{}</option>", x.0, x.0))
+        .collect::<Vec<_>>();
+    read_to_string("assets/element/thread-tools.html").unwrap()
+        .replace("{{move-form}}", if topics.is_empty() { "".to_string() } else {
+            read_to_string("assets/element/move-thread.html").unwrap()
+                .replace("{{topics}}", topics.join("").as_str())
+        }.as_str())
+        .replace("{{thread-id}}", thread_id.0.as_str())
+        .replace("{{will-be-locked}}", (!thread.locked).to_string().as_str())
+        .replace("{{lock-text}}", if thread.locked { "Unlock" } else { "Lock" })
+        .replace("{{will-be-pinned}}", (!thread.pinned).to_string().as_str())
+        .replace("{{pin-text}}", if thread.pinned { "Unpin" } else { "Pin" })
+}
+
+/// Only shown to those with the `capability` there, `fields` say what exactly goes to inspection
+pub fn render_mod_button(db: &DB, user: Option<&UserID>, capability: Capability, target: Target, action: &str, fields: &[(&str, &str)]) -> String {
+    match user {
+        Some(user) if db.can(user, capability, target) => read_to_string("assets/element/mod-button.html").unwrap()
+            .replace("{{action}}", action)
+            .replace("{{fields}}", fields.iter()
+                .map(|(name, value)| format!("<input type=hidden name={} value=\"{}\">", name, value))
+                .collect::<Vec<_>>().join("").as_str()),
+        _ => "".to_string(),
+    }
+}
+
+/// Anyone logged in can report, replies are told apart from their thread by `reply`
+pub fn render_report_form(user: Option<&UserID>, thread_id: &ThreadID, reply: Option<&ReplyID>) -> String {
+    if user.is_none() {
+        return "".to_string();
+    }
+    read_to_string("assets/element/report-form.html").unwrap()
+        .replace("{{thread-id}}", thread_id.0.as_str())
+        .replace("{{reply-field}}", reply.map_or_else(String::new, |x| format!("<input type=hidden name=reply value=\"{}\">", x.0)).as_str())
+        .replace("{{reasons}}", ReportReason::ALL.iter()
+            .map(|x| format!("<option value={}>{}</option>", x.as_str(), x.label()))
+            .collect::<Vec<_>>().join("").as_str())
+}
+
+pub fn render_ban_fields() -> String {
+    read_to_string("assets/element/ban-fields.html").unwrap()
+        .replace("{{kinds}}", BanKind::ALL.iter()
+            .map(|x| format!("<option value={}>{}</option>", x.as_str(), x.label()))
+            .collect::<Vec<_>>().join("").as_str())
+}
+
+/// The user's current ban with a way to lift it, or a form to issue one
+pub fn render_ban_controls(db: &DB, viewer: Option<&UserID>, user_id: &UserID) -> String {
+    match viewer {
+        Some(viewer) if viewer != user_id && db.can(viewer, Capability::ManageUsers, Target::Site) => (),
+        _ => return "".to_string(),
+    }
+    match db.get_ban(user_id) {
+        Some(ban) => read_to_string("assets/element/ban-status.html").unwrap()
+            .replace("{{description}}", html_escape::encode_text(&ban.describe()).as_ref())
+            .replace("{{moderator}}", ban.moderator.0.as_str())
+            .replace("{{issued}}", format_date_time(&ban.issued).as_str()),
+        None => read_to_string("assets/element/ban-user.html").unwrap()
+            .replace("{{ban-fields}}", render_ban_fields().as_str()),
+    }.replace("{{user-name}}", user_id.0.as_str())
+}
+
+/// Notes only show up for those allowed to see them, `back` is the page their forms return to
+pub fn render_notes(db: &DB, viewer: Option<&UserID>, subject: &NoteSubject, back: &str) -> String {
+    if !viewer.is_some_and(|x| db.can_see_notes(x, subject)) {
+        return "".to_string();
+    }
+    let subject_fields = match subject {
+        NoteSubject::User(user) => format!("<input type=hidden name=user value=\"{}\">", user.0),
+        NoteSubject::Post(Reported::Thread(thread)) => format!("<input type=hidden name=thread value=\"{}\">", thread.0),
+        NoteSubject::Post(Reported::Reply(thread, reply)) =>
+            format!("<input type=hidden name=thread value=\"{}\"><input type=hidden name=reply value=\"{}\">", thread.0, reply.0),
+    } + format!("<input type=hidden name=back value=\"{}\">", html_escape::encode_double_quoted_attribute(back)).as_str();
+    let title = match subject {
+        NoteSubject::User(user) => format!("Notes on @{}", user.0),
+        NoteSubject::Post(reported) => format!("Notes on this {}", reported.kind()),
+    };
+    let note_html = read_to_string("assets/element/mod-note.html").unwrap();
+    let notes = db.get_notes(subject);
+    read_to_string("assets/element/mod-notes.html").unwrap()
+        .replace("{{title}}", title.as_str())
+        .replace("{{count}}", notes.len().to_string().as_str())
+        .replace("{{notes}}", notes.iter().enumerate()
+            .map(|(i, x)| render_note(note_html.as_str(), subject_fields.as_str(), i, x))
+            .collect::<Vec<_>>().join("").as_str())
+        .replace("{{subject-fields}}", subject_fields.as_str())
+}
+
+fn render_note(preloaded_html: &str, subject_fields: &str, index: usize, note: &ModNote) -> String {
+    let current = note.current();
+    let (edited, history) = match note.revisions.split_last() {
+        Some((_, earlier)) if !earlier.is_empty() => (
+            format!("edited by <a href=\"/u/{0}\">@{0}</a>, <time>{1}</time>", current.author.0, format_date_time(&current.created)),
+            read_to_string("assets/element/mod-note-history.html").unwrap()
+                .replace("{{revisions}}", earlier.iter().rev()
+                    .map(|x| format!("<li><p>{0}</p><small><a href=\"/u/{2}\">@{2}</a>, <time>{1}</time></small></li>",
+                        html_escape::encode_text(&x.content), format_date_time(&x.created), x.author.0))
+                    .collect::<Vec<_>>().join("").as_str()),
+        ),
+        _ => ("".to_string(), "".to_string()),
+    };
+    preloaded_html
+        .replace("{{author}}", note.original().author.0.as_str())
+        .replace("{{created}}", format_date_time(&note.original().created).as_str())
+        .replace("{{edited}}", edited.as_str())
+        .replace("{{history}}", history.as_str())
+        .replace("{{index}}", index.to_string().as_str())
+        .replace("{{subject-fields}}", subject_fields)
+        .replace("{{content}}", html_escape::encode_text(&current.content).as_ref())
+}
+
+/// Reasons and snapshots are left out unless `full`, they may be about things nobody should see anymore
+pub fn render_mod_record(preloaded_html: &str, record: &ModRecord, full: bool) -> String {
+    let details = if full {
+        read_to_string("assets/element/mod-record-details.html").unwrap()
+            .replace("{{reason}}", html_escape::encode_text(&record.reason).as_ref())
+            .replace("{{before}}", html_escape::encode_text(&record.before).as_ref())
+            .replace("{{after}}", html_escape::encode_text(&record.after).as_ref())
+    } else {
+        "".to_string()
+    };
+    preloaded_html
+        .replace("{{details}}", details.as_str())
+        .replace("{{created}}", record.created.format("%Y-%m-%d %H:%M").to_string().as_str())
+        .replace("{{actor}}", record.actor.0.as_str())
+        .replace("{{action}}", record.action.label())
+        .replace("{{target}}", html_escape::encode_text(&record.target).as_ref())
+        .replace("{{topic}}", record.topic.as_ref().map_or_else(String::new, |x| format!(" in <a href=\"/λ/{}\">λ/{}</a>", x.0, x.0)).as_str())
+}
+
+pub fn render_automod_rule(preloaded_html: &str, rule: &AutomodRule, hits: usize) -> String {
+    preloaded_html
+        .replace("{{rule-query}}", serde_urlencoded::to_string([("rule", rule.name.as_str())]).unwrap().as_str())
+        .replace("{{name}}", html_escape::encode_text(&rule.name).as_ref())
+        .replace("{{topic}}", rule.topic.as_ref().map_or_else(|| "everywhere".to_string(), |x| format!("<a href=\"/λ/{}\">λ/{}</a>", x.0, x.0)).as_str())
+        .replace("{{conditions}}", html_escape::encode_text(&rule.conditions.iter().map(|x| x.describe()).collect::<Vec<_>>().join(" and ")).as_ref())
+        .replace("{{action}}", html_escape::encode_text(&rule.action.describe()).as_ref())
+        .replace("{{hits}}", hits.to_string().as_str())
+}
+
+pub fn render_automod_hit(preloaded_html: &str, hit: &AutomodHit) -> String {
+    preloaded_html
+        .replace("{{created}}", hit.created.format("%Y-%m-%d %H:%M").to_string().as_str())
+        .replace("{{place}}", match &hit.thread {
+            Some(thread) => format!("<a href=\"/t/{}\">a thread</a>", thread.0),
+            None => format!("a new thread in <a href=\"/λ/{}\">λ/{}</a>", hit.topic.0, hit.topic.0),
+        }.as_str())
+        .replace("{{action}}", html_escape::encode_text(&hit.action).as_ref())
+        .replace("{{excerpt}}", html_escape::encode_text(&hit.excerpt).as_ref())
+        .replace("{{rule}}", html_escape::encode_text(&hit.rule).as_ref())
+        .replace("{{user}}", hit.user.0.as_str())
+}
+
+/// Everything reported about one thing, as seen in the moderators' queue
+pub fn render_report_group(db: &DB, user: &UserID, preloaded_html: &str, reported: &Reported, reports: &[Report]) -> String {
+    let report_html = read_to_string("assets/element/report.html").unwrap();
+    let thread = db.get_thread(reported.thread());
+    let (reply_id, mod_button) = match reported {
+        Reported::Thread(thread_id) => (None, render_mod_button(db, Some(user), Capability::ModerateTopic, Target::Thread(thread_id),
+            "/do/mod/thread", &[("thread", thread_id.0.as_str())])),
+        Reported::Reply(thread_id, reply_id) => (Some(reply_id), render_mod_button(db, Some(user), Capability::ModerateTopic, Target::Thread(thread_id),
+            "/do/mod/reply", &[("thread", thread_id.0.as_str()), ("reply", reply_id.0.as_str())])),
+    };
+    let content = reply_id.or_else(|| thread.and_then(|x| x.replies.first()))
+        .and_then(|x| db.get_reply(x))
+        .map_or("", |x| x.content.as_str());
+    preloaded_html
+        .replace("{{kind}}", reported.kind())
+        .replace("{{thread-id}}", reported.thread().0.as_str())
+        .replace("{{thread-title}}", thread.map_or("[deleted thread]", |x| x.title.as_str()))
+        .replace("{{report-count}}", reports.len().to_string().as_str())
+        .replace("{{hidden}}", if db.is_hidden(reported) { ", hidden until dealt with" } else { "" })
+        .replace("{{reply-field}}", reply_id.map_or_else(String::new, |x| format!("<input type=hidden name=reply value=\"{}\">", x.0)).as_str())
+        .replace("{{mod-button}}", mod_button.as_str())
+        .replace("{{reports}}", reports.iter().map(|x| report_html
+            .replace("{{user-name}}", x.reporter.0.as_str())
+            .replace("{{reason}}", x.reason.label())
+            .replace("{{created-time}}", format_date_time(&x.created).as_str())
+            .replace("{{note}}", html_escape::encode_text(x.note.as_str()).as_ref())
+        ).collect::<Vec<_>>().join("").as_str())
+        .replace("{{content}}", content)
+}
+
+pub fn render_inspection_reply(preloaded_html: &str, author: Option<&User>, thread_id: &ThreadID, thread: Option<&Thread>, reply: &Reply) -> String {
     preloaded_html
         .replace("{{created-time}}", format_date_time(&reply.created).as_str())
         .replace("{{thread-id}}", thread_id.0.as_str())
-        .replace("{{thread-title}}", &thread.title.as_str())
-        .replace("{{pronouns}}", user.and_then(|x| x.pronouns.as_ref()).map_or_else(|| "unknown pronouns".to_string(), |x| x.join("/")).as_str())
-        .replace("{{user-name}}", user.map_or_else(|| "[user not found]", |_| reply.user.0.as_str()))
+        .replace("{{thread-title}}", thread.map_or("[deleted thread]", |x| x.title.as_str()))
+        .replace("{{user-name}}", reply.user.0.as_str())
+        .replace("{{display-name}}", render_display_name(author, &reply.user).as_str())
         .replace("{{content}}", reply.content.as_str())
+}
+
+/// The content of an item in inspection or waiting for approval, as it was when it got there
+fn render_moderatable(db: &DB, item: &ModItem) -> String {
+    let author = item.author.as_ref().map(|(_, x)| x);
+    match &item.thing {
+        Moderatable::User => read_to_string("assets/element/inspection-user.html").unwrap()
+            .replace("{{display-name}}", item.author.as_ref().map_or_else(String::new, |(id, user)| render_display_name(Some(user), id)).as_str())
+            .replace("{{pronouns}}", author.and_then(|x| x.pronouns.as_ref()).map_or_else(|| "".to_string(), |x| x.join("/")).as_str())
+            .replace("{{about}}", author.map_or("", |x| x.about.as_str())),
+        Moderatable::Topic(topic_id, topic, _, _) => read_to_string("assets/element/inspection-topic.html").unwrap()
+            .replace("{{topic-name}}", topic_id.0.as_str())
+            .replace("{{thread-count}}", topic.threads.len().to_string().as_str())
+            .replace("{{about}}", topic.about.as_str()),
+        Moderatable::Thread(_, thread, replies, topic_id, _) => read_to_string("assets/element/inspection-thread.html").unwrap()
+            .replace("{{title}}", thread.title.as_str())
+            .replace("{{topic-name}}", topic_id.0.as_str())
+            .replace("{{reply-count}}", thread.replies.len().to_string().as_str())
+            .replace("{{content}}", thread.replies.first().and_then(|x| replies.get(x)).map_or("", |x| x.content.as_str())),
+        Moderatable::Reply(_, reply, thread_id, _) => render_inspection_reply(
+            read_to_string("assets/element/reply/inspection-reply.html").unwrap().as_str(),
+            author, thread_id, db.get_thread(thread_id), reply),
+    }
+}
+
+fn render_item_author(item: &ModItem) -> String {
+    item.author_id().map_or_else(|| "nobody".to_string(), |x| format!("<a href=\"/u/{}\">{}</a>", x.0, x.0))
+}
+
+/// The original place is gone, so the moderator has to pick a new one
+fn render_restore_target(db: &DB, user: &UserID, item: &ModItem) -> String {
+    match &item.thing {
+        Moderatable::Reply(_, _, thread_id, _) if db.get_thread(thread_id).is_none() =>
+            read_to_string("assets/element/restore-into-thread.html").unwrap(),
+        Moderatable::Thread(_, _, _, topic_id, _) if db.get_topic(topic_id).is_none() =>
+            read_to_string("assets/element/restore-into-topic.html").unwrap()
+                .replace("{{topics}}", db.get_sorted_topic_ids().into_iter()
+                    .filter(|x| db.can(user, Capability::ModerateTopic, Target::Topic(x)))
+                    .map(|x| format!("<option value=\"{}\">λ/{}</option>", x.0, x.0))
+                    .collect::<Vec<_>>().join("").as_str()),
+        _ => "".to_string(),
+    }
+}
+
+fn render_item_reason(item: &ModItem) -> String {
+    if item.reason.is_empty() { "".to_string() } else {
+        format!("<p>Reason: {}</p>", html_escape::encode_text(&item.reason))
+    }
+}
+
+/// One item in the inspection queue along with what can be done about it
+pub fn render_inspection_item(db: &DB, user: &UserID, preloaded_html: &str, id: &ModItemID, item: &ModItem) -> String {
+    let thing = render_moderatable(db, item);
+    let appeal = match &item.appeal {
+        Some(Appeal { statement, denied: None, .. }) => format!("<p>Appealed, see <a href=/appeals>appeals</a>:</p><blockquote class=appeal>{}</blockquote>",
+            html_escape::encode_text(statement)),
+        Some(Appeal { statement, denied: Some(response), .. }) => format!("<p>Appeal turned down{}:</p><blockquote class=appeal>{}</blockquote>",
+            if response.is_empty() { "".to_string() } else { format!(", \"{}\"", html_escape::encode_text(response)) },
+            html_escape::encode_text(statement)),
+        None => "".to_string(),
+    };
+    let ban_author = match item.author_id() {
+        Some(author) => if let Some(ban) = db.get_ban(author) {
+            format!("<span>Author is {}</span>", html_escape::encode_text(&ban.describe()))
+        } else if !db.can(user, Capability::ManageUsers, Target::Site) || author == user {
+            "".to_string()
+        } else {
+            read_to_string("assets/element/ban-author.html").unwrap()
+                .replace("{{ban-fields}}", render_ban_fields().as_str())
+        },
+        None => "".to_string(),
+    };
+    preloaded_html
+        .replace("{{reason}}", render_item_reason(item).as_str())
+        .replace("{{kind}}", item.thing.kind())
+        .replace("{{author}}", render_item_author(item).as_str())
+        .replace("{{moderated-time}}", format_date_time(&item.moderated).as_str())
+        .replace("{{thing}}", thing.as_str())
+        .replace("{{appeal}}", appeal.as_str())
+        .replace("{{restore-target}}", render_restore_target(db, user, item).as_str())
+        .replace("{{ban-author}}", ban_author.as_str())
+        .replace("{{notes}}", render_item_notes(db, user, item, "/inspection").as_str())
+        .replace("{{item-id}}", id.0.as_str())
+}
+
+/// One appeal waiting on a decision
+pub fn render_appeal_item(db: &DB, user: &UserID, preloaded_html: &str, id: &ModItemID, item: &ModItem) -> String {
+    let thing = render_moderatable(db, item);
+    let (statement, appealed) = item.appeal.as_ref()
+        .map_or_else(Default::default, |x| (x.statement.clone(), format_date_time(&x.created)));
+    preloaded_html
+        .replace("{{reason}}", render_item_reason(item).as_str())
+        .replace("{{kind}}", item.thing.kind())
+        .replace("{{author}}", render_item_author(item).as_str())
+        .replace("{{moderated-time}}", format_date_time(&item.moderated).as_str())
+        .replace("{{appealed-time}}", appealed.as_str())
+        .replace("{{thing}}", thing.as_str())
+        .replace("{{restore-target}}", render_restore_target(db, user, item).as_str())
+        .replace("{{notes}}", render_item_notes(db, user, item, "/appeals").as_str())
+        .replace("{{item-id}}", id.0.as_str())
+        .replace("{{statement}}", html_escape::encode_text(&statement).as_ref())
+}
+
+/// Something of the viewer's that is in inspection, and whether they can still appeal it
+pub fn render_own_item(db: &DB, preloaded_html: &str, id: &ModItemID, item: &ModItem) -> String {
+    let thing = render_moderatable(db, item);
+    let appeal = match &item.appeal {
+        None => read_to_string("assets/element/appeal-form.html").unwrap()
+            .replace("{{item-id}}", id.0.as_str()),
+        Some(Appeal { denied: None, .. }) => "<p>You appealed this, moderators will get back to you</p>".to_string(),
+        Some(Appeal { denied: Some(response), .. }) if response.is_empty() => "<p>Your appeal was turned down</p>".to_string(),
+        Some(Appeal { denied: Some(response), .. }) =>
+            format!("<p>Your appeal was turned down: {}</p>", html_escape::encode_text(response)),
+    };
+    preloaded_html
+        .replace("{{reason}}", render_item_reason(item).as_str())
+        .replace("{{kind}}", item.thing.kind())
+        .replace("{{moderated-time}}", format_date_time(&item.moderated).as_str())
+        .replace("{{thing}}", thing.as_str())
+        .replace("{{appeal}}", appeal.as_str())
+}
+
+/// About the post itself as well as its author, who may have a history
+fn render_item_notes(db: &DB, user: &UserID, item: &ModItem, back: &str) -> String {
+    let post = match &item.thing {
+        Moderatable::Reply(reply_id, _, thread_id, _) => Some(Reported::Reply(thread_id.clone(), reply_id.clone())),
+        Moderatable::Thread(thread_id, _, _, _, _) => Some(Reported::Thread(thread_id.clone())),
+        Moderatable::Topic(_, _, _, _) | Moderatable::User => None,
+    };
+    post.map(NoteSubject::Post).into_iter()
+        .chain(item.author_id().map(|x| NoteSubject::User(x.clone())))
+        .map(|x| render_notes(db, Some(user), &x, back))
+        .collect::<Vec<_>>().join("")
+}
+
+/// A post waiting for approval, for those who get to decide
+pub fn render_pending_item(db: &DB, preloaded_html: &str, id: &ModItemID, item: &ModItem) -> String {
+    preloaded_html
+        .replace("{{kind}}", item.thing.kind())
+        .replace("{{author}}", render_item_author(item).as_str())
+        .replace("{{submitted-time}}", format_date_time(&item.moderated).as_str())
+        .replace("{{thing}}", render_moderatable(db, item).as_str())
+        .replace("{{item-id}}", id.0.as_str())
+}
+
+/// The author's own thread that's waiting for approval, it doesn't have a page to link to yet
+pub fn render_pending_thread(preloaded_html: &str, thread: &Thread, first: Option<&Reply>) -> String {
+    preloaded_html
+        .replace("{{title}}", thread.title.as_str())
+        .replace("{{content}}", first.map_or("", |x| x.content.as_str()))
+}
+
+/// Nested lists of who invited whom, starting from `roots`
+pub fn render_invite_tree(tree: &HashMap<&UserID, Vec<&UserID>>, roots: &[&UserID]) -> String {
+    if roots.is_empty() {
+        return "".to_string();
+    }
+    let items = roots.iter().map(|user| {
+        let children = tree.get(user).map_or_else(Vec::new, |x| x.clone());
+        format!("<li><a href=\"/u/{0}\">{0}</a>{1}</li>", user.0, render_invite_tree(tree, &children))
+    }).collect::<Vec<_>>();
+    format!("<ul class=invite-tree>{}</ul>", items.join(""))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::scratch_store;
+    use super::*;
+
+    #[test]
+    fn threads_only_preview_what_the_viewer_may_see() {
+        let _store = scratch_store();
+        let mut db = DB::load();
+        let [alice, bob, carol] = ["alice", "bob", "carol"].map(|x| db.create_new_user(x, None));
+        let topic = db.create_new_topic(&alice, "cats", &alice).unwrap();
+        let thread = db.create_new_thread(&topic, "Hello".to_string(), &bob).unwrap();
+        db.try_reply("Opening", &thread, &bob, vec![]).unwrap();
+        db.ban_user(&bob, &alice, BanKind::Shadow, None, String::new()).unwrap();
+        db.try_reply("Shadowed", &thread, &bob, vec![]).unwrap();
+        let html = "{{title}}: {{content}}";
+
+        assert_eq!(render_thread(&db, Some(&carol), html, &thread).as_deref(), Some("Hello: Opening"));
+        assert_eq!(render_thread(&db, Some(&bob), html, &thread).as_deref(), Some("Hello: Shadowed"));
+        db.block_user(&carol, &bob, true).unwrap();
+        assert_eq!(render_thread(&db, Some(&carol), html, &thread), None);
+    }
+
+    #[test]
+    fn favorites_leave_with_their_thread() {
+        let _store = scratch_store();
+        let mut db = DB::load();
+        let [alice, bob] = ["alice", "bob"].map(|x| db.create_new_user(x, None));
+        let topic = db.create_new_topic(&alice, "cats", &alice).unwrap();
+        let thread = db.create_new_thread(&topic, "Hello".to_string(), &bob).unwrap();
+        db.try_reply("Hi", &thread, &bob, vec![]).unwrap();
+        db.favorite_thread(&bob, &thread, true);
+
+        db.move_thread_to_inspection(&thread, &alice, String::new());
+        assert!(!DB::load().is_thread_favorite(&bob, &thread));
+        // Ids that point nowhere are skipped rather than taking the page down
+        db.favorite_thread(&bob, &ThreadID("gone".to_string()), true);
+        let session = UserSession { user: bob, session_id: None, scopes: None };
+        let response = render_page(&db, Some(&session), &CsrfToken(String::new()), || "Page".to_string());
+        assert_eq!(response.status(), StatusCode::OK);
+    }
 }
\ No newline at end of file
@@ -1,27 +1,56 @@
 use std::sync::Mutex;
 
-use crate::{auth::{Auth, UserSession, build_session_cookie}, db::DB};
-use actix_web::{get, post, web::{Form, Data}, cookie::Cookie, HttpResponse, http::{StatusCode, header::LOCATION}};
+use std::fs::read_to_string;
+
+use crate::{auth::{Auth, UserSession, LoginStep, PendingLoginID, LoginError, build_session_cookie, build_pending_login_cookie, oidc::{self, OidcError, build_oidc_state_cookie}}, db::DB, data::UserID};
+use actix_web::{get, post, web::{self, Form, Data, Query}, cookie::Cookie, HttpRequest, HttpResponse, http::{StatusCode, header::{LOCATION, ContentType}}};
 use serde::Deserialize;
 
+use super::with_error;
+
 #[derive(Deserialize)]
 pub struct Signup {
     user_name: String,
     password: String,
+    invite: Option<String>,
 }
 #[derive(Deserialize)]
 pub struct Login {
     user_name: String,
     password: String,
 }
+#[derive(Deserialize)]
+pub struct LoginTotp {
+    code: String,
+}
+#[derive(Deserialize)]
+pub struct OidcCallback {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+}
+
+/// Used for throttling, there's no reverse proxy in front of us to trust forwarding headers from
+fn client_ip(req: &HttpRequest) -> String {
+    req.peer_addr().map_or_else(|| "unknown".to_string(), |x| x.ip().to_string())
+}
+
+/// Where to go after a successful login, moderators that have to enroll in 2FA get sent to do so
+fn after_login(db: &DB, user: &UserID) -> String {
+    if db.lacks_required_2fa(user) {
+        with_error("/settings/2fa", "Your moderation powers require 2FA, enable it to use them")
+    } else {
+        "/".to_string()
+    }
+}
 
 #[post("/auth/signup")]
-pub async fn auth_signup(auth: Data<Mutex<Auth>>, db: Data<Mutex<DB>>, Form(form): Form<Signup>) -> HttpResponse {
+pub async fn auth_signup(auth: Data<Mutex<Auth>>, db: Data<Mutex<DB>>, req: HttpRequest, Form(form): Form<Signup>) -> HttpResponse {
     let mut db = db.lock().unwrap();
     let mut auth = auth.lock().unwrap();
-    match auth.signup(form.user_name.as_str(), form.password.as_str(), &mut db) {
-        Ok(_) => {
-            let (_, session_id) = auth.login(form.user_name.as_str(), form.password.as_str()).unwrap();
+    match auth.signup(form.user_name.as_str(), form.password.as_str(), form.invite.as_deref(), &client_ip(&req), &mut db) {
+        Ok(user_id) => {
+            let session_id = auth.create_session(user_id);
             HttpResponse::build(StatusCode::SEE_OTHER)
                 .append_header((LOCATION, "/"))
                 .cookie(build_session_cookie(&session_id))
@@ -29,30 +58,69 @@ pub async fn auth_signup(auth: Data<Mutex<Auth>>, db: Data<Mutex<DB>>, Form(form
         },
         Err(e) => 
             HttpResponse::build(StatusCode::SEE_OTHER)
-                .append_header((LOCATION, format!("/signup?error={}", e.to_string())))
+                .append_header((LOCATION, format!("/signup?{}", serde_urlencoded::to_string([
+                    ("error", e.to_string()),
+                    ("invite", form.invite.unwrap_or_default()),
+                ]).unwrap())))
                 .finish(),
     }
 }
 
 #[post("/auth/login")]
-pub async fn auth_login(auth: Data<Mutex<Auth>>, Form(form): Form<Login>) -> HttpResponse {
+pub async fn auth_login(auth: Data<Mutex<Auth>>, db: Data<Mutex<DB>>, req: HttpRequest, Form(form): Form<Login>) -> HttpResponse {
+    let db = db.lock().unwrap();
     let mut auth = auth.lock().unwrap();
-    let session_id = auth.login(form.user_name.as_str(), form.password.as_str());
-    match session_id {
-        Ok((_, session_id)) => {
+    let step = auth.login(form.user_name.as_str(), form.password.as_str(), &client_ip(&req), &db);
+    match step {
+        Ok(LoginStep::Done(user_id, session_id)) => {
             HttpResponse::build(StatusCode::SEE_OTHER)
-                .append_header((LOCATION, "/"))
+                .append_header((LOCATION, after_login(&db, &user_id)))
                 .cookie(build_session_cookie(&session_id))
                 .finish()
         },
+        Ok(LoginStep::NeedsTotp(pending_id)) => {
+            HttpResponse::build(StatusCode::SEE_OTHER)
+                .append_header((LOCATION, "/login/2fa"))
+                .cookie(build_pending_login_cookie(&pending_id))
+                .finish()
+        },
         Err(e) =>
             HttpResponse::build(StatusCode::SEE_OTHER)
-                .append_header((LOCATION, format!("/login?error={}", e.to_string())))
+                .append_header((LOCATION, with_error("/login", e)))
                 .finish()
     }
 }
 
-#[get("/auth/logout")]
+#[post("/auth/login/2fa")]
+pub async fn auth_login_totp(auth: Data<Mutex<Auth>>, db: Data<Mutex<DB>>, req: HttpRequest, Form(form): Form<LoginTotp>) -> HttpResponse {
+    let db = db.lock().unwrap();
+    let mut auth = auth.lock().unwrap();
+    let result = match req.cookie("pending-login") {
+        Some(cookie) => auth.login_totp(&PendingLoginID(cookie.value().to_string()), form.code.as_str(), &db),
+        None => Err(LoginError::PendingLoginExpired),
+    };
+    match result {
+        Ok((user_id, session_id)) => {
+            let mut pending_cookie = Cookie::new("pending-login", "");
+            pending_cookie.make_removal();
+            HttpResponse::build(StatusCode::SEE_OTHER)
+                .append_header((LOCATION, after_login(&db, &user_id)))
+                .cookie(build_session_cookie(&session_id))
+                .cookie(pending_cookie)
+                .finish()
+        },
+        Err(e @ (LoginError::PendingLoginExpired | LoginError::Banned(_))) =>
+            HttpResponse::build(StatusCode::SEE_OTHER)
+                .append_header((LOCATION, with_error("/login", e)))
+                .finish(),
+        Err(e) =>
+            HttpResponse::build(StatusCode::SEE_OTHER)
+                .append_header((LOCATION, with_error("/login/2fa", e)))
+                .finish(),
+    }
+}
+
+#[post("/auth/logout")]
 pub async fn auth_logout(auth: Data<Mutex<Auth>>, user: UserSession) -> HttpResponse {
     let mut auth = auth.lock().unwrap();
     auth.logout(user);
@@ -62,4 +130,109 @@ pub async fn auth_logout(auth: Data<Mutex<Auth>>, user: UserSession) -> HttpResp
         .append_header((LOCATION, "/"))
         .cookie(cookie)
         .finish()
+}
+
+/// Looks up the issuer's endpoints and returns the flow's state along with where to send the browser
+async fn start_oidc(auth: &Data<Mutex<Auth>>, link_to: Option<UserID>) -> Result<(String, String), OidcError> {
+    let config = auth.lock().unwrap().oidc_config().cloned().ok_or(OidcError::NotConfigured)?;
+    let provider = web::block(move || oidc::discover(&config)).await
+        .map_err(|_| OidcError::Unreachable)??;
+    auth.lock().unwrap().begin_oidc(provider, link_to)
+}
+
+/// The session cookie is `Strict`, browsers won't send it along a redirect chain that started at the issuer,
+/// so the last hop is a page of our own
+fn finish_oidc_redirect(to: &str, step: Option<&LoginStep>) -> HttpResponse {
+    let mut state_cookie = Cookie::build("oidc-state", "").path("/auth/oidc").finish();
+    state_cookie.make_removal();
+    let mut builder = HttpResponse::build(StatusCode::OK);
+    builder.content_type(ContentType::html()).cookie(state_cookie);
+    match step {
+        Some(LoginStep::Done(_, session_id)) => builder.cookie(build_session_cookie(session_id)),
+        Some(LoginStep::NeedsTotp(pending_id)) => builder.cookie(build_pending_login_cookie(pending_id)),
+        None => &mut builder,
+    };
+    builder.body(read_to_string("assets/page/oidc-redirect.html").unwrap()
+        .replace("{{location}}", html_escape::encode_double_quoted_attribute(to).as_ref()))
+}
+
+#[get("/auth/oidc/start")]
+pub async fn auth_oidc_start(auth: Data<Mutex<Auth>>) -> HttpResponse {
+    match start_oidc(&auth, None).await {
+        Ok((state, url)) =>
+            HttpResponse::build(StatusCode::SEE_OTHER)
+                .append_header((LOCATION, url))
+                .cookie(build_oidc_state_cookie(&state))
+                .finish(),
+        Err(e) =>
+            HttpResponse::build(StatusCode::SEE_OTHER)
+                .append_header((LOCATION, with_error("/login", e)))
+                .finish(),
+    }
+}
+
+#[post("/auth/oidc/link")]
+pub async fn auth_oidc_link(auth: Data<Mutex<Auth>>, user: UserSession) -> HttpResponse {
+    if !user.is_browser() {
+        return HttpResponse::Forbidden().finish();
+    }
+    let mut builder = HttpResponse::build(StatusCode::SEE_OTHER);
+    user.keep(&mut builder);
+    match start_oidc(&auth, Some(user.user.clone())).await {
+        Ok((state, url)) => builder
+            .append_header((LOCATION, url))
+            .cookie(build_oidc_state_cookie(&state))
+            .finish(),
+        Err(e) => builder
+            .append_header((LOCATION, with_error("/settings", e)))
+            .finish(),
+    }
+}
+
+#[post("/auth/oidc/unlink")]
+pub async fn auth_oidc_unlink(auth: Data<Mutex<Auth>>, user: UserSession) -> HttpResponse {
+    if !user.is_browser() {
+        return HttpResponse::Forbidden().finish();
+    }
+    let location = match auth.lock().unwrap().unlink_oidc_identities(&user.user) {
+        Ok(()) => "/settings".to_string(),
+        Err(e) => with_error("/settings", e),
+    };
+    let mut builder = HttpResponse::build(StatusCode::SEE_OTHER);
+    builder.append_header((LOCATION, location));
+    user.keep(&mut builder);
+    builder.finish()
+}
+
+#[get("/auth/oidc/callback")]
+pub async fn auth_oidc_callback(auth: Data<Mutex<Auth>>, db: Data<Mutex<DB>>, req: HttpRequest, Query(query): Query<OidcCallback>) -> HttpResponse {
+    let flow = match (req.cookie("oidc-state"), &query.state) {
+        (Some(cookie), Some(state)) if cookie.value() == state => auth.lock().unwrap().take_oidc_flow(state),
+        _ => None,
+    };
+    let Some(flow) = flow else {
+        return finish_oidc_redirect(&with_error("/login", OidcError::UnknownFlow), None);
+    };
+    let link_to = flow.link_to.clone();
+    let error_page = if link_to.is_some() { "/settings" } else { "/login" };
+    // Nothing else can log in while the lock is held, so it has to be let go of before talking to the issuer
+    let config = auth.lock().unwrap().oidc_config().cloned();
+    let id_token = match (query.error, query.code, config) {
+        (Some(error), _, _) => Err(OidcError::Denied(error)),
+        (None, None, _) => Err(OidcError::InvalidResponse),
+        (None, Some(_), None) => Err(OidcError::NotConfigured),
+        (None, Some(code), Some(config)) => web::block(move || oidc::redeem_code(&config, &flow, &code)).await
+            .unwrap_or(Err(OidcError::Unreachable)),
+    };
+    let mut db = db.lock().unwrap();
+    let mut auth = auth.lock().unwrap();
+    let result = id_token.and_then(|id_token| match &link_to {
+        Some(user) => auth.link_oidc_identity(user, id_token.identity, &db)
+            .map(|_| finish_oidc_redirect("/settings", None)),
+        None => auth.login_oidc(id_token, &mut db).map(|step| match &step {
+            LoginStep::Done(user, _) => finish_oidc_redirect(&after_login(&db, user), Some(&step)),
+            LoginStep::NeedsTotp(_) => finish_oidc_redirect("/login/2fa", Some(&step)),
+        }),
+    });
+    result.unwrap_or_else(|e| finish_oidc_redirect(&with_error(error_page, e), None))
 }
\ No newline at end of file
@@ -1,8 +1,10 @@
-use std::{sync::Mutex, collections::HashSet};
+use std::{sync::Mutex, collections::HashSet, fs::read_to_string};
 
-use crate::data::{TopicID, ReplyID};
-use crate::{auth::UserSession, data::ThreadID};
-use crate::db::DB;
+use crate::data::{TopicID, ReplyID, UserID, ModItemID, RegistrationMode, Capability, Reported, ReportReason, BanKind, ModAction, RoleGrant, RoleID, NoteSubject};
+use crate::{auth::{Auth, UserSession, token::{Scope, ApiTokenID}}, data::ThreadID};
+use crate::db::{DB, permissions::Target, ban::BanError, automod::Verdict, topic::TopicError, account::AccountError};
+use crate::render::render_page;
+use crate::csrf::CsrfToken;
 use actix_web::http::StatusCode;
 use actix_web::http::header::LOCATION;
 use actix_web::web::{Data, Form};
@@ -12,6 +14,8 @@ use regex::Regex;
 use thiserror::Error;
 use serde::Deserialize;
 
+use super::with_error;
+
 #[derive(Deserialize)]
 pub struct MakeReply {
     thread: String,
@@ -37,8 +41,21 @@ pub struct FavoriteThread {
     favorite: bool,
 }
 
+#[derive(Deserialize)]
+pub struct BlockUser {
+    user: String,
+    block: bool,
+}
+
+#[derive(Deserialize)]
+pub struct MuteTopic {
+    topic: String,
+    mute: bool,
+}
+
 #[derive(Deserialize)]
 pub struct SettingsForm {
+    pub display_name: String,
     pub about: String,
     pub pronouns: String,
 }
@@ -53,12 +70,244 @@ pub struct DeleteReply {
 pub struct ModReply {
     thread: String,
     reply: String,
+    /// Goes into the moderation log
+    reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct EnableTotp {
+    secret: String,
+    code: String,
+}
+
+#[derive(Deserialize)]
+pub struct DisableTotp {
+    code: String,
+}
+
+#[derive(Deserialize)]
+pub struct SiteSettingsForm {
+    require_mod_2fa: Option<String>,
+    registration_mode: String,
+    invite_quota: usize,
+    report_threshold: usize,
+    approval_posts: usize,
+}
+
+#[derive(Deserialize)]
+pub struct MakeReport {
+    thread: String,
+    reply: Option<String>,
+    reason: String,
+    note: String,
+}
+
+#[derive(Deserialize)]
+pub struct DismissReports {
+    thread: String,
+    reply: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ModThread {
+    thread: String,
+    reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct LockThread {
+    thread: String,
+    locked: bool,
+}
+
+#[derive(Deserialize)]
+pub struct PinThread {
+    thread: String,
+    pinned: bool,
+}
+
+#[derive(Deserialize)]
+pub struct MoveThread {
+    thread: String,
+    topic: String,
+}
+
+#[derive(Deserialize)]
+pub struct ModTopic {
+    topic: String,
+    reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ModUser {
+    user: String,
+    reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct TopicAbout {
+    topic: String,
+    about: String,
+}
+
+#[derive(Deserialize)]
+pub struct CreateTopic {
+    name: String,
+    owner: String,
+    about: String,
+}
+
+#[derive(Deserialize)]
+pub struct EditTopic {
+    topic: String,
+    about: String,
+    color: String,
+}
+
+#[derive(Deserialize)]
+pub struct RenameTopic {
+    topic: String,
+    name: String,
+}
+
+#[derive(Deserialize)]
+pub struct ArchiveTopic {
+    topic: String,
+    archived: bool,
+}
+
+#[derive(Deserialize)]
+pub struct DeleteTopic {
+    topic: String,
+    confirm: String,
+}
+
+#[derive(Deserialize)]
+pub struct ManageRole {
+    user: String,
+    role: String,
+    /// Empty for a site wide grant
+    topic: String,
+}
+
+#[derive(Deserialize)]
+pub struct AccountAction {
+    user: String,
+}
+
+#[derive(Deserialize)]
+pub struct PurgeUser {
+    user: String,
+    confirm: String,
+    reason: String,
+}
+
+#[derive(Deserialize)]
+pub struct AddNote {
+    /// Either a user, or a thread along with the reply if it's about one
+    user: Option<String>,
+    thread: Option<String>,
+    reply: Option<String>,
+    /// The page the notes were shown on
+    back: String,
+    content: String,
+}
+
+#[derive(Deserialize)]
+pub struct EditNote {
+    user: Option<String>,
+    thread: Option<String>,
+    reply: Option<String>,
+    back: String,
+    index: usize,
+    content: String,
+}
+
+#[derive(Deserialize)]
+pub struct InspectionItem {
+    item: String,
+    /// Where to restore to instead when the original place is gone
+    target: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct MakeAppeal {
+    item: String,
+    statement: String,
+}
+
+#[derive(Deserialize)]
+pub struct DenyAppeal {
+    item: String,
+    response: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct PendingItem {
+    item: String,
+    reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct BanAuthor {
+    item: String,
+    kind: String,
+    /// Empty for a permanent ban
+    days: String,
+    reason: String,
+}
+
+#[derive(Deserialize)]
+pub struct BanUser {
+    user: String,
+    kind: String,
+    days: String,
+    reason: String,
+}
+
+#[derive(Deserialize)]
+pub struct RequestRename {
+    new_name: String,
+}
+
+#[derive(Deserialize)]
+pub struct ReviewRename {
+    user: String,
+}
+
+#[derive(Deserialize)]
+pub struct CreateApiToken {
+    name: String,
+    read: Option<String>,
+    post: Option<String>,
+    moderate: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct RevokeApiToken {
+    token: String,
+}
+
+#[derive(Deserialize)]
+pub struct Unlock {
+    key: String,
 }
 
 #[derive(Error, Debug)]
 pub enum SettingsError {
     #[error("Invalid pronouns format. Must be either nominative/oblique/possessive or empty")]
     InvalidPronounsFormat,
+    #[error("Enable 2FA on your own account before requiring it from others")]
+    OwnTotpMissing,
+    #[error("Display name can't be longer than {MAX_DISPLAY_NAME_LENGTH} characters")]
+    DisplayNameTooLong,
+}
+
+const MAX_DISPLAY_NAME_LENGTH: usize = 48;
+
+/// For API tokens that lack the scope a route needs
+fn forbidden() -> HttpResponse {
+    HttpResponse::Forbidden().finish()
 }
 
 fn redirect(to: String, session: &UserSession) -> HttpResponse {
@@ -67,8 +316,15 @@ fn redirect(to: String, session: &UserSession) -> HttpResponse {
         .finish()
 }
 
+const HELD_MESSAGE: &str = "Your post is waiting for a moderator to have a look at it";
+const PENDING_MESSAGE: &str = "Your post is awaiting approval, new accounts' first posts are checked by hand";
+const BLOCKED_MESSAGE: &str = "Someone you mentioned has blocked you";
+
 #[post("/do/reply")]
 pub async fn make_reply(db: Data<Mutex<DB>>, user: UserSession, Form(input): Form<MakeReply>) -> HttpResponse {
+    if !user.allows(Scope::Post) {
+        return forbidden();
+    }
     let content = Builder::new()
         .tags(HashSet::from([
             "a", "abbr", "acronym", "area", "aside", "b", "bdi",
@@ -88,12 +344,42 @@ pub async fn make_reply(db: Data<Mutex<DB>>, user: UserSession, Form(input): For
     let content = Regex::new(" *\\n *<").unwrap().replace_all(content.as_str(), "<").to_string();
     let content = Regex::new(" *\\n *").unwrap().replace_all(content.as_str(), "<br>").to_string();
     let content = Regex::new(" +").unwrap().replace_all(content.as_str(), " ").to_string();
-    let _ = db.lock().unwrap().try_reply(content.as_str(), &ThreadID(input.thread.clone()), &user.user);
-    redirect(format!("/t/{}", input.thread), &user)
+    let mut db = db.lock().unwrap();
+    let thread_id = ThreadID(input.thread.clone());
+    let page = format!("/t/{}", input.thread);
+    if !db.can(&user.user, Capability::Post, Target::Thread(&thread_id)) {
+        return redirect(page, &user);
+    }
+    let Some(topic_id) = db.get_thread_topic(&thread_id).cloned() else {
+        return redirect(page, &user);
+    };
+    if db.mentions_blocker(&user.user, content.as_str()) {
+        return redirect(with_error(&page, BLOCKED_MESSAGE), &user);
+    }
+    match db.run_automod(&user.user, &topic_id, Some(&thread_id), "", content.as_str()) {
+        Verdict::Pass(tags) if db.needs_approval(&user.user, &topic_id) => {
+            if db.submit_reply(content.as_str(), &thread_id, &user.user, tags) {
+                redirect(with_error(&page, PENDING_MESSAGE), &user)
+            } else {
+                redirect(page, &user)
+            }
+        },
+        Verdict::Pass(tags) => {
+            let _ = db.try_reply(content.as_str(), &thread_id, &user.user, tags);
+            redirect(page, &user)
+        },
+        Verdict::Hold(rules) if db.hold_reply(content.as_str(), &thread_id, &user.user, &rules) =>
+            redirect(with_error(&page, HELD_MESSAGE), &user),
+        Verdict::Hold(_) => redirect(page, &user),
+        Verdict::Reject(message) => redirect(with_error(&page, message), &user),
+    }
 }
 
 #[post("/do/thread")]
 pub async fn make_thread(db: Data<Mutex<DB>>, user: UserSession, Form(input): Form<MakeThread>) -> HttpResponse {
+    if !user.allows(Scope::Post) {
+        return forbidden();
+    }
     let content = Builder::new()
         .tags(HashSet::from(["b", "i", "em", "q", "u", "var"]))
         .clean_content_tags(HashSet::from(["script", "style", "iframe"]))
@@ -102,22 +388,72 @@ pub async fn make_thread(db: Data<Mutex<DB>>, user: UserSession, Form(input): Fo
         .replace("\n", "")
         .replace("  ", "");
     let mut db = db.lock().unwrap();
-    let Some(id) = db.create_new_thread(&TopicID(input.topic.clone()), input.title.clone()) else {
+    let topic_id = TopicID(input.topic.clone());
+    if !db.can_post(&user.user) || !db.can(&user.user, Capability::CreateThread, Target::Topic(&topic_id)) {
         return redirect(format!("/λ/{}", input.topic), &user);
+    }
+    let page = format!("/λ/{}", input.topic);
+    if db.mentions_blocker(&user.user, input.title.as_str()) || db.mentions_blocker(&user.user, content.as_str()) {
+        return redirect(with_error(&page, BLOCKED_MESSAGE), &user);
+    }
+    let tags = match db.run_automod(&user.user, &topic_id, None, input.title.as_str(), content.as_str()) {
+        Verdict::Pass(tags) => tags,
+        Verdict::Hold(rules) if db.hold_thread(&topic_id, input.title.clone(), content.as_str(), &user.user, &rules) =>
+            return redirect(with_error(&page, HELD_MESSAGE), &user),
+        Verdict::Hold(_) => return redirect(page, &user),
+        Verdict::Reject(message) => return redirect(with_error(&page, message), &user),
     };
-    let _ = db.try_reply(content.as_str(), &id, &user.user);
+    if db.needs_approval(&user.user, &topic_id) {
+        return if db.submit_thread(&topic_id, input.title.clone(), content.as_str(), &user.user, tags) {
+            redirect(with_error(&page, PENDING_MESSAGE), &user)
+        } else {
+            redirect(page, &user)
+        };
+    }
+    let Some(id) = db.create_new_thread(&topic_id, input.title.clone(), &user.user) else {
+        return redirect(page, &user);
+    };
+    let _ = db.try_reply(content.as_str(), &id, &user.user, tags);
     redirect(format!("/t/{}", id.0), &user)
 }
 
 #[post("/do/fav-topic")]
 pub async fn favorite_topic(db: Data<Mutex<DB>>, user: UserSession, Form(input): Form<FavoriteTopic>) -> HttpResponse {
+    if !user.allows(Scope::Post) {
+        return forbidden();
+    }
     let _ = db.lock().unwrap()
         .favorite_topic(&user.user, &TopicID(input.topic.clone()), input.favorite);
     redirect(format!("/λ/{}", input.topic), &user)
 }
 
+#[post("/do/block")]
+pub async fn block_user(db: Data<Mutex<DB>>, user: UserSession, Form(input): Form<BlockUser>) -> HttpResponse {
+    if !user.is_browser() {
+        return forbidden();
+    }
+    match db.lock().unwrap().block_user(&user.user, &UserID(input.user.trim().to_string()), input.block) {
+        Ok(()) => redirect("/settings".to_string(), &user),
+        Err(e) => redirect(with_error("/settings", e), &user),
+    }
+}
+
+#[post("/do/mute-topic")]
+pub async fn mute_topic(db: Data<Mutex<DB>>, user: UserSession, Form(input): Form<MuteTopic>) -> HttpResponse {
+    if !user.is_browser() {
+        return forbidden();
+    }
+    match db.lock().unwrap().mute_topic(&user.user, &TopicID(input.topic.clone()), input.mute) {
+        Ok(()) => redirect("/settings".to_string(), &user),
+        Err(e) => redirect(with_error("/settings", e), &user),
+    }
+}
+
 #[post("/do/fav-thread")]
 pub async fn favorite_thread(db: Data<Mutex<DB>>, user: UserSession, Form(input): Form<FavoriteThread>) -> HttpResponse {
+    if !user.allows(Scope::Post) {
+        return forbidden();
+    }
     let _ = db.lock().unwrap()
         .favorite_thread(&user.user, &ThreadID(input.thread.clone()), input.favorite);
     redirect(format!("/t/{}", input.thread), &user)
@@ -125,44 +461,767 @@ pub async fn favorite_thread(db: Data<Mutex<DB>>, user: UserSession, Form(input)
 
 #[post("/do/update-settings")]
 pub async fn update_settings(db: Data<Mutex<DB>>, user: UserSession, Form(input): Form<SettingsForm>) -> HttpResponse {
+    if !user.is_browser() {
+        return forbidden();
+    }
+    let display_name = match input.display_name.trim() {
+        "" => Ok(None),
+        x if x.chars().count() > MAX_DISPLAY_NAME_LENGTH => Err(SettingsError::DisplayNameTooLong),
+        x => Ok(Some(x.to_string())),
+    };
     let pronouns = match input.pronouns.split("/").take(3).collect::<Vec<_>>().as_slice() {
         [""] => Ok(None),
         [x, y, z] => Ok(Some([x.to_string(), y.to_string(), z.to_string()])),
         _ => Err(SettingsError::InvalidPronounsFormat),
     };
-    match pronouns {
-        Ok(pronouns) => {
+    match display_name.and_then(|x| pronouns.map(|y| (x, y))) {
+        Ok((display_name, pronouns)) => {
             let about = Builder::new()
                 .add_tags(HashSet::from(["style"]))
                 .clean_content_tags(HashSet::from(["script"]))
                 .clean(input.about.as_str())
                 .to_string();
             let _ = db.lock().unwrap()
-                .update_user(&user.user, about, pronouns);
+                .update_user(&user.user, display_name, about, pronouns);
             redirect(format!("/u/{}", user.user.0), &user)
         },
-        Err(e) => redirect(format!("/settings?error={}", e.to_string()), &user)
+        Err(e) => redirect(with_error("/settings", e), &user)
     }
 }
 
 #[post("/do/delete/reply")]
 pub async fn delete_reply(db: Data<Mutex<DB>>, user: UserSession, Form(input): Form<DeleteReply>) -> HttpResponse {
+    if !user.allows(Scope::Post) {
+        return forbidden();
+    }
     let mut db = db.lock().unwrap();
+    let thread_id = ThreadID(input.thread.clone());
     let reply_id = ReplyID(input.reply.clone());
-    let Some(reply) = db.get_reply(&reply_id) else {
-        return redirect("/inspection".to_string(), &user);
-    };
-    if user.user == reply.user {
-        db.delete_reply(&ThreadID(input.thread.clone()), &reply_id);
+    if db.can_delete_reply(&user.user, &thread_id, &reply_id) {
+        db.delete_reply_by(&thread_id, &reply_id, &user.user);
     }
     redirect("/inspection".to_string(), &user)
 }
 
+#[post("/do/report")]
+pub async fn make_report(db: Data<Mutex<DB>>, user: UserSession, Form(input): Form<MakeReport>) -> HttpResponse {
+    if !user.allows(Scope::Post) {
+        return forbidden();
+    }
+    let mut db = db.lock().unwrap();
+    if db.is_banned(&user.user) {
+        return redirect(format!("/t/{}", input.thread), &user);
+    }
+    let thread_id = ThreadID(input.thread.clone());
+    let reported = match &input.reply {
+        Some(reply) => Reported::Reply(thread_id, ReplyID(reply.clone())),
+        None => Reported::Thread(thread_id),
+    };
+    let reason = ReportReason::parse(input.reason.as_str()).unwrap_or(ReportReason::Other);
+    match db.report(&user.user, reported, reason, input.note.trim().to_string()) {
+        Ok(()) => redirect(format!("/t/{}", input.thread), &user),
+        Err(e) => redirect(with_error(&format!("/t/{}", input.thread), e), &user),
+    }
+}
+
+#[post("/do/mod/dismiss-reports")]
+pub async fn dismiss_reports(db: Data<Mutex<DB>>, user: UserSession, Form(input): Form<DismissReports>) -> HttpResponse {
+    if !user.allows(Scope::Moderate) {
+        return forbidden();
+    }
+    let mut db = db.lock().unwrap();
+    let thread_id = ThreadID(input.thread.clone());
+    if db.can(&user.user, Capability::ModerateTopic, Target::Thread(&thread_id)) {
+        db.dismiss_reports(&match &input.reply {
+            Some(reply) => Reported::Reply(thread_id, ReplyID(reply.clone())),
+            None => Reported::Thread(thread_id),
+        }, &user.user);
+    }
+    redirect("/reports".to_string(), &user)
+}
+
 #[post("/do/mod/reply")]
 pub async fn move_reply_to_inspection(db: Data<Mutex<DB>>, user: UserSession, Form(input): Form<ModReply>) -> HttpResponse {
+    if !user.allows(Scope::Moderate) {
+        return forbidden();
+    }
+    let mut db = db.lock().unwrap();
+    let thread_id = ThreadID(input.thread.clone());
+    if db.can(&user.user, Capability::ModerateTopic, Target::Thread(&thread_id)) {
+        db.move_reply_to_inspection(&thread_id, &ReplyID(input.reply.clone()), &user.user, input.reason.unwrap_or_default());
+    }
+    redirect("/inspection".to_string(), &user)
+}
+
+#[post("/do/mod/thread")]
+pub async fn move_thread_to_inspection(db: Data<Mutex<DB>>, user: UserSession, Form(input): Form<ModThread>) -> HttpResponse {
+    if !user.allows(Scope::Moderate) {
+        return forbidden();
+    }
     let mut db = db.lock().unwrap();
-    if db.is_admin(&user.user) {
-        db.move_reply_to_inspection(&ThreadID(input.thread.clone()), &ReplyID(input.reply.clone()));
+    let thread_id = ThreadID(input.thread.clone());
+    if db.can(&user.user, Capability::ModerateTopic, Target::Thread(&thread_id)) {
+        db.move_thread_to_inspection(&thread_id, &user.user, input.reason.unwrap_or_default());
     }
     redirect("/inspection".to_string(), &user)
+}
+
+#[post("/do/mod/lock")]
+pub async fn lock_thread(db: Data<Mutex<DB>>, user: UserSession, Form(input): Form<LockThread>) -> HttpResponse {
+    if !user.allows(Scope::Moderate) {
+        return forbidden();
+    }
+    let mut db = db.lock().unwrap();
+    let thread_id = ThreadID(input.thread.clone());
+    if db.can(&user.user, Capability::ModerateTopic, Target::Thread(&thread_id)) {
+        db.set_thread_locked(&thread_id, input.locked, &user.user);
+    }
+    redirect(format!("/t/{}", input.thread), &user)
+}
+
+#[post("/do/mod/pin")]
+pub async fn pin_thread(db: Data<Mutex<DB>>, user: UserSession, Form(input): Form<PinThread>) -> HttpResponse {
+    if !user.allows(Scope::Moderate) {
+        return forbidden();
+    }
+    let mut db = db.lock().unwrap();
+    let thread_id = ThreadID(input.thread.clone());
+    if db.can(&user.user, Capability::ModerateTopic, Target::Thread(&thread_id)) {
+        db.set_thread_pinned(&thread_id, input.pinned, &user.user);
+    }
+    redirect(format!("/t/{}", input.thread), &user)
+}
+
+/// Takes moderating both where the thread is and where it goes
+#[post("/do/mod/move-thread")]
+pub async fn move_thread(db: Data<Mutex<DB>>, user: UserSession, Form(input): Form<MoveThread>) -> HttpResponse {
+    if !user.allows(Scope::Moderate) {
+        return forbidden();
+    }
+    let mut db = db.lock().unwrap();
+    let thread_id = ThreadID(input.thread.clone());
+    let topic_id = TopicID(input.topic.clone());
+    if !db.can(&user.user, Capability::ModerateTopic, Target::Thread(&thread_id))
+        || !db.can(&user.user, Capability::ModerateTopic, Target::Topic(&topic_id)) {
+        return redirect(format!("/t/{}", input.thread), &user);
+    }
+    match db.move_thread(&thread_id, &topic_id, &user.user) {
+        Ok(()) => redirect(format!("/t/{}", input.thread), &user),
+        Err(e) => redirect(with_error(&format!("/t/{}", input.thread), e), &user),
+    }
+}
+
+#[post("/do/mod/topic")]
+pub async fn move_topic_to_inspection(db: Data<Mutex<DB>>, user: UserSession, Form(input): Form<ModTopic>) -> HttpResponse {
+    if !user.allows(Scope::Moderate) {
+        return forbidden();
+    }
+    let mut db = db.lock().unwrap();
+    if db.can(&user.user, Capability::ManageTopics, Target::Site) {
+        db.move_topic_to_inspection(&TopicID(input.topic.clone()), &user.user, input.reason.unwrap_or_default());
+    }
+    redirect("/inspection".to_string(), &user)
+}
+
+#[post("/do/mod/user")]
+pub async fn move_user_profile_to_inspection(db: Data<Mutex<DB>>, user: UserSession, Form(input): Form<ModUser>) -> HttpResponse {
+    if !user.allows(Scope::Moderate) {
+        return forbidden();
+    }
+    let mut db = db.lock().unwrap();
+    if db.can(&user.user, Capability::ManageUsers, Target::Site) {
+        db.move_user_profile_to_inspection(&UserID(input.user.clone()), &user.user, input.reason.unwrap_or_default());
+    }
+    redirect("/inspection".to_string(), &user)
+}
+
+#[post("/do/topic/about")]
+pub async fn update_topic_about(db: Data<Mutex<DB>>, user: UserSession, Form(input): Form<TopicAbout>) -> HttpResponse {
+    if !user.allows(Scope::Moderate) {
+        return forbidden();
+    }
+    let mut db = db.lock().unwrap();
+    let topic_id = TopicID(input.topic.clone());
+    if db.can(&user.user, Capability::ModerateTopic, Target::Topic(&topic_id)) {
+        db.update_topic_about(&topic_id, clean_topic_about(&input.about));
+    }
+    redirect(format!("/λ/{}", input.topic), &user)
+}
+
+/// Descriptions may have formatting, just nothing that runs
+fn clean_topic_about(about: &str) -> String {
+    Builder::new()
+        .clean_content_tags(HashSet::from(["script"]))
+        .clean(about)
+        .to_string()
+}
+
+#[post("/do/admin/topic/create")]
+pub async fn create_topic(db: Data<Mutex<DB>>, user: UserSession, Form(input): Form<CreateTopic>) -> HttpResponse {
+    if !user.is_browser() {
+        return forbidden();
+    }
+    let mut db = db.lock().unwrap();
+    if !db.can(&user.user, Capability::ManageTopics, Target::Site) {
+        return redirect("/".to_string(), &user);
+    }
+    let owner = match input.owner.trim() {
+        "" => user.user.clone(),
+        x => UserID(x.to_string()),
+    };
+    let topic_id = match db.create_new_topic(&owner, input.name.trim(), &user.user) {
+        Ok(x) => x,
+        Err(e) => return redirect(with_error("/admin/topics", e), &user),
+    };
+    let about = clean_topic_about(&input.about);
+    if !about.is_empty() {
+        db.update_topic_about(&topic_id, about);
+    }
+    redirect(format!("/λ/{}", topic_id.0), &user)
+}
+
+#[post("/do/admin/topic/edit")]
+pub async fn edit_topic(db: Data<Mutex<DB>>, user: UserSession, Form(input): Form<EditTopic>) -> HttpResponse {
+    if !user.is_browser() {
+        return forbidden();
+    }
+    let mut db = db.lock().unwrap();
+    if !db.can(&user.user, Capability::ManageTopics, Target::Site) {
+        return redirect("/".to_string(), &user);
+    }
+    let page = format!("/admin/topics/{}", input.topic);
+    match db.edit_topic(&TopicID(input.topic.clone()), clean_topic_about(&input.about), &input.color, &user.user) {
+        Ok(()) => redirect(page, &user),
+        Err(e) => redirect(with_error(&page, e), &user),
+    }
+}
+
+#[post("/do/admin/topic/rename")]
+pub async fn rename_topic(db: Data<Mutex<DB>>, user: UserSession, Form(input): Form<RenameTopic>) -> HttpResponse {
+    if !user.is_browser() {
+        return forbidden();
+    }
+    let mut db = db.lock().unwrap();
+    if !db.can(&user.user, Capability::ManageTopics, Target::Site) {
+        return redirect("/".to_string(), &user);
+    }
+    match db.rename_topic(&TopicID(input.topic.clone()), input.name.trim(), &user.user) {
+        Ok(new) => redirect(format!("/admin/topics/{}", new.0), &user),
+        Err(e) => redirect(with_error(&format!("/admin/topics/{}", input.topic), e), &user),
+    }
+}
+
+#[post("/do/admin/topic/archive")]
+pub async fn archive_topic(db: Data<Mutex<DB>>, user: UserSession, Form(input): Form<ArchiveTopic>) -> HttpResponse {
+    if !user.is_browser() {
+        return forbidden();
+    }
+    let mut db = db.lock().unwrap();
+    if !db.can(&user.user, Capability::ManageTopics, Target::Site) {
+        return redirect("/".to_string(), &user);
+    }
+    let page = format!("/admin/topics/{}", input.topic);
+    match db.set_topic_archived(&TopicID(input.topic.clone()), input.archived, &user.user) {
+        Ok(()) => redirect(page, &user),
+        Err(e) => redirect(with_error(&page, e), &user),
+    }
+}
+
+#[post("/do/admin/topic/delete")]
+pub async fn delete_topic(db: Data<Mutex<DB>>, user: UserSession, Form(input): Form<DeleteTopic>) -> HttpResponse {
+    if !user.is_browser() {
+        return forbidden();
+    }
+    let mut db = db.lock().unwrap();
+    if !db.can(&user.user, Capability::ManageTopics, Target::Site) {
+        return redirect("/".to_string(), &user);
+    }
+    let page = format!("/admin/topics/{}", input.topic);
+    if input.confirm.trim() != input.topic {
+        return redirect(with_error(&page, TopicError::NotConfirmed), &user);
+    }
+    match db.delete_topic(&TopicID(input.topic.clone()), &user.user) {
+        Ok(()) => redirect("/admin/topics".to_string(), &user),
+        Err(e) => redirect(with_error(&page, e), &user),
+    }
+}
+
+fn role_grant(input: &ManageRole) -> RoleGrant {
+    RoleGrant {
+        role: RoleID(input.role.clone()),
+        topic: Some(input.topic.trim()).filter(|x| !x.is_empty()).map(|x| TopicID(x.to_string())),
+    }
+}
+
+#[post("/do/admin/user/grant")]
+pub async fn grant_role(db: Data<Mutex<DB>>, user: UserSession, Form(input): Form<ManageRole>) -> HttpResponse {
+    if !user.is_browser() {
+        return forbidden();
+    }
+    let mut db = db.lock().unwrap();
+    let user_id = UserID(input.user.clone());
+    if !db.is_admin(&user.user) || db.get_user(&user_id).is_none() {
+        return redirect("/".to_string(), &user);
+    }
+    let page = format!("/admin/users/{}", input.user);
+    let grant = role_grant(&input);
+    if let Err(e) = db.check_grant(&grant) {
+        return redirect(with_error(&page, e), &user);
+    }
+    db.grant_permission(&user_id, grant, &user.user);
+    redirect(page, &user)
+}
+
+#[post("/do/admin/user/revoke")]
+pub async fn revoke_role(db: Data<Mutex<DB>>, user: UserSession, Form(input): Form<ManageRole>) -> HttpResponse {
+    if !user.is_browser() {
+        return forbidden();
+    }
+    let mut db = db.lock().unwrap();
+    if !db.is_admin(&user.user) {
+        return redirect("/".to_string(), &user);
+    }
+    let page = format!("/admin/users/{}", input.user);
+    if !db.revoke_permission(&UserID(input.user.clone()), role_grant(&input), &user.user) {
+        return redirect(with_error(&page, AccountError::NotGranted), &user);
+    }
+    redirect(page, &user)
+}
+
+#[post("/do/admin/user/logout")]
+pub async fn force_logout(db: Data<Mutex<DB>>, auth: Data<Mutex<Auth>>, user: UserSession, Form(input): Form<AccountAction>) -> HttpResponse {
+    if !user.is_browser() {
+        return forbidden();
+    }
+    let mut db = db.lock().unwrap();
+    let user_id = UserID(input.user.clone());
+    if !db.can_manage_account(&user.user, &user_id) || db.get_user(&user_id).is_none() || user_id == user.user {
+        return redirect("/".to_string(), &user);
+    }
+    let (sessions, tokens) = {
+        let mut auth = auth.lock().unwrap();
+        let counts = (auth.get_sessions(&user_id).len(), auth.api_tokens_for_user(&user_id).len());
+        auth.end_sessions(&user_id);
+        auth.revoke_api_tokens(&user_id);
+        counts
+    };
+    db.record_account_action(ModAction::ForceLogout, &user_id, &user.user, String::new(), format!("{} sessions, {} API tokens", sessions, tokens));
+    redirect(format!("/admin/users/{}", input.user), &user)
+}
+
+#[post("/do/admin/user/reset-password")]
+pub async fn reset_password(db: Data<Mutex<DB>>, auth: Data<Mutex<Auth>>, user: UserSession, csrf: CsrfToken, Form(input): Form<AccountAction>) -> HttpResponse {
+    if !user.is_browser() {
+        return forbidden();
+    }
+    let mut db = db.lock().unwrap();
+    let user_id = UserID(input.user.clone());
+    if !db.can_manage_account(&user.user, &user_id) || db.get_user(&user_id).is_none() || user_id == user.user {
+        return redirect("/".to_string(), &user);
+    }
+    let password = auth.lock().unwrap().reset_password(&user_id);
+    db.record_account_action(ModAction::ResetPassword, &user_id, &user.user, String::new(), String::new());
+    // Shown once right here instead of redirecting, the password never goes into a URL
+    render_page(&db, Some(&user), &csrf, || {
+        read_to_string("assets/page/password-reset.html").unwrap()
+            .replace("{{user-name}}", user_id.0.as_str())
+            .replace("{{password}}", password.as_str())
+    })
+}
+
+#[post("/do/admin/user/purge")]
+pub async fn purge_user(db: Data<Mutex<DB>>, user: UserSession, Form(input): Form<PurgeUser>) -> HttpResponse {
+    if !user.is_browser() {
+        return forbidden();
+    }
+    let mut db = db.lock().unwrap();
+    let user_id = UserID(input.user.clone());
+    if !db.can_manage_account(&user.user, &user_id) || db.get_user(&user_id).is_none() {
+        return redirect("/".to_string(), &user);
+    }
+    let page = format!("/admin/users/{}", input.user);
+    if input.confirm.trim() != input.user {
+        return redirect(with_error(&page, AccountError::NotConfirmed), &user);
+    }
+    db.purge_user_content(&user_id, &user.user, input.reason.trim().to_string());
+    redirect(page, &user)
+}
+
+fn note_subject(user: &Option<String>, thread: &Option<String>, reply: &Option<String>) -> Option<NoteSubject> {
+    match (user, thread, reply) {
+        (Some(user), _, _) => Some(NoteSubject::User(UserID(user.clone()))),
+        (None, Some(thread), Some(reply)) => Some(NoteSubject::Post(Reported::Reply(ThreadID(thread.clone()), ReplyID(reply.clone())))),
+        (None, Some(thread), None) => Some(NoteSubject::Post(Reported::Thread(ThreadID(thread.clone())))),
+        (None, None, _) => None,
+    }
+}
+
+/// Only pages on this site, so the form can't send anyone elsewhere.
+/// Browsers read `\` as `/` and drop tabs and newlines, so `/\evil.com` would lead elsewhere just like `//evil.com`
+fn local_page(page: &str) -> String {
+    let local = page.starts_with('/') && !page.starts_with("//")
+        && !page.chars().any(|x| x == '\\' || x.is_control());
+    if local { page.to_string() } else { "/".to_string() }
+}
+
+#[post("/do/mod/note")]
+pub async fn add_note(db: Data<Mutex<DB>>, user: UserSession, Form(input): Form<AddNote>) -> HttpResponse {
+    if !user.allows(Scope::Moderate) {
+        return forbidden();
+    }
+    let mut db = db.lock().unwrap();
+    let page = local_page(&input.back);
+    let Some(subject) = note_subject(&input.user, &input.thread, &input.reply).filter(|x| db.can_see_notes(&user.user, x)) else {
+        return redirect(page, &user);
+    };
+    match db.add_note(subject, input.content.trim().to_string(), &user.user) {
+        Ok(()) => redirect(page, &user),
+        Err(e) => redirect(with_error(&page, e), &user),
+    }
+}
+
+#[post("/do/mod/note/edit")]
+pub async fn edit_note(db: Data<Mutex<DB>>, user: UserSession, Form(input): Form<EditNote>) -> HttpResponse {
+    if !user.allows(Scope::Moderate) {
+        return forbidden();
+    }
+    let mut db = db.lock().unwrap();
+    let page = local_page(&input.back);
+    let Some(subject) = note_subject(&input.user, &input.thread, &input.reply).filter(|x| db.can_see_notes(&user.user, x)) else {
+        return redirect(page, &user);
+    };
+    match db.edit_note(&subject, input.index, input.content.trim().to_string(), &user.user) {
+        Ok(()) => redirect(page, &user),
+        Err(e) => redirect(with_error(&page, e), &user),
+    }
+}
+
+#[post("/do/mod/restore")]
+pub async fn restore_from_inspection(db: Data<Mutex<DB>>, user: UserSession, Form(input): Form<InspectionItem>) -> HttpResponse {
+    if !user.allows(Scope::Moderate) {
+        return forbidden();
+    }
+    let mut db = db.lock().unwrap();
+    let id = ModItemID(input.item.clone());
+    let target = input.target.as_deref().filter(|x| !x.is_empty());
+    if !db.can_handle_inspection_item(&user.user, &id, target) {
+        return redirect("/inspection".to_string(), &user);
+    }
+    match db.restore_from_inspection(&id, target, &user.user) {
+        Ok(()) => redirect("/inspection".to_string(), &user),
+        Err(e) => redirect(with_error("/inspection", e), &user),
+    }
+}
+
+#[post("/do/appeal")]
+pub async fn make_appeal(db: Data<Mutex<DB>>, user: UserSession, Form(input): Form<MakeAppeal>) -> HttpResponse {
+    if !user.allows(Scope::Post) {
+        return forbidden();
+    }
+    let mut db = db.lock().unwrap();
+    match db.appeal(&ModItemID(input.item), &user.user, input.statement.trim().to_string()) {
+        Ok(()) => redirect("/notices".to_string(), &user),
+        Err(e) => redirect(with_error("/notices", e), &user),
+    }
+}
+
+#[post("/do/mod/appeal/accept")]
+pub async fn accept_appeal(db: Data<Mutex<DB>>, user: UserSession, Form(input): Form<InspectionItem>) -> HttpResponse {
+    if !user.allows(Scope::Moderate) {
+        return forbidden();
+    }
+    let mut db = db.lock().unwrap();
+    let id = ModItemID(input.item.clone());
+    let target = input.target.as_deref().filter(|x| !x.is_empty());
+    if !db.can_handle_inspection_item(&user.user, &id, target) {
+        return redirect("/appeals".to_string(), &user);
+    }
+    match db.accept_appeal(&id, target, &user.user) {
+        Ok(()) => redirect("/appeals".to_string(), &user),
+        Err(e) => redirect(with_error("/appeals", e), &user),
+    }
+}
+
+#[post("/do/mod/appeal/deny")]
+pub async fn deny_appeal(db: Data<Mutex<DB>>, user: UserSession, Form(input): Form<DenyAppeal>) -> HttpResponse {
+    if !user.allows(Scope::Moderate) {
+        return forbidden();
+    }
+    let mut db = db.lock().unwrap();
+    let id = ModItemID(input.item.clone());
+    if !db.can_handle_inspection_item(&user.user, &id, None) {
+        return redirect("/appeals".to_string(), &user);
+    }
+    match db.deny_appeal(&id, &user.user, input.response.unwrap_or_default().trim().to_string()) {
+        Ok(()) => redirect("/appeals".to_string(), &user),
+        Err(e) => redirect(with_error("/appeals", e), &user),
+    }
+}
+
+#[post("/do/approve")]
+pub async fn approve_pending(db: Data<Mutex<DB>>, user: UserSession, Form(input): Form<PendingItem>) -> HttpResponse {
+    if !user.allows(Scope::Moderate) {
+        return forbidden();
+    }
+    let mut db = db.lock().unwrap();
+    let id = ModItemID(input.item.clone());
+    if !db.can_approve(&user.user, &id) {
+        return redirect("/approvals".to_string(), &user);
+    }
+    match db.approve_pending(&id, &user.user) {
+        Ok(()) => redirect("/approvals".to_string(), &user),
+        Err(e) => redirect(with_error("/approvals", e), &user),
+    }
+}
+
+#[post("/do/reject")]
+pub async fn reject_pending(db: Data<Mutex<DB>>, user: UserSession, Form(input): Form<PendingItem>) -> HttpResponse {
+    if !user.allows(Scope::Moderate) {
+        return forbidden();
+    }
+    let mut db = db.lock().unwrap();
+    let id = ModItemID(input.item.clone());
+    if db.can_approve(&user.user, &id) {
+        db.reject_pending(&id, &user.user, input.reason.unwrap_or_default().trim().to_string());
+    }
+    redirect("/approvals".to_string(), &user)
+}
+
+#[post("/do/mod/purge")]
+pub async fn delete_from_inspection(db: Data<Mutex<DB>>, user: UserSession, Form(input): Form<InspectionItem>) -> HttpResponse {
+    if !user.allows(Scope::Moderate) {
+        return forbidden();
+    }
+    let mut db = db.lock().unwrap();
+    let id = ModItemID(input.item.clone());
+    if db.can_handle_inspection_item(&user.user, &id, None) {
+        db.delete_from_inspection(&id, &user.user);
+    }
+    redirect("/inspection".to_string(), &user)
+}
+
+/// Ends the sessions and revokes the API tokens of fully banned users, suspended ones may keep reading
+fn ban(db: &mut DB, auth: &Data<Mutex<Auth>>, user: &UserID, moderator: &UserID, kind: &str, days: &str, reason: &str) -> Result<(), BanError> {
+    let kind = BanKind::parse(kind).unwrap_or(BanKind::Ban);
+    let days = match days.trim() {
+        "" => None,
+        x => Some(x.parse::<i64>().map_err(|_| BanError::InvalidDuration)?),
+    };
+    db.ban_user(user, moderator, kind, days, reason.trim().to_string())?;
+    if kind == BanKind::Ban {
+        let mut auth = auth.lock().unwrap();
+        auth.end_sessions(user);
+        auth.revoke_api_tokens(user);
+    }
+    Ok(())
+}
+
+#[post("/do/mod/ban-author")]
+pub async fn ban_author(db: Data<Mutex<DB>>, auth: Data<Mutex<Auth>>, user: UserSession, Form(input): Form<BanAuthor>) -> HttpResponse {
+    if !user.allows(Scope::Moderate) {
+        return forbidden();
+    }
+    let mut db = db.lock().unwrap();
+    let Some(author) = db.get_inspection_item(&ModItemID(input.item.clone())).and_then(|x| x.author_id().cloned()) else {
+        return redirect("/inspection".to_string(), &user);
+    };
+    if !db.can_manage_account(&user.user, &author) {
+        return redirect("/inspection".to_string(), &user);
+    }
+    match ban(&mut db, &auth, &author, &user.user, &input.kind, &input.days, &input.reason) {
+        Ok(()) => redirect("/inspection".to_string(), &user),
+        Err(e) => redirect(with_error("/inspection", e), &user),
+    }
+}
+
+#[post("/do/mod/ban")]
+pub async fn ban_user(db: Data<Mutex<DB>>, auth: Data<Mutex<Auth>>, user: UserSession, Form(input): Form<BanUser>) -> HttpResponse {
+    if !user.allows(Scope::Moderate) {
+        return forbidden();
+    }
+    let mut db = db.lock().unwrap();
+    let target = UserID(input.user.clone());
+    if !db.can_manage_account(&user.user, &target) {
+        return redirect(format!("/u/{}", input.user), &user);
+    }
+    match ban(&mut db, &auth, &target, &user.user, &input.kind, &input.days, &input.reason) {
+        Ok(()) => redirect(format!("/u/{}", input.user), &user),
+        Err(e) => redirect(with_error(&format!("/u/{}", input.user), e), &user),
+    }
+}
+
+#[post("/do/mod/unban")]
+pub async fn unban_user(db: Data<Mutex<DB>>, user: UserSession, Form(input): Form<ModUser>) -> HttpResponse {
+    if !user.allows(Scope::Moderate) {
+        return forbidden();
+    }
+    let mut db = db.lock().unwrap();
+    let target = UserID(input.user.clone());
+    if db.can_manage_account(&user.user, &target) {
+        db.lift_ban(&target, &user.user);
+    }
+    redirect(format!("/u/{}", input.user), &user)
+}
+
+#[post("/do/2fa/enable")]
+pub async fn enable_totp(db: Data<Mutex<DB>>, auth: Data<Mutex<Auth>>, user: UserSession, csrf: CsrfToken, Form(input): Form<EnableTotp>) -> HttpResponse {
+    if !user.is_browser() {
+        return forbidden();
+    }
+    let db = db.lock().unwrap();
+    let result = auth.lock().unwrap().enable_totp(&user.user, input.secret.as_str(), input.code.as_str());
+    match result {
+        Ok(recovery_codes) => render_page(&db, Some(&user), &csrf, || {
+            read_to_string("assets/page/recovery-codes.html").unwrap()
+                .replace("{{recovery-codes}}", recovery_codes.iter().map(|x| format!("<li>{x}</li>")).collect::<Vec<_>>().join("").as_str())
+        }),
+        Err(e) => redirect(with_error("/settings/2fa", e), &user),
+    }
+}
+
+#[post("/do/2fa/disable")]
+pub async fn disable_totp(auth: Data<Mutex<Auth>>, user: UserSession, Form(input): Form<DisableTotp>) -> HttpResponse {
+    if !user.is_browser() {
+        return forbidden();
+    }
+    let result = auth.lock().unwrap().disable_totp(&user.user, input.code.as_str());
+    match result {
+        Ok(()) => redirect("/settings".to_string(), &user),
+        Err(e) => redirect(with_error("/settings/2fa", e), &user),
+    }
+}
+
+#[post("/do/admin/settings")]
+pub async fn update_site_settings(db: Data<Mutex<DB>>, auth: Data<Mutex<Auth>>, user: UserSession, Form(input): Form<SiteSettingsForm>) -> HttpResponse {
+    if !user.is_browser() {
+        return forbidden();
+    }
+    let mut db = db.lock().unwrap();
+    if !db.is_admin(&user.user) {
+        return redirect("/".to_string(), &user);
+    }
+    let require_mod_2fa = input.require_mod_2fa.is_some();
+    if require_mod_2fa && !auth.lock().unwrap().has_totp(&user.user) {
+        return redirect(with_error("/admin/settings", SettingsError::OwnTotpMissing), &user);
+    }
+    db.set_require_mod_2fa(require_mod_2fa);
+    let mode = RegistrationMode::parse(input.registration_mode.as_str()).unwrap_or(db.get_settings().registration_mode);
+    db.set_registration(mode, input.invite_quota);
+    db.set_report_threshold(input.report_threshold);
+    db.set_approval_posts(input.approval_posts);
+    redirect("/admin/settings".to_string(), &user)
+}
+
+#[post("/do/admin/unlock")]
+pub async fn unlock(db: Data<Mutex<DB>>, auth: Data<Mutex<Auth>>, user: UserSession, Form(input): Form<Unlock>) -> HttpResponse {
+    if !user.is_browser() {
+        return forbidden();
+    }
+    let db = db.lock().unwrap();
+    if db.can(&user.user, Capability::ManageUsers, Target::Site) {
+        auth.lock().unwrap().unlock(input.key.as_str());
+    }
+    redirect("/admin/lockouts".to_string(), &user)
+}
+
+#[post("/do/rename/request")]
+pub async fn request_rename(db: Data<Mutex<DB>>, user: UserSession, Form(input): Form<RequestRename>) -> HttpResponse {
+    if !user.is_browser() {
+        return forbidden();
+    }
+    match db.lock().unwrap().request_rename(&user.user, input.new_name.trim()) {
+        Ok(()) => redirect("/settings".to_string(), &user),
+        Err(e) => redirect(with_error("/settings", e), &user),
+    }
+}
+
+#[post("/do/admin/rename/approve")]
+pub async fn approve_rename(db: Data<Mutex<DB>>, auth: Data<Mutex<Auth>>, user: UserSession, Form(input): Form<ReviewRename>) -> HttpResponse {
+    if !user.is_browser() {
+        return forbidden();
+    }
+    let mut db = db.lock().unwrap();
+    if !db.can(&user.user, Capability::ManageUsers, Target::Site) {
+        return redirect("/admin/renames".to_string(), &user);
+    }
+    let old = UserID(input.user.clone());
+    let Some(new) = db.get_rename_request(&old).cloned() else {
+        return redirect("/admin/renames".to_string(), &user);
+    };
+    match db.rename_user(&old, &new) {
+        Ok(()) => {
+            auth.lock().unwrap().rename_user(&old, &new);
+            redirect("/admin/renames".to_string(), &user)
+        },
+        Err(e) => redirect(with_error("/admin/renames", e), &user),
+    }
+}
+
+#[post("/do/admin/rename/reject")]
+pub async fn reject_rename(db: Data<Mutex<DB>>, user: UserSession, Form(input): Form<ReviewRename>) -> HttpResponse {
+    if !user.is_browser() {
+        return forbidden();
+    }
+    let mut db = db.lock().unwrap();
+    if db.can(&user.user, Capability::ManageUsers, Target::Site) {
+        db.cancel_rename_request(&UserID(input.user.clone()));
+    }
+    redirect("/admin/renames".to_string(), &user)
+}
+
+#[post("/do/token/create")]
+pub async fn create_api_token(db: Data<Mutex<DB>>, auth: Data<Mutex<Auth>>, user: UserSession, csrf: CsrfToken, Form(input): Form<CreateApiToken>) -> HttpResponse {
+    if !user.is_browser() {
+        return forbidden();
+    }
+    let db = db.lock().unwrap();
+    let scopes = [(Scope::Read, &input.read), (Scope::Post, &input.post), (Scope::Moderate, &input.moderate)]
+        .into_iter()
+        .filter(|(_, checked)| checked.is_some())
+        .map(|(scope, _)| scope)
+        .collect();
+    let result = auth.lock().unwrap().create_api_token(&user.user, input.name.as_str(), scopes);
+    match result {
+        Ok(token) => render_page(&db, Some(&user), &csrf, || {
+            read_to_string("assets/page/api-token-created.html").unwrap()
+                .replace("{{name}}", html_escape::encode_text(input.name.trim()).as_ref())
+                .replace("{{token}}", token.as_str())
+        }),
+        Err(e) => redirect(with_error("/settings/tokens", e), &user),
+    }
+}
+
+#[post("/do/token/revoke")]
+pub async fn revoke_api_token(auth: Data<Mutex<Auth>>, user: UserSession, Form(input): Form<RevokeApiToken>) -> HttpResponse {
+    if !user.is_browser() {
+        return forbidden();
+    }
+    auth.lock().unwrap().revoke_api_token(&user.user, &ApiTokenID(input.token));
+    redirect("/settings/tokens".to_string(), &user)
+}
+
+#[post("/do/invite/create")]
+pub async fn create_invite(db: Data<Mutex<DB>>, user: UserSession) -> HttpResponse {
+    if !user.is_browser() {
+        return forbidden();
+    }
+    match db.lock().unwrap().create_invite(&user.user) {
+        Ok(_) => redirect("/settings/invites".to_string(), &user),
+        Err(e) => redirect(with_error("/settings/invites", e), &user),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn local_pages_stay_on_the_site() {
+        for page in ["/u/alice", "/t/abc?error=x", "/λ/cats"] {
+            assert_eq!(local_page(page), page);
+        }
+        for page in ["//evil.com", "/\\evil.com", "/\\/evil.com", "/u/\\x", "/\t/evil.com", "/\n/evil.com", "https://evil.com", ""] {
+            assert_eq!(local_page(page), "/", "{:?}", page);
+        }
+    }
 }
\ No newline at end of file
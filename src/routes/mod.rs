@@ -6,4 +6,9 @@ mod resources;
 pub use auth::*;
 pub use interact::*;
 pub use page::*;
-pub use resources::*;
\ No newline at end of file
+pub use resources::*;
+
+/// Errors can say anything, like the reason for a ban, so they get encoded to fit into the query string
+fn with_error(page: &str, error: impl ToString) -> String {
+    format!("{}?{}", page, serde_urlencoded::to_string([("error", error.to_string())]).unwrap())
+}
\ No newline at end of file
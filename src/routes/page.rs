@@ -1,37 +1,76 @@
 use std::{fs::read_to_string, sync::Mutex};
-use actix_web::{get, HttpResponse, web::{Data, Path, Query}};
+use actix_web::{get, HttpResponse, http::{StatusCode, header}, web::{Data, Path, Query}};
+use chrono::NaiveDate;
 use serde::Deserialize;
-use crate::{db::DB, render::{render_page, render_topic_fav, render_thread, render_user_reply, render_reply, render_thread_fav, render_inspection_reply}, auth::UserSession, data::{UserID, TopicID, ThreadID, Moderatable}};
+use crate::{db::{DB, inspection::inspection_requirement, permissions::Target, topic::MAX_TOPIC_NAME_LENGTH}, render::{format_date_time, render_invite_tree, render_page, render_topic_fav, render_thread, render_user_reply, render_reply, render_thread_fav, render_inspection_item, render_display_name, render_mod_button, render_report_form, render_report_group, render_ban_controls, render_mod_record, render_thread_tools, render_reply_tags, render_automod_rule, render_automod_hit, render_pending_item, render_pending_thread, render_notes, render_appeal_item, render_own_item}, auth::{Auth, UserSession, totp}, csrf::CsrfToken, data::{UserID, TopicID, ThreadID, RegistrationMode, Capability, Reported, RecordFilter, Moderatable, RoleGrant, NoteSubject}};
+
+/// For pages that exist, but the user has no business seeing
+fn render_not_found(db: &DB, user: Option<&UserSession>, csrf: &CsrfToken) -> HttpResponse {
+    let mut response = render_page(db, user, csrf, || {
+        read_to_string("assets/page/404.html").unwrap()
+    });
+    *response.status_mut() = StatusCode::NOT_FOUND;
+    response
+}
 
 #[get("/")]
-pub async fn page_home(db: Data<Mutex<DB>>, user: Option<UserSession>) -> HttpResponse {
+pub async fn page_home(db: Data<Mutex<DB>>, user: Option<UserSession>, csrf: CsrfToken) -> HttpResponse {
     let db = db.lock().unwrap();
-    render_page(&db, user.as_ref(), || {
+    render_page(&db, user.as_ref(), &csrf, || {
         read_to_string("assets/page/root.html").unwrap()
     })
 }
 
 #[get("/λ/{topic_name}")]
-pub async fn page_topic(db: Data<Mutex<DB>>, user: Option<UserSession>, topic_name: Path<String>) -> HttpResponse {
+pub async fn page_topic(db: Data<Mutex<DB>>, user: Option<UserSession>, csrf: CsrfToken, topic_name: Path<String>, query: Query<Error>) -> HttpResponse {
     let db = db.lock().unwrap();
     let topic_id = TopicID(topic_name.into_inner());
     let topic = db.get_topic(&topic_id);
+    if let (None, Some(new_id)) = (topic, db.get_topic_redirect(&topic_id)) {
+        return HttpResponse::MovedPermanently()
+            .insert_header((header::LOCATION, format!("/λ/{}", new_id.0)))
+            .finish();
+    }
     match topic {
-        Some(topic) => render_page(&db, user.as_ref(), || {
+        Some(topic) => render_page(&db, user.as_ref(), &csrf, || {
             let thread_html = read_to_string("assets/element/thread.html").unwrap();
-            let threads: Vec<String> = db.get_sorted_threads(&topic_id).iter().map(|x| render_thread(&db, thread_html.as_str(), x)).collect();
+            let threads: Vec<String> = db.get_sorted_threads(&topic_id).into_iter()
+                .filter(|x| !db.is_hidden_from(user.as_ref().map(|x| &x.user), &Reported::Thread((*x).clone())))
+                .filter(|x| !db.is_thread_blocked(user.as_ref().map(|x| &x.user), x))
+                .filter_map(|x| render_thread(&db, user.as_ref().map(|x| &x.user), thread_html.as_str(), x)).collect();
+            let pending_html = read_to_string("assets/element/pending-thread.html").unwrap();
+            let pending = user.as_ref().map_or_else(Vec::new, |x| db.get_pending_by(&x.user)).into_iter()
+                .filter_map(|x| match &x.thing {
+                    Moderatable::Thread(_, thread, replies, id, _) if id == &topic_id =>
+                        Some(render_pending_thread(pending_html.as_str(), thread, thread.replies.first().and_then(|x| replies.get(x)))),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
             let html = read_to_string("assets/page/topic.html").unwrap();
             html
                 .replace("{{insert-favorite-here}}", render_topic_fav(&db, user.as_ref().map(|x| &x.user), &topic_id).as_str())
+                .replace("{{mod-button}}", render_mod_button(&db, user.as_ref().map(|x| &x.user), Capability::ManageTopics, Target::Site, "/do/mod/topic", &[("topic", topic_id.0.as_str())]).as_str())
+                .replace("{{edit-about}}", match &user {
+                    Some(user) if db.can(&user.user, Capability::ModerateTopic, Target::Topic(&topic_id)) =>
+                        read_to_string("assets/element/edit-topic-about.html").unwrap()
+                            .replace("{{about}}", html_escape::encode_text(topic.about.as_str()).as_ref()),
+                    _ => "".to_string(),
+                }.as_str())
+                .replace("{{insert-error-here}}", render_error(query.0.error.as_deref()).as_str())
+                .replace("{{manage}}", if user.as_ref().is_some_and(|x| db.can(&x.user, Capability::ManageTopics, Target::Site)) {
+                    format!("<a href=\"/admin/topics/{}\">Manage</a>", topic_id.0)
+                } else { "".to_string() }.as_str())
+                .replace("{{archived}}", if topic.archived { "<p class=locked>This topic is archived</p>" } else { "" })
+                .replace("{{color}}", topic.color.as_ref().map_or_else(String::new, |x| format!("<span class=topic-color style=\"background: {}\"></span>", x)).as_str())
                 .replace("{{topic-name}}", topic_id.0.as_str())
-                .replace("{{create-thread}}", if user.is_some() {
+                .replace("{{create-thread}}", if user.as_ref().is_some_and(|x| db.can(&x.user, Capability::CreateThread, Target::Topic(&topic_id))) {
                     read_to_string("assets/element/create-thread.html").unwrap()
                         .replace("{{topic-name}}", topic_id.0.as_str())
                 } else { "".to_string() }.as_str())
                 .replace("{{about}}", topic.about.as_str())
-                .replace("{{threads}}", threads.join("").as_str())
+                .replace("{{threads}}", (pending.join("") + threads.join("").as_str()).as_str())
         }),
-        None => render_page(&db, user.as_ref(), || {
+        None => render_page(&db, user.as_ref(), &csrf, || {
             let html = read_to_string("assets/page/topic-404.html").unwrap();
             html.replace("{{topic-name}}", topic_id.0.as_str())
         }),
@@ -39,29 +78,43 @@ pub async fn page_topic(db: Data<Mutex<DB>>, user: Option<UserSession>, topic_na
 }
 
 #[get("/u/{user_name}")]
-pub async fn page_user(db: Data<Mutex<DB>>, current_user: Option<UserSession>, user_name: Path<String>) -> HttpResponse {
+pub async fn page_user(db: Data<Mutex<DB>>, current_user: Option<UserSession>, csrf: CsrfToken, user_name: Path<String>, query: Query<Error>) -> HttpResponse {
     let db = db.lock().unwrap();
     let user_id = UserID(user_name.into_inner());
     let user = db.get_user(&user_id);
+    if let (None, Some(new_id)) = (user, db.get_user_redirect(&user_id)) {
+        return HttpResponse::MovedPermanently()
+            .insert_header((header::LOCATION, format!("/u/{}", new_id.0)))
+            .finish();
+    }
     match user {
-        Some(user) => render_page(&db, current_user.as_ref(), || {
+        Some(user) => render_page(&db, current_user.as_ref(), &csrf, || {
             let reply_html = read_to_string("assets/element/reply/user-reply.html").unwrap();
             let replies: Vec<String> = db.collect_replies_for_user(
                 &user_id,
-                |thread_id, thread, reply|
-                    render_user_reply(reply_html.as_str(), thread_id, thread, reply)
+                |thread_id, thread, reply_id, reply|
+                    if db.is_hidden_from(current_user.as_ref().map(|x| &x.user), &Reported::Reply(thread_id.clone(), reply_id.clone())) {
+                        "".to_string()
+                    } else {
+                        render_user_reply(reply_html.as_str(), thread_id, thread, reply)
+                    }
             );
             let html = read_to_string("assets/page/user.html").unwrap();
             html.replace("{{user-name}}", user_id.0.as_str())
+                .replace("{{display-name}}", render_display_name(Some(user), &user_id).as_str())
                 .replace("{{pronouns}}", user.pronouns.as_ref().map_or_else(|| "".to_string(), |x| x.join("/")).as_str())
                 .replace("{{link-to-settings}}", match &current_user {
                     Some(x) if x.user == user_id => "<a href=/settings>Settings</a>",
                     _ => "",
                 })
+                .replace("{{mod-button}}", render_mod_button(&db, current_user.as_ref().map(|x| &x.user), Capability::ManageUsers, Target::Site, "/do/mod/user", &[("user", user_id.0.as_str())]).as_str())
+                .replace("{{ban}}", render_ban_controls(&db, current_user.as_ref().map(|x| &x.user), &user_id).as_str())
+                .replace("{{notes}}", render_notes(&db, current_user.as_ref().map(|x| &x.user), &NoteSubject::User(user_id.clone()), format!("/u/{}", user_id.0).as_str()).as_str())
+                .replace("{{insert-error-here}}", render_error(query.0.error.as_deref()).as_str())
                 .replace("{{about}}", user.about.as_str())
                 .replace("{{replies}}", replies.join("").as_str())
         }),
-        None => render_page(&db, current_user.as_ref(), || {
+        None => render_page(&db, current_user.as_ref(), &csrf, || {
             let html = read_to_string("assets/page/user-404.html").unwrap();
             html.replace("{{user-name}}", user_id.0.as_str())
         }),
@@ -69,36 +122,67 @@ pub async fn page_user(db: Data<Mutex<DB>>, current_user: Option<UserSession>, u
 }
 
 #[get("/t/{thread_id}")]
-pub async fn page_thread(db: Data<Mutex<DB>>, user: Option<UserSession>, thread_id: Path<String>) -> HttpResponse {
+pub async fn page_thread(db: Data<Mutex<DB>>, user: Option<UserSession>, csrf: CsrfToken, thread_id: Path<String>, query: Query<Error>) -> HttpResponse {
     let db = db.lock().unwrap();
     let thread_id = ThreadID(thread_id.into_inner());
     let thread = db.get_thread(&thread_id);
+    let viewer = user.as_ref().map(|x| &x.user);
     match thread {
-        Some(thread) => render_page(&db, user.as_ref(), || {
+        Some(_) if db.is_hidden_from(viewer, &Reported::Thread(thread_id.clone())) => render_page(&db, user.as_ref(), &csrf, || {
+            read_to_string("assets/page/thread-hidden.html").unwrap()
+        }),
+        Some(thread) => render_page(&db, user.as_ref(), &csrf, || {
+            let page = format!("/t/{}", thread_id.0);
             let reply_html = read_to_string("assets/element/reply/reply.html").unwrap();
+            let blocked_html = read_to_string("assets/element/reply/blocked-reply.html").unwrap();
             let replies: Vec<String> = thread.replies.iter()
-                .filter_map(|x| db.get_reply(x))
-                .map(|x| render_reply(&db, reply_html.as_str(), x)).collect();
+                .filter(|id| !db.is_hidden_from(viewer, &Reported::Reply(thread_id.clone(), (*id).clone())))
+                .filter_map(|id| db.get_reply(id).map(|x| (id, x)))
+                .map(|(id, x)| {
+                    let html = render_reply(&db, reply_html.as_str(), x)
+                        .replace("{{mod-button}}", render_mod_button(&db, viewer, Capability::ModerateTopic, Target::Thread(&thread_id), "/do/mod/reply",
+                            &[("thread", thread_id.0.as_str()), ("reply", id.0.as_str())]).as_str())
+                        .replace("{{report}}", render_report_form(viewer, &thread_id, Some(id)).as_str())
+                        .replace("{{tags}}", render_reply_tags(&db, viewer, &thread_id, x).as_str())
+                        .replace("{{notes}}", render_notes(&db, viewer, &NoteSubject::Post(Reported::Reply(thread_id.clone(), id.clone())), page.as_str()).as_str());
+                    // Still there to open, blocking only keeps it out of the way
+                    if db.has_blocked(viewer, &x.user) { blocked_html.replace("{{reply}}", html.as_str()) } else { html }
+                })
+                .collect();
+            let pending_html = read_to_string("assets/element/reply/pending-reply.html").unwrap();
+            let pending = viewer.map_or_else(Vec::new, |x| db.get_pending_by(x)).into_iter()
+                .filter_map(|x| match &x.thing {
+                    Moderatable::Reply(_, reply, id, _) if id == &thread_id => Some(render_reply(&db, pending_html.as_str(), reply)),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
             let html = read_to_string("assets/page/thread.html").unwrap();
             html
-                .replace("{{insert-favorite-here}}", render_thread_fav(&db, user.as_ref().map(|x| &x.user), &thread_id).as_str())
-                .replace("{{insert-form-here}}", if user.is_some() {
+                .replace("{{insert-favorite-here}}", render_thread_fav(&db, viewer, &thread_id).as_str())
+                .replace("{{report}}", render_report_form(viewer, &thread_id, None).as_str())
+                .replace("{{insert-error-here}}", render_error(query.0.error.as_deref()).as_str())
+                .replace("{{mod-button}}", render_mod_button(&db, user.as_ref().map(|x| &x.user), Capability::ModerateTopic, Target::Thread(&thread_id), "/do/mod/thread", &[("thread", thread_id.0.as_str())]).as_str())
+                .replace("{{thread-tools}}", render_thread_tools(&db, viewer, &thread_id, thread).as_str())
+                .replace("{{notes}}", render_notes(&db, viewer, &NoteSubject::Post(Reported::Thread(thread_id.clone())), page.as_str()).as_str())
+                .replace("{{insert-form-here}}", if thread.locked {
+                    "<p class=locked>This thread is locked</p>".to_string()
+                } else if user.as_ref().is_some_and(|x| db.can(&x.user, Capability::Post, Target::Thread(&thread_id))) {
                     read_to_string("assets/element/reply-form.html").unwrap()
                         .replace("{{thread-id}}", thread_id.0.as_str())
                 } else { "".to_string() }.as_str())
                 .replace("{{title}}", thread.title.as_str())
-                .replace("{{replies}}", replies.join("").as_str())
+                .replace("{{replies}}", (replies.join("") + pending.join("").as_str()).as_str())
         }),
-        None => render_page(&db, user.as_ref(), || {
+        None => render_page(&db, user.as_ref(), &csrf, || {
             read_to_string("assets/page/thread-404.html").unwrap()
         }),
     }
 }
 
 #[get("/λ/{topic}/create-thread")]
-pub async fn page_create_thread(db: Data<Mutex<DB>>, user: UserSession, topic: Path<String>) -> HttpResponse {
+pub async fn page_create_thread(db: Data<Mutex<DB>>, user: UserSession, csrf: CsrfToken, topic: Path<String>) -> HttpResponse {
     let db = db.lock().unwrap();
-    render_page(&db, Some(&user), || {
+    render_page(&db, Some(&user), &csrf, || {
         read_to_string("assets/page/create-thread.html").unwrap()
             .replace("{{topic-name}}", topic.as_str())
     })
@@ -109,33 +193,386 @@ pub struct Error {
     error: Option<String>,
 }
 
+/// Anyone can link to a page with whatever error they like, so it's escaped like any other user input
+fn render_error(error: Option<&str>) -> String {
+    html_escape::encode_text(error.unwrap_or_default()).to_string()
+}
+
 #[get("/settings")]
-pub async fn page_settings(db: Data<Mutex<DB>>, user: UserSession, query: Query<Error>) -> HttpResponse {
+pub async fn page_settings(db: Data<Mutex<DB>>, auth: Data<Mutex<Auth>>, user: UserSession, csrf: CsrfToken, query: Query<Error>) -> HttpResponse {
     let db = db.lock().unwrap();
-    render_page(&db, Some(&user), || {
+    let auth = auth.lock().unwrap();
+    let has_totp = auth.has_totp(&user.user);
+    let sso_settings = match auth.oidc_config() {
+        Some(config) => {
+            let linked = auth.has_oidc_identity(&user.user);
+            read_to_string("assets/element/sso-settings.html").unwrap()
+                .replace("{{action}}", if linked { "/auth/oidc/unlink" } else { "/auth/oidc/link" })
+                .replace("{{label}}", html_escape::encode_text(&config.label).as_ref())
+                .replace("{{status}}", if linked { "linked" } else { "not linked" })
+                .replace("{{button}}", if linked { "Unlink" } else { "Link" })
+        },
+        None => "".to_string(),
+    };
+    render_page(&db, Some(&user), &csrf, || {
+        let rename_status = match db.get_rename_request(&user.user) {
+            Some(x) => format!("Waiting for an overlord to approve @{}", x.0),
+            None => "".to_string(),
+        };
         let user = db.get_user(&user.user).unwrap();
+        let unblock_html = read_to_string("assets/element/unblock-user.html").unwrap();
+        let unmute_html = read_to_string("assets/element/unmute-topic.html").unwrap();
         read_to_string("assets/page/settings.html").unwrap()
-            .replace("{{insert-error-here}}", query.0.error.unwrap_or_else(|| "".to_string()).as_str())
+            .replace("{{blocked}}", user.blocked.iter()
+                .map(|x| unblock_html.replace("{{user-name}}", x.0.as_str()))
+                .collect::<Vec<_>>().join("").as_str())
+            .replace("{{muted-topics}}", user.muted_topics.iter()
+                .map(|x| unmute_html.replace("{{topic-name}}", x.0.as_str()))
+                .collect::<Vec<_>>().join("").as_str())
+            .replace("{{topics}}", db.get_sorted_topic_ids().into_iter()
+                .filter(|x| !user.muted_topics.contains(x))
+                .map(|x| format!("<option value=\"{0}\">λ/{0}</option>", x.0))
+                .collect::<Vec<_>>().join("").as_str())
+            .replace("{{insert-error-here}}", render_error(query.error.as_deref()).as_str())
+            .replace("{{display-name}}", html_escape::encode_double_quoted_attribute(user.display_name.as_deref().unwrap_or_default()).as_ref())
+            .replace("{{rename-status}}", rename_status.as_str())
             .replace("{{pronouns}}", user.pronouns.as_ref().map_or_else(|| "".to_string(), |x| x.join("/")).as_str())
             .replace("{{about}}", html_escape::encode_text(user.about.as_str()).as_ref())
+            .replace("{{totp-status}}", if has_totp { "enabled" } else { "disabled" })
+            .replace("{{sso-settings}}", sso_settings.as_str())
+    })
+}
+
+#[get("/settings/2fa")]
+pub async fn page_settings_totp(db: Data<Mutex<DB>>, auth: Data<Mutex<Auth>>, user: UserSession, csrf: CsrfToken, query: Query<Error>) -> HttpResponse {
+    let db = db.lock().unwrap();
+    let auth = auth.lock().unwrap();
+    let error = render_error(query.error.as_deref());
+    if auth.has_totp(&user.user) {
+        let recovery_code_count = auth.remaining_recovery_codes(&user.user);
+        render_page(&db, Some(&user), &csrf, || {
+            read_to_string("assets/page/settings-2fa-enabled.html").unwrap()
+                .replace("{{insert-error-here}}", error.as_str())
+                .replace("{{recovery-code-count}}", recovery_code_count.to_string().as_str())
+        })
+    } else {
+        let secret = totp::gen_secret();
+        let uri = totp::otpauth_uri(&secret, &user.user.0);
+        render_page(&db, Some(&user), &csrf, || {
+            read_to_string("assets/page/settings-2fa.html").unwrap()
+                .replace("{{insert-error-here}}", error.as_str())
+                .replace("{{qr}}", totp::render_qr_svg(&uri).as_str())
+                .replace("{{otpauth-uri}}", html_escape::encode_double_quoted_attribute(&uri).as_ref())
+                .replace("{{secret}}", secret.as_str())
+        })
+    }
+}
+
+#[get("/settings/tokens")]
+pub async fn page_settings_tokens(db: Data<Mutex<DB>>, auth: Data<Mutex<Auth>>, user: UserSession, csrf: CsrfToken, query: Query<Error>) -> HttpResponse {
+    let db = db.lock().unwrap();
+    let auth = auth.lock().unwrap();
+    let token_html = read_to_string("assets/element/api-token.html").unwrap();
+    let tokens = auth.api_tokens_for_user(&user.user).into_iter().map(|(id, token)| {
+        token_html
+            .replace("{{token-id}}", id.0.as_str())
+            .replace("{{name}}", html_escape::encode_text(&token.name).as_ref())
+            .replace("{{scopes}}", token.scopes.iter().map(|x| x.as_str()).collect::<Vec<_>>().join(", ").as_str())
+            .replace("{{created-time}}", format_date_time(&token.created).as_str())
+    }).collect::<Vec<_>>();
+    render_page(&db, Some(&user), &csrf, || {
+        read_to_string("assets/page/settings-tokens.html").unwrap()
+            .replace("{{insert-error-here}}", render_error(query.error.as_deref()).as_str())
+            .replace("{{tokens}}", tokens.join("").as_str())
+    })
+}
+
+#[get("/settings/invites")]
+pub async fn page_settings_invites(db: Data<Mutex<DB>>, user: UserSession, csrf: CsrfToken, query: Query<Error>) -> HttpResponse {
+    let db = db.lock().unwrap();
+    let invite_html = read_to_string("assets/element/invite.html").unwrap();
+    let invites = db.get_invites_for_user(&user.user).into_iter().map(|(id, invite)| {
+        invite_html
+            .replace("{{invite-id}}", id.0.as_str())
+            .replace("{{created-time}}", format_date_time(&invite.created).as_str())
+            .replace("{{status}}", match &invite.used_by {
+                Some(used_by) => format!("used by <a href=\"/u/{0}\">{0}</a>", used_by.0),
+                None => format!("unused, share <a href=\"/signup?invite={0}\">/signup?invite={0}</a>", id.0),
+            }.as_str())
+    }).collect::<Vec<_>>();
+    let invites_left = if db.is_admin(&user.user) { "unlimited".to_string() } else { db.invites_left(&user.user).to_string() };
+    render_page(&db, Some(&user), &csrf, || {
+        read_to_string("assets/page/settings-invites.html").unwrap()
+            .replace("{{insert-error-here}}", render_error(query.error.as_deref()).as_str())
+            .replace("{{invites-left}}", invites_left.as_str())
+            .replace("{{invites}}", invites.join("").as_str())
+    })
+}
+
+#[get("/admin/invites")]
+pub async fn page_admin_invites(db: Data<Mutex<DB>>, user: UserSession, csrf: CsrfToken) -> HttpResponse {
+    let db = db.lock().unwrap();
+    if !db.can(&user.user, Capability::ManageUsers, Target::Site) {
+        return render_not_found(&db, Some(&user), &csrf);
+    }
+    let tree = db.get_invite_tree();
+    let roots = db.get_sorted_user_ids().into_iter()
+        .filter(|x| db.get_inviter(x).is_none())
+        .collect::<Vec<_>>();
+    render_page(&db, Some(&user), &csrf, || {
+        read_to_string("assets/page/admin-invites.html").unwrap()
+            .replace("{{tree}}", render_invite_tree(&tree, &roots).as_str())
+    })
+}
+
+#[get("/admin/settings")]
+pub async fn page_admin_settings(db: Data<Mutex<DB>>, user: UserSession, csrf: CsrfToken, query: Query<Error>) -> HttpResponse {
+    let db = db.lock().unwrap();
+    if !db.is_admin(&user.user) {
+        return render_not_found(&db, Some(&user), &csrf);
+    }
+    let settings = db.get_settings();
+    render_page(&db, Some(&user), &csrf, || {
+        read_to_string("assets/page/admin-settings.html").unwrap()
+            .replace("{{insert-error-here}}", render_error(query.error.as_deref()).as_str())
+            .replace("{{require-mod-2fa}}", if settings.require_mod_2fa { "checked" } else { "" })
+            .replace("{{registration-modes}}", RegistrationMode::ALL.iter().map(|mode| format!(
+                "<option value={0}{1}>{0}</option>",
+                mode.as_str(),
+                if *mode == settings.registration_mode { " selected" } else { "" },
+            )).collect::<Vec<_>>().join("").as_str())
+            .replace("{{invite-quota}}", settings.invite_quota.to_string().as_str())
+            .replace("{{report-threshold}}", settings.report_threshold.to_string().as_str())
+            .replace("{{approval-posts}}", settings.approval_posts.to_string().as_str())
+    })
+}
+
+#[get("/admin/topics")]
+pub async fn page_admin_topics(db: Data<Mutex<DB>>, user: UserSession, csrf: CsrfToken, query: Query<Error>) -> HttpResponse {
+    let db = db.lock().unwrap();
+    if !db.can(&user.user, Capability::ManageTopics, Target::Site) {
+        return render_not_found(&db, Some(&user), &csrf);
+    }
+    let topic_html = read_to_string("assets/element/admin-topic.html").unwrap();
+    let topics = db.get_sorted_topic_ids().into_iter().filter_map(|id| {
+        let topic = db.get_topic(id)?;
+        Some(topic_html
+            .replace("{{flags}}", if topic.archived { "<span class=thread-flag>Archived</span>" } else { "" })
+            .replace("{{thread-count}}", topic.threads.len().to_string().as_str())
+            .replace("{{owner}}", db.get_topic_owner(id).map_or_else(|| "nobody".to_string(), |x| format!("<a href=\"/u/{}\">{}</a>", x.0, x.0)).as_str())
+            .replace("{{topic-name}}", id.0.as_str()))
+    }).collect::<Vec<_>>();
+    render_page(&db, Some(&user), &csrf, || {
+        read_to_string("assets/page/admin-topics.html").unwrap()
+            .replace("{{insert-error-here}}", render_error(query.0.error.as_deref()).as_str())
+            .replace("{{max-name-length}}", MAX_TOPIC_NAME_LENGTH.to_string().as_str())
+            .replace("{{topics}}", topics.join("").as_str())
+    })
+}
+
+#[get("/admin/topics/{topic}")]
+pub async fn page_admin_topic(db: Data<Mutex<DB>>, user: UserSession, csrf: CsrfToken, topic: Path<String>, query: Query<Error>) -> HttpResponse {
+    let db = db.lock().unwrap();
+    let topic_id = TopicID(topic.into_inner());
+    let (true, Some(topic)) = (db.can(&user.user, Capability::ManageTopics, Target::Site), db.get_topic(&topic_id)) else {
+        return render_not_found(&db, Some(&user), &csrf);
+    };
+    render_page(&db, Some(&user), &csrf, || {
+        read_to_string("assets/page/admin-topic.html").unwrap()
+            .replace("{{insert-error-here}}", render_error(query.0.error.as_deref()).as_str())
+            .replace("{{about}}", html_escape::encode_text(topic.about.as_str()).as_ref())
+            .replace("{{color}}", topic.color.as_deref().unwrap_or_default())
+            .replace("{{max-name-length}}", MAX_TOPIC_NAME_LENGTH.to_string().as_str())
+            .replace("{{will-be-archived}}", (!topic.archived).to_string().as_str())
+            .replace("{{archive-text}}", if topic.archived { "Unarchive" } else { "Archive" })
+            .replace("{{thread-count}}", topic.threads.len().to_string().as_str())
+            .replace("{{topic-name}}", topic_id.0.as_str())
+    })
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UserQuery {
+    q: Option<String>,
+}
+
+#[get("/admin/users")]
+pub async fn page_admin_users(db: Data<Mutex<DB>>, user: UserSession, csrf: CsrfToken, query: Query<UserQuery>) -> HttpResponse {
+    let db = db.lock().unwrap();
+    if !db.can(&user.user, Capability::ManageUsers, Target::Site) {
+        return render_not_found(&db, Some(&user), &csrf);
+    }
+    let search = query.0.q.unwrap_or_default();
+    let user_html = read_to_string("assets/element/admin-user.html").unwrap();
+    let users = db.search_users(&search).into_iter().filter_map(|id| {
+        let account = db.get_user(id)?;
+        Some(user_html
+            .replace("{{display-name}}", account.display_name.as_deref().map_or_else(String::new, |x| html_escape::encode_text(x).to_string()).as_str())
+            .replace("{{joined}}", account.joined.map_or_else(|| "unknown".to_string(), |x| x.format("%Y-%m-%d").to_string()).as_str())
+            .replace("{{reply-count}}", db.count_replies_by(id).to_string().as_str())
+            .replace("{{roles}}", db.get_grants(id).iter().map(describe_grant).collect::<Vec<_>>().join(", ").as_str())
+            .replace("{{status}}", db.get_ban(id).map_or_else(String::new, |x| html_escape::encode_text(&x.describe()).to_string()).as_str())
+            .replace("{{user-name}}", id.0.as_str()))
+    }).collect::<Vec<_>>();
+    render_page(&db, Some(&user), &csrf, || {
+        read_to_string("assets/page/admin-users.html").unwrap()
+            .replace("{{query}}", html_escape::encode_double_quoted_attribute(&search).as_ref())
+            .replace("{{users}}", users.join("").as_str())
+    })
+}
+
+fn describe_grant(grant: &RoleGrant) -> String {
+    match &grant.topic {
+        Some(topic) => format!("{} of <a href=\"/λ/{}\">λ/{}</a>", grant.role.0, topic.0, topic.0),
+        None => grant.role.0.clone(),
+    }
+}
+
+/// Where someone's reported or reporting post is, for the account overview
+fn describe_reported(db: &DB, reported: &Reported) -> String {
+    let thread = reported.thread();
+    format!("{} in <a href=\"/t/{}\">{}</a>", reported.kind(), thread.0, db.get_thread(thread).map_or("[deleted thread]", |x| x.title.as_str()))
+}
+
+#[get("/admin/users/{user}")]
+pub async fn page_admin_user(db: Data<Mutex<DB>>, auth: Data<Mutex<Auth>>, user: UserSession, csrf: CsrfToken, user_name: Path<String>, query: Query<Error>) -> HttpResponse {
+    let db = db.lock().unwrap();
+    let user_id = UserID(user_name.into_inner());
+    let (true, Some(account)) = (db.can(&user.user, Capability::ManageUsers, Target::Site), db.get_user(&user_id)) else {
+        return render_not_found(&db, Some(&user), &csrf);
+    };
+    let auth = auth.lock().unwrap();
+    let sessions = auth.get_sessions(&user_id);
+    let is_admin = db.is_admin(&user.user);
+    let revoke_html = read_to_string("assets/element/revoke-role.html").unwrap();
+    let grant_html = read_to_string("assets/element/role-grant.html").unwrap();
+    let grants = db.get_grants(&user_id).iter().map(|x| grant_html
+        .replace("{{grant}}", describe_grant(x).as_str())
+        .replace("{{revoke}}", if is_admin {
+            revoke_html
+                .replace("{{role}}", x.role.0.as_str())
+                .replace("{{topic}}", x.topic.as_ref().map_or("", |x| x.0.as_str()))
+        } else { "".to_string() }.as_str())
+    ).collect::<Vec<_>>();
+    let grant_form = if is_admin {
+        read_to_string("assets/element/grant-role.html").unwrap()
+            .replace("{{roles}}", db.get_role_ids().into_iter().map(|x| format!("<option value=\"{0}\">{0}</option>", x)).collect::<Vec<_>>().join("").as_str())
+            .replace("{{topics}}", db.get_sorted_topic_ids().into_iter().map(|x| format!("<option value=\"{0}\">λ/{0}</option>", x.0)).collect::<Vec<_>>().join("").as_str())
+    } else { "".to_string() };
+    let reports_against = db.get_reports_against(&user_id).into_iter()
+        .map(|(reported, reports)| format!("<li>{}: {} reports</li>", describe_reported(&db, reported), reports.len()))
+        .collect::<Vec<_>>();
+    let reports_by = db.get_reports_by(&user_id).into_iter()
+        .map(|(reported, report)| format!("<li><time>{}</time> {} on {}</li>", format_date_time(&report.created), report.reason.label(), describe_reported(&db, reported)))
+        .collect::<Vec<_>>();
+    render_page(&db, Some(&user), &csrf, || {
+        read_to_string("assets/page/admin-user.html").unwrap()
+            .replace("{{insert-error-here}}", render_error(query.0.error.as_deref()).as_str())
+            .replace("{{display-name}}", render_display_name(Some(account), &user_id).as_str())
+            .replace("{{joined}}", account.joined.map_or_else(|| "unknown".to_string(), |x| x.format("%Y-%m-%d %H:%M").to_string()).as_str())
+            .replace("{{reply-count}}", db.count_replies_by(&user_id).to_string().as_str())
+            .replace("{{sessions}}", match sessions.first() {
+                Some(last) => format!("{} active, last used {}", sessions.len(), last.format("%Y-%m-%d %H:%M")),
+                None => "none".to_string(),
+            }.as_str())
+            .replace("{{totp}}", if auth.has_totp(&user_id) { "enabled" } else { "not enabled" })
+            .replace("{{grants}}", if grants.is_empty() { "<li>Only the default role</li>".to_string() } else { grants.join("") }.as_str())
+            .replace("{{grant-form}}", grant_form.as_str())
+            .replace("{{reports-against}}", if reports_against.is_empty() { "<li>None</li>".to_string() } else { reports_against.join("") }.as_str())
+            .replace("{{reports-by}}", if reports_by.is_empty() { "<li>None</li>".to_string() } else { reports_by.join("") }.as_str())
+            .replace("{{ban}}", render_ban_controls(&db, Some(&user.user), &user_id).as_str())
+            .replace("{{notes}}", render_notes(&db, Some(&user.user), &NoteSubject::User(user_id.clone()), format!("/admin/users/{}", user_id.0).as_str()).as_str())
+            .replace("{{account-actions}}", if db.can_manage_account(&user.user, &user_id) && user.user != user_id {
+                read_to_string("assets/element/account-actions.html").unwrap()
+            } else { "".to_string() }.as_str())
+            .replace("{{user-name}}", user_id.0.as_str())
+    })
+}
+
+#[get("/admin/lockouts")]
+pub async fn page_admin_lockouts(db: Data<Mutex<DB>>, auth: Data<Mutex<Auth>>, user: UserSession, csrf: CsrfToken) -> HttpResponse {
+    let db = db.lock().unwrap();
+    if !db.can(&user.user, Capability::ManageUsers, Target::Site) {
+        return render_not_found(&db, Some(&user), &csrf);
+    }
+    let auth = auth.lock().unwrap();
+    let lockout_html = read_to_string("assets/element/lockout.html").unwrap();
+    let lockouts = auth.lockouts().into_iter().map(|(kind, key, until, attempts)| {
+        lockout_html
+            .replace("{{kind}}", kind)
+            .replace("{{key}}", html_escape::encode_text(key).as_ref())
+            .replace("{{key-attr}}", html_escape::encode_double_quoted_attribute(key).as_ref())
+            .replace("{{locked-until}}", until.format("%b %d %H:%M:%S").to_string().as_str())
+            .replace("{{attempts}}", attempts.to_string().as_str())
+    }).collect::<Vec<_>>();
+    render_page(&db, Some(&user), &csrf, || {
+        read_to_string("assets/page/admin-lockouts.html").unwrap()
+            .replace("{{lockouts}}", if lockouts.is_empty() { "<p>Nobody is locked out right now</p>".to_string() } else { lockouts.join("") }.as_str())
+    })
+}
+
+#[get("/admin/renames")]
+pub async fn page_admin_renames(db: Data<Mutex<DB>>, user: UserSession, csrf: CsrfToken, query: Query<Error>) -> HttpResponse {
+    let db = db.lock().unwrap();
+    if !db.can(&user.user, Capability::ManageUsers, Target::Site) {
+        return render_not_found(&db, Some(&user), &csrf);
+    }
+    let request_html = read_to_string("assets/element/rename-request.html").unwrap();
+    let requests = db.get_rename_requests().into_iter().map(|(old, new)| {
+        request_html
+            .replace("{{old-name}}", old.0.as_str())
+            .replace("{{new-name}}", new.0.as_str())
+    }).collect::<Vec<_>>();
+    render_page(&db, Some(&user), &csrf, || {
+        read_to_string("assets/page/admin-renames.html").unwrap()
+            .replace("{{insert-error-here}}", render_error(query.error.as_deref()).as_str())
+            .replace("{{requests}}", if requests.is_empty() { "<p>No pending rename requests</p>".to_string() } else { requests.join("") }.as_str())
     })
 }
 
 #[get("/login")]
-pub async fn page_login(db: Data<Mutex<DB>>, user: Option<UserSession>, query: Query<Error>) -> HttpResponse {
+pub async fn page_login(db: Data<Mutex<DB>>, auth: Data<Mutex<Auth>>, user: Option<UserSession>, csrf: CsrfToken, query: Query<Error>) -> HttpResponse {
     let db = db.lock().unwrap();
-    render_page(&db, user.as_ref(), || {
+    let sso_login = match auth.lock().unwrap().oidc_config() {
+        Some(config) => read_to_string("assets/element/sso-login.html").unwrap()
+            .replace("{{label}}", html_escape::encode_text(&config.label).as_ref()),
+        None => "".to_string(),
+    };
+    render_page(&db, user.as_ref(), &csrf, || {
         read_to_string("assets/page/login.html").unwrap()
-            .replace("{{insert-error-here}}", query.0.error.unwrap_or_else(|| "".to_string()).as_str())
+            .replace("{{insert-error-here}}", render_error(query.0.error.as_deref()).as_str())
+            .replace("{{sso-login}}", sso_login.as_str())
+    })
+}
+
+#[get("/login/2fa")]
+pub async fn page_login_totp(db: Data<Mutex<DB>>, user: Option<UserSession>, csrf: CsrfToken, query: Query<Error>) -> HttpResponse {
+    let db = db.lock().unwrap();
+    render_page(&db, user.as_ref(), &csrf, || {
+        read_to_string("assets/page/login-2fa.html").unwrap()
+            .replace("{{insert-error-here}}", render_error(query.error.as_deref()).as_str())
     })
 }
 
+#[derive(Debug, Deserialize)]
+pub struct SignupQuery {
+    error: Option<String>,
+    invite: Option<String>,
+}
+
 #[get("/signup")]
-pub async fn page_signup(db: Data<Mutex<DB>>, user: Option<UserSession>, query: Query<Error>) -> HttpResponse {
+pub async fn page_signup(db: Data<Mutex<DB>>, user: Option<UserSession>, csrf: CsrfToken, query: Query<SignupQuery>) -> HttpResponse {
     let db = db.lock().unwrap();
-    render_page(&db, user.as_ref(), || {
+    let mode = db.get_settings().registration_mode;
+    render_page(&db, user.as_ref(), &csrf, || {
         read_to_string("assets/page/signup.html").unwrap()
-            .replace("{{insert-error-here}}", query.0.error.unwrap_or_else(|| "".to_string()).as_str())
+            .replace("{{insert-error-here}}", render_error(query.error.as_deref()).as_str())
+            .replace("{{invite}}", html_escape::encode_double_quoted_attribute(query.0.invite.as_deref().unwrap_or_default()).as_ref())
+            .replace("{{invite-required}}", if mode == RegistrationMode::InviteOnly { "required" } else { "" })
+            .replace("{{registration-note}}", match mode {
+                RegistrationMode::Open => "",
+                RegistrationMode::InviteOnly => "<p>Joining is invite-only right now, ask someone who's already here for a code</p>",
+                RegistrationMode::Closed => "<p>Registration is closed right now</p>",
+            })
     })
 }
 
@@ -145,35 +582,206 @@ pub struct Search {
 }
 
 #[get("/search")]
-pub async fn page_search(db: Data<Mutex<DB>>, user: Option<UserSession>, query: Query<Search>) -> HttpResponse {
+pub async fn page_search(db: Data<Mutex<DB>>, user: Option<UserSession>, csrf: CsrfToken, query: Query<Search>) -> HttpResponse {
     let thread_html = read_to_string("assets/element/thread.html").unwrap();
     let db = db.lock().unwrap();
     let topics = db.search_topics(query.0.q.as_str());
     let threads = db.search_threads(query.0.q.as_str());
-    render_page(&db, user.as_ref(), || {
+    render_page(&db, user.as_ref(), &csrf, || {
         read_to_string("assets/page/search.html").unwrap()
             .replace("{{query}}", query.0.q.as_str())
             .replace("{{topics}}", topics.into_iter().map(|x| format!("<li><a href=\"/λ/{}\">{}</a></li>", x.0, x.0)).collect::<Vec<_>>().join("").as_str())
-            .replace("{{threads}}", threads.into_iter().map(|x| render_thread(&db, thread_html.as_str(), &x)).collect::<Vec<_>>().join("").as_str())
+            .replace("{{threads}}", threads.into_iter()
+                .filter(|x| !db.is_hidden_from(user.as_ref().map(|x| &x.user), &Reported::Thread((*x).clone())))
+                .filter(|x| !db.is_thread_blocked(user.as_ref().map(|x| &x.user), x))
+                .filter(|x| db.get_thread_topic(x).is_none_or(|x| !db.has_muted(user.as_ref().map(|x| &x.user), x)))
+                .filter_map(|x| render_thread(&db, user.as_ref().map(|x| &x.user), thread_html.as_str(), x)).collect::<Vec<_>>().join("").as_str())
+    })
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LogQuery {
+    moderator: Option<String>,
+    user: Option<String>,
+    topic: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+}
+
+/// Shared by the full log and the public one, which leaves out anything private
+fn render_mod_log(db: &DB, query: &LogQuery, full: bool) -> String {
+    let given = |x: &Option<String>| x.as_deref().map(str::trim).filter(|x| !x.is_empty()).map(str::to_string);
+    let date = |x: &Option<String>| given(x).and_then(|x| NaiveDate::parse_from_str(&x, "%Y-%m-%d").ok());
+    let filter = RecordFilter {
+        actor: given(&query.moderator).map(UserID),
+        user: given(&query.user).map(UserID),
+        topic: given(&query.topic).map(TopicID),
+        from: date(&query.from),
+        to: date(&query.to),
+    };
+    let record_html = read_to_string("assets/element/mod-record.html").unwrap();
+    let records = db.get_records(&filter).into_iter()
+        .filter(|x| full || x.action.is_public())
+        .map(|x| render_mod_record(record_html.as_str(), x, full))
+        .collect::<Vec<_>>();
+    let value = |x: &Option<String>| html_escape::encode_double_quoted_attribute(x.as_deref().unwrap_or_default()).to_string();
+    read_to_string("assets/page/mod-log.html").unwrap()
+        .replace("{{title}}", if full { "Moderation Log" } else { "Transparency Log" })
+        .replace("{{other-view}}", if full { "<a href=/transparency>Public view</a>" } else { "" })
+        .replace("{{action}}", if full { "/mod/log" } else { "/transparency" })
+        .replace("{{moderator}}", value(&query.moderator).as_str())
+        .replace("{{user}}", value(&query.user).as_str())
+        .replace("{{topic}}", value(&query.topic).as_str())
+        .replace("{{from}}", value(&query.from).as_str())
+        .replace("{{to}}", value(&query.to).as_str())
+        .replace("{{records}}", if records.is_empty() { "<p>Nothing was recorded</p>".to_string() } else { records.join("") }.as_str())
+}
+
+#[get("/mod/log")]
+pub async fn page_mod_log(db: Data<Mutex<DB>>, user: UserSession, csrf: CsrfToken, query: Query<LogQuery>) -> HttpResponse {
+    let db = db.lock().unwrap();
+    if !db.is_admin(&user.user) {
+        return render_not_found(&db, Some(&user), &csrf);
+    }
+    render_page(&db, Some(&user), &csrf, || render_mod_log(&db, &query.0, true))
+}
+
+#[get("/transparency")]
+pub async fn page_transparency(db: Data<Mutex<DB>>, user: Option<UserSession>, csrf: CsrfToken, query: Query<LogQuery>) -> HttpResponse {
+    let db = db.lock().unwrap();
+    render_page(&db, user.as_ref(), &csrf, || render_mod_log(&db, &query.0, false))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AutomodQuery {
+    rule: Option<String>,
+}
+
+/// Most hits that get shown at once
+const MAX_AUTOMOD_HITS: usize = 100;
+
+#[get("/mod/automod")]
+pub async fn page_automod(db: Data<Mutex<DB>>, user: UserSession, csrf: CsrfToken, query: Query<AutomodQuery>) -> HttpResponse {
+    let db = db.lock().unwrap();
+    if !db.is_admin(&user.user) {
+        return render_not_found(&db, Some(&user), &csrf);
+    }
+    let rule = query.0.rule.filter(|x| !x.is_empty());
+    let rule_html = read_to_string("assets/element/automod-rule.html").unwrap();
+    let rules = db.get_automod_rules().iter()
+        .map(|x| render_automod_rule(rule_html.as_str(), x, db.get_automod_hits(Some(&x.name)).len()))
+        .collect::<Vec<_>>();
+    let hit_html = read_to_string("assets/element/automod-hit.html").unwrap();
+    let hits = db.get_automod_hits(rule.as_deref()).into_iter()
+        .take(MAX_AUTOMOD_HITS)
+        .map(|x| render_automod_hit(hit_html.as_str(), x))
+        .collect::<Vec<_>>();
+    render_page(&db, Some(&user), &csrf, || {
+        read_to_string("assets/page/automod.html").unwrap()
+            .replace("{{rules}}", rules.join("").as_str())
+            .replace("{{hits-title}}", match &rule {
+                Some(rule) => format!("Recent hits of {}", html_escape::encode_text(rule)),
+                None => "Recent hits".to_string(),
+            }.as_str())
+            .replace("{{hits}}", if hits.is_empty() { "<p>No rule was hit</p>".to_string() } else { hits.join("") }.as_str())
+    })
+}
+
+#[get("/reports")]
+pub async fn page_reports(db: Data<Mutex<DB>>, user: UserSession, csrf: CsrfToken) -> HttpResponse {
+    let db = db.lock().unwrap();
+    if !db.is_moderator(&user.user) {
+        return render_not_found(&db, Some(&user), &csrf);
+    }
+    let group_html = read_to_string("assets/element/report-group.html").unwrap();
+    let groups = db.get_sorted_reports().into_iter()
+        .filter(|(reported, _)| db.can(&user.user, Capability::ModerateTopic, Target::Thread(reported.thread())))
+        .map(|(reported, reports)| render_report_group(&db, &user.user, group_html.as_str(), reported, reports))
+        .collect::<Vec<_>>();
+    render_page(&db, Some(&user), &csrf, || {
+        read_to_string("assets/page/reports.html").unwrap()
+            .replace("{{groups}}", if groups.is_empty() { "<p>Nothing was reported</p>".to_string() } else { groups.join("") }.as_str())
+    })
+}
+
+#[get("/approvals")]
+pub async fn page_approvals(db: Data<Mutex<DB>>, user: UserSession, csrf: CsrfToken, query: Query<Error>) -> HttpResponse {
+    let db = db.lock().unwrap();
+    if !db.is_approver(&user.user) {
+        return render_not_found(&db, Some(&user), &csrf);
+    }
+    let item_html = read_to_string("assets/element/pending-item.html").unwrap();
+    let items = db.get_sorted_pending().into_iter()
+        .filter(|(id, _)| db.can_approve(&user.user, id))
+        .map(|(id, item)| render_pending_item(&db, item_html.as_str(), id, item))
+        .collect::<Vec<_>>();
+    render_page(&db, Some(&user), &csrf, || {
+        read_to_string("assets/page/approvals.html").unwrap()
+            .replace("{{insert-error-here}}", render_error(query.0.error.as_deref()).as_str())
+            .replace("{{items}}", if items.is_empty() { "<p>Nothing is waiting for approval</p>".to_string() } else { items.join("") }.as_str())
+    })
+}
+
+#[get("/appeals")]
+pub async fn page_appeals(db: Data<Mutex<DB>>, user: UserSession, csrf: CsrfToken, query: Query<Error>) -> HttpResponse {
+    let db = db.lock().unwrap();
+    if !db.is_moderator(&user.user) {
+        return render_not_found(&db, Some(&user), &csrf);
+    }
+    let item_html = read_to_string("assets/element/appeal-item.html").unwrap();
+    let items = db.get_sorted_appeals().into_iter()
+        .filter(|(_, item)| {
+            let (capability, target) = inspection_requirement(item);
+            db.can(&user.user, capability, target)
+        })
+        .map(|(id, item)| render_appeal_item(&db, &user.user, item_html.as_str(), id, item))
+        .collect::<Vec<_>>();
+    render_page(&db, Some(&user), &csrf, || {
+        read_to_string("assets/page/appeals.html").unwrap()
+            .replace("{{insert-error-here}}", render_error(query.0.error.as_deref()).as_str())
+            .replace("{{items}}", if items.is_empty() { "<p>No appeals are waiting</p>".to_string() } else { items.join("") }.as_str())
+    })
+}
+
+/// Seeing them here is what counts as having read them
+#[get("/notices")]
+pub async fn page_notices(db: Data<Mutex<DB>>, user: UserSession, csrf: CsrfToken, query: Query<Error>) -> HttpResponse {
+    let mut db = db.lock().unwrap();
+    let notices = db.get_notices(&user.user).into_iter()
+        .map(|x| format!("<li{}><time>{}</time> {}</li>",
+            if x.seen { "" } else { " class=unseen" },
+            format_date_time(&x.created), html_escape::encode_text(&x.text)))
+        .collect::<Vec<_>>();
+    let item_html = read_to_string("assets/element/own-item.html").unwrap();
+    let items = db.get_inspection_by(&user.user).into_iter()
+        .map(|(id, item)| render_own_item(&db, item_html.as_str(), id, item))
+        .collect::<Vec<_>>();
+    db.mark_notices_seen(&user.user);
+    render_page(&db, Some(&user), &csrf, || {
+        read_to_string("assets/page/notices.html").unwrap()
+            .replace("{{insert-error-here}}", render_error(query.0.error.as_deref()).as_str())
+            .replace("{{notices}}", if notices.is_empty() { "<li>Nothing happened to your content</li>".to_string() } else { notices.join("") }.as_str())
+            .replace("{{items}}", if items.is_empty() { "<p>None of your content is in inspection</p>".to_string() } else { items.join("") }.as_str())
     })
 }
 
 #[get("/inspection")]
-pub async fn page_inspection(db: Data<Mutex<DB>>, user: UserSession) -> HttpResponse {
-    let reply_html = read_to_string("assets/element/reply/inspection-reply.html").unwrap();
+pub async fn page_inspection(db: Data<Mutex<DB>>, user: UserSession, csrf: CsrfToken, query: Query<Error>) -> HttpResponse {
     let db = db.lock().unwrap();
-    let inspection = db.get_inspection();
-    render_page(&db, Some(&user), || {
+    if !db.is_moderator(&user.user) {
+        return render_not_found(&db, Some(&user), &csrf);
+    }
+    let item_html = read_to_string("assets/element/inspection-item.html").unwrap();
+    let items = db.get_sorted_inspection().into_iter()
+        .filter(|(_, item)| {
+            let (capability, target) = inspection_requirement(item);
+            db.can(&user.user, capability, target)
+        })
+        .map(|(id, item)| render_inspection_item(&db, &user.user, item_html.as_str(), id, item))
+        .collect::<Vec<_>>();
+    render_page(&db, Some(&user), &csrf, || {
         read_to_string("assets/page/inspection.html").unwrap()
-            .replace("{{items}}", inspection.iter().map(|(_, item)| {
-                match &item.thing {
-                    Moderatable::User(user) => "user",
-                    Moderatable::Topic(owner, topic) => "topic",
-                    Moderatable::Thread(user, thread, topic_id) => "thread",
-                    Moderatable::Reply(user, reply, thread_id) => "reply",
-                }
-            }).collect::<Vec<_>>().join("").as_str())
+            .replace("{{insert-error-here}}", render_error(query.error.as_deref()).as_str())
+            .replace("{{items}}", if items.is_empty() { "<p>Nothing to inspect</p>".to_string() } else { items.join("") }.as_str())
     })
-
-    // render_inspection_reply(&db, reply_html.as_str(), thread_id, thread, reply).as_str()
 }
\ No newline at end of file
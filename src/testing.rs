@@ -0,0 +1,19 @@
+use std::{env, fs::{create_dir_all, remove_dir_all}, os::unix::fs::symlink, sync::{Mutex, MutexGuard, Once, PoisonError}};
+
+static STORE: Mutex<()> = Mutex::new(());
+static ENTER: Once = Once::new();
+
+/// The store lives at a fixed path relative to the working directory, so every test that touches it
+/// runs in the same scratch directory and they take turns. Hold on to the guard for the whole test
+pub fn scratch_store() -> MutexGuard<'static, ()> {
+    let guard = STORE.lock().unwrap_or_else(PoisonError::into_inner);
+    ENTER.call_once(|| {
+        let assets = env::current_dir().unwrap().join("assets");
+        let dir = env::temp_dir().join(format!("lamda-network-test-{}", std::process::id()));
+        create_dir_all(&dir).unwrap();
+        let _ = symlink(assets, dir.join("assets"));
+        env::set_current_dir(dir).unwrap();
+    });
+    let _ = remove_dir_all("store");
+    guard
+}
\ No newline at end of file